@@ -0,0 +1,116 @@
+//! Search over a large history.
+//!
+//! Run with `cargo bench --bench history_bench`. Prints the average time of each query.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant, SystemTime},
+};
+use super_v::{
+    common::ClipboardItem,
+    history::{ClipboardHistory, HistoryQuery, KindFilter},
+};
+
+const ENTRIES: usize = 50_000;
+const RUNS: u32 = 20;
+
+const WORDS: &[&str] = &[
+    "clipboard",
+    "history",
+    "search",
+    "index",
+    "rust",
+    "daemon",
+    "window",
+    "paste",
+    "copy",
+    "select",
+    "image",
+    "file",
+    "manager",
+    "wayland",
+    "socket",
+    "thread",
+    "query",
+    "phrase",
+    "prefix",
+    "rank",
+    "summary",
+    "thumbnail",
+    "entry",
+    "limit",
+    "token",
+    "super",
+    "emoji",
+    "config",
+    "server",
+    "client",
+];
+
+// Deterministic pseudo random numbers, so every run searches the same history
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, below: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % below
+    }
+}
+
+fn build_history() -> ClipboardHistory {
+    let mut rng = Lcg(42);
+    let mut history = ClipboardHistory::new(ENTRIES);
+
+    for n in 0..ENTRIES {
+        let len = 5 + rng.next(40);
+        let mut words: Vec<&str> = (0..len).map(|_| WORDS[rng.next(WORDS.len())]).collect();
+
+        // A few rare words to look for
+        if n % 1000 == 0 {
+            words.push("needle");
+        }
+        history.add(ClipboardItem::Text(format!("{} #{n}", words.join(" "))));
+    }
+    history
+}
+
+fn bench(name: &str, history: &ClipboardHistory, query: HistoryQuery) {
+    let start = Instant::now();
+    let mut found = 0;
+    for _ in 0..RUNS {
+        found = black_box(history.query(black_box(&query))).total;
+    }
+    let average = start.elapsed() / RUNS;
+    println!("{name:<28} {average:>12.2?}  ({found} matches)");
+}
+
+fn main() {
+    let start = Instant::now();
+    let history = build_history();
+    println!("add {ENTRIES} entries          {:>12.2?}", start.elapsed());
+
+    let text = |text: &str| HistoryQuery {
+        text: text.to_string(),
+        limit: Some(100),
+        ..HistoryQuery::default()
+    };
+
+    bench("list (no text)", &history, text(""));
+    bench("rare word", &history, text("needle"));
+    bench("common prefix", &history, text("cl"));
+    bench("two words", &history, text("rust daemon"));
+    bench("phrase", &history, text("\"rust daemon\""));
+    bench("fuzzy fallback", &history, text("ndle"));
+    bench(
+        "last hour, text only",
+        &history,
+        HistoryQuery {
+            kind: Some(KindFilter::Text),
+            since: Some(SystemTime::now() - Duration::from_secs(3600)),
+            ..text("needle")
+        },
+    );
+}
@@ -1,19 +1,113 @@
 // Standard Crates
 #[allow(unused)]
-use std::{error::Error, fmt};
+use std::{
+    borrow::Cow,
+    env,
+    error::Error,
+    fmt,
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
 // External Crates
-use arboard::Clipboard;
-use serde::{Deserialize, Serialize};
+use arboard::{Clipboard, GetExtLinux, ImageData, LinuxClipboardKind};
+use image::{
+    ExtendedColorType, ImageEncoder, ImageFormat, RgbaImage, codecs::png::PngEncoder, imageops,
+};
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, SeqAccess, Visitor},
+};
+
+// My Crates
+use crate::services::x11_clipboard::{self, SelectionReader};
 
 // ------------------------- Constants --------------------------------
-pub const SOCKET_PATH: &str = "/tmp/super_v.sock";
-pub const LOCK_PATH: &str = "/tmp/super_v.lock";
+// File name of the daemon's socket inside the runtime directory
+pub const SOCKET_NAME: &str = "super_v.sock";
+
+// Longest side of image thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 200;
+// --------------------------------------------------------------------
+
+// ----------------------- Runtime Paths ------------------------------
+/// The per-user directory that holds the daemon's socket and lock file.
+///
+/// **Behavior**:
+/// - Uses `$XDG_RUNTIME_DIR` when it is set to an absolute path. The session manager makes it private to the user.
+/// - Otherwise falls back to `/tmp/super_v-<uid>`, see `ensure_private_dir`.
+///
+/// **Errors**:
+/// - Returns `IPCServerError::UnsafePath` if the fallback directory can't be created or other users could get into it.
+pub fn runtime_dir() -> Result<PathBuf, IPCServerError> {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+        && dir.is_absolute()
+    {
+        return Ok(dir);
+    }
+
+    let dir = env::temp_dir().join(format!("super_v-{}", current_uid()));
+    ensure_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Where the daemon listens unless told otherwise: `super_v.sock` in `runtime_dir()`.
+pub fn default_socket_path() -> Result<PathBuf, IPCServerError> {
+    runtime_dir().map(|dir| dir.join(SOCKET_NAME))
+}
+
+/// The lock file of the daemon listening on `socket_path`, next to it with a `.lock` extension.
+///
+/// Daemons on different sockets have different locks, so a second one can be run with `--socket`.
+pub fn lock_path(socket_path: &Path) -> PathBuf {
+    socket_path.with_extension("lock")
+}
+
+/// Creates `dir` with mode 0700 if it is missing and checks that only we can get into it.
+///
+/// Anyone can create directories in `/tmp`, so one that already exists may have been
+/// put there by another user waiting for our socket.
+///
+/// **Errors**:
+/// - Returns `IPCServerError::UnsafePath` if `dir` can't be created, is a symlink or no directory,
+///   belongs to another user, or is open to group or others.
+pub fn ensure_private_dir(dir: &Path) -> Result<(), IPCServerError> {
+    let unsafe_path =
+        |reason: &str| IPCServerError::UnsafePath(format!("{}: {reason}", dir.display()));
+
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(unsafe_path(&err.to_string())),
+    }
+
+    let metadata = fs::symlink_metadata(dir).map_err(|err| unsafe_path(&err.to_string()))?;
+    if !metadata.is_dir() {
+        return Err(unsafe_path("not a directory"));
+    }
+    if metadata.uid() != current_uid() {
+        return Err(unsafe_path("owned by another user"));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(unsafe_path("other users have access to it"));
+    }
+    Ok(())
+}
+
+/// The real user id of this process.
+pub fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail
+    unsafe { libc::getuid() }
+}
 // --------------------------------------------------------------------
 
 // --------------------------- Errors --------------------------------
 /// Error types for clipboard operations.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[allow(unused)]
 pub enum ClipboardError {
     /// Returned when attempting to access an empty clipboard
@@ -21,6 +115,18 @@ pub enum ClipboardError {
 
     /// Returned when trying to access an item out of bounds.
     IndexOutOfBound,
+
+    /// Returned when no history entry has the given id (anymore).
+    ItemNotFound(u64),
+
+    /// Returned when the clipboard can't be opened, read or written.
+    Unavailable(String),
+
+    /// Returned when an image can't be encoded or decoded.
+    InvalidImage(String),
+
+    /// Returned when the IPC client's user may not run the command.
+    PermissionDenied(String),
 }
 
 /// Error Type for Clipboard Manager Daemon
@@ -31,6 +137,12 @@ pub enum DaemonError {
     ManagerMultiSpawn,
 
     IPCErr(IPCServerError),
+
+    /// Returned when the persisted history could not be loaded.
+    StorageErr(StorageError),
+
+    /// Returned when the clipboard backend could not be opened.
+    ClipboardErr(ClipboardError),
 }
 
 /// Error Type for IPCServer
@@ -42,6 +154,99 @@ pub enum IPCServerError {
     BindError(String),
     SendError(String),
     RecieveError(String),
+
+    /// Returned when there is no directory only this user can access for the socket.
+    UnsafePath(String),
+
+    /// Returned when the daemon refused the connection, e.g. because it speaks another protocol version.
+    Rejected(IPCError),
+
+    /// Returned when a frame announces more bytes than the reader accepts.
+    FrameTooLarge(usize),
+
+    /// Returned when the other side did not send or take a frame within the stream's timeout.
+    Timeout,
+}
+
+/// Stable codes for why the daemon could not carry out a request.
+///
+/// Clients should match on these instead of `IPCResponse::message`, which is meant for people and may change.
+///
+/// **Available**:
+/// * **NotFound** - The entry the command names is not in the history (anymore).
+/// * **Locked** - The history or clipboard could not be locked. Trying again may work.
+/// * **BadRequest** - The payload was not a request the daemon understands.
+/// * **Unsupported** - Client and daemon speak different protocol versions.
+/// * **PermissionDenied** - The client's user may not connect, or may only read.
+/// * **Unavailable** - The system clipboard could not be read or written.
+/// * **Busy** - The daemon already has as many subscribers as it allows. Trying again later may work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    NotFound,
+    Locked,
+    BadRequest,
+    Unsupported,
+    PermissionDenied,
+    Unavailable,
+    Busy,
+}
+
+/// A failed request, as sent back in `IPCResult::Err`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IPCError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+/// Error Type for the on-disk history store
+#[derive(Debug, PartialEq)]
+#[allow(unused)]
+pub enum StorageError {
+    /// Reading or writing the state file failed.
+    Io(String),
+
+    /// The state file is truncated, has a bad checksum or could not be decoded.
+    Corrupt(String),
+
+    /// The state file was written by a newer/unknown format version.
+    UnsupportedVersion(u16),
+
+    /// The history could not be encoded for writing.
+    Encode(String),
+
+    /// The state file is encrypted but no key was supplied.
+    KeyRequired,
+
+    /// The key is wrong or the encrypted file was tampered with.
+    DecryptionFailed,
+
+    /// The supplied keyfile or passphrase can't be used.
+    InvalidKey(String),
+}
+
+/// Error Type for event-driven clipboard watchers
+#[derive(Debug, PartialEq)]
+#[allow(unused)]
+pub enum WatcherError {
+    /// The display server could not be reached (e.g. not a Wayland session).
+    Unavailable(String),
+
+    /// The display server is missing a protocol or extension the watcher needs.
+    Unsupported(String),
+}
+
+/// Error Type for the config file
+#[derive(Debug, PartialEq)]
+#[allow(unused)]
+pub enum ConfigError {
+    /// The config file exists but could not be read.
+    Io(String),
+
+    /// The config file is not valid TOML or a value has the wrong type.
+    Parse(String),
+
+    /// A value is out of range, e.g. a window width of 0.
+    Invalid(String),
 }
 
 // Displays for the Errors
@@ -57,6 +262,18 @@ impl fmt::Display for ClipboardError {
             ClipboardError::IndexOutOfBound => {
                 write!(f, "Item position not found or out-of-bounds.")
             }
+            ClipboardError::ItemNotFound(id) => {
+                write!(f, "No item with id {} in history.", id)
+            }
+            ClipboardError::Unavailable(string) => {
+                write!(f, "Clipboard unavailable: {}", string)
+            }
+            ClipboardError::InvalidImage(string) => {
+                write!(f, "Invalid image: {}", string)
+            }
+            ClipboardError::PermissionDenied(string) => {
+                write!(f, "Permission denied: {}", string)
+            }
         }
     }
 }
@@ -70,6 +287,12 @@ impl fmt::Display for DaemonError {
             DaemonError::IPCErr(ipc_error) => {
                 write!(f, "IPC Error: {}", ipc_error)
             }
+            DaemonError::StorageErr(storage_error) => {
+                write!(f, "Storage Error: {}", storage_error)
+            }
+            DaemonError::ClipboardErr(clipboard_error) => {
+                write!(f, "Clipboard Error: {}", clipboard_error)
+            }
         }
     }
 }
@@ -92,6 +315,101 @@ impl fmt::Display for IPCServerError {
             IPCServerError::FileNotFound => {
                 write!(f, "Sock file is missing?")
             }
+            IPCServerError::UnsafePath(string) => {
+                write!(f, "No private directory for the socket: {}", string)
+            }
+            IPCServerError::Rejected(err) => {
+                write!(f, "The daemon refused the connection: {}", err)
+            }
+            IPCServerError::FrameTooLarge(len) => {
+                write!(f, "Frame of {} bytes is too large", len)
+            }
+            IPCServerError::Timeout => {
+                write!(f, "Timed out waiting for the other side")
+            }
+        }
+    }
+}
+
+impl fmt::Display for IPCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.message, self.code)
+    }
+}
+
+impl From<&ClipboardError> for ErrorCode {
+    fn from(err: &ClipboardError) -> Self {
+        match err {
+            ClipboardError::ClipboardEmpty
+            | ClipboardError::IndexOutOfBound
+            | ClipboardError::ItemNotFound(_) => ErrorCode::NotFound,
+            ClipboardError::Unavailable(_) | ClipboardError::InvalidImage(_) => {
+                ErrorCode::Unavailable
+            }
+            ClipboardError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(string) => {
+                write!(f, "Could not access history file: {}", string)
+            }
+            StorageError::Corrupt(string) => {
+                write!(f, "History file is corrupt: {}", string)
+            }
+            StorageError::UnsupportedVersion(version) => {
+                write!(f, "History file has unsupported format version {}", version)
+            }
+            StorageError::Encode(string) => {
+                write!(f, "Could not encode history: {}", string)
+            }
+            StorageError::KeyRequired => {
+                write!(
+                    f,
+                    "History file is encrypted. Start with --keyfile or --passphrase to unlock it."
+                )
+            }
+            StorageError::DecryptionFailed => {
+                write!(
+                    f,
+                    "Could not decrypt history file. The key is wrong or the file was tampered with."
+                )
+            }
+            StorageError::InvalidKey(string) => {
+                write!(f, "Invalid key: {}", string)
+            }
+        }
+    }
+}
+
+impl fmt::Display for WatcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatcherError::Unavailable(string) => {
+                write!(f, "Display server unavailable: {}", string)
+            }
+            WatcherError::Unsupported(string) => {
+                write!(f, "Clipboard events not supported: {}", string)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(string) => {
+                write!(f, "Could not read config file: {}", string)
+            }
+            ConfigError::Parse(string) => {
+                write!(f, "Config file is invalid: {}", string)
+            }
+            ConfigError::Invalid(string) => {
+                write!(f, "Invalid config: {}", string)
+            }
         }
     }
 }
@@ -100,25 +418,338 @@ impl fmt::Display for IPCServerError {
 impl Error for ClipboardError {}
 impl Error for DaemonError {}
 impl Error for IPCServerError {}
+impl Error for IPCError {}
+impl Error for StorageError {}
+impl Error for WatcherError {}
+impl Error for ConfigError {}
 // -------------------------------------------------------------------
 
 // ----------------------- Clipboard Item ----------------------------
 /// Represents an item that can be stored in the clipboard.
 ///
-/// This enum supports both text and image data types, allowing the clipboard
+/// This enum supports text, formatted text and image data types, allowing the clipboard
 /// to handle multiple content formats.
+///
+/// Formatted text keeps the plain text the source app offered next to it, so
+/// apps that don't understand the format can still paste something.
 #[allow(unused)]
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum ClipboardItem {
     /// Plain text content
     Text(String),
 
-    /// Image content with dimensions and raw bytes
-    Image {
+    /// Image content, stored as PNG
+    Image(ClipboardImage),
+
+    /// `text/html` content, e.g. copied from a browser
+    Html { html: String, alt_text: String },
+
+    /// `text/rtf` content, e.g. copied from an office suite
+    Rtf { rtf: String, alt_text: String },
+
+    /// Files copied in a file manager, as absolute paths
+    Files(Vec<PathBuf>),
+}
+
+/// # ClipboardImage
+/// An image, stored as lossless PNG.
+///
+/// Images are only decoded when they are put back on the clipboard. Clients
+/// show the thumbnail, which the daemon renders once when the image is copied.
+///
+/// Two images are equal when their size, pixel hash and PNG match. The PNG is
+/// only compared when the rest matches, so unequal images rarely touch the
+/// (possibly large) image data.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct ClipboardImage {
+    pub width: usize,
+    pub height: usize,
+
+    /// The full image as PNG. Empty for images without pixels.
+    pub png: Vec<u8>,
+
+    /// Hash of the RGBA pixels.
+    pub hash: u64,
+
+    /// PNG no larger than `THUMBNAIL_SIZE` on either side. Empty for images without pixels.
+    pub thumbnail: Vec<u8>,
+}
+
+impl ClipboardImage {
+    /// Compresses raw RGBA pixels and renders the thumbnail.
+    ///
+    /// # Errors
+    ///
+    /// * `ClipboardError::InvalidImage` - `rgba` doesn't hold `width * height` pixels,
+    ///   or the image could not be encoded
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Result<Self, ClipboardError> {
+        let hash = pixel_hash(width, height, rgba);
+
+        // PNG can't store an image without pixels
+        if width == 0 || height == 0 {
+            if !rgba.is_empty() {
+                return Err(ClipboardError::InvalidImage(
+                    "Image without pixels has data".to_string(),
+                ));
+            }
+            return Ok(Self {
+                width,
+                height,
+                png: Vec::new(),
+                hash,
+                thumbnail: Vec::new(),
+            });
+        }
+
+        let (Ok(w), Ok(h)) = (u32::try_from(width), u32::try_from(height)) else {
+            return Err(ClipboardError::InvalidImage(
+                "Image is too large".to_string(),
+            ));
+        };
+        // The size may come from IPC or an old history file, don't let it overflow
+        let expected = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| ClipboardError::InvalidImage("Image is too large".to_string()))?;
+        let pixels = RgbaImage::from_raw(w, h, rgba.to_vec()).ok_or_else(|| {
+            ClipboardError::InvalidImage(format!(
+                "Expected {expected} bytes of pixels but found {}",
+                rgba.len()
+            ))
+        })?;
+
+        // Scale the longest side down to THUMBNAIL_SIZE, keeping the aspect ratio
+        let scale = (THUMBNAIL_SIZE as f64 / w.max(h) as f64).min(1.0);
+        let thumbnail = imageops::thumbnail(
+            &pixels,
+            ((w as f64 * scale).round() as u32).max(1),
+            ((h as f64 * scale).round() as u32).max(1),
+        );
+
+        Ok(Self {
+            width,
+            height,
+            png: encode_png(&pixels)?,
+            hash,
+            thumbnail: encode_png(&thumbnail)?,
+        })
+    }
+
+    /// Like `from_rgba`, but returns a copy of `last` if the pixels hash the same as its own.
+    ///
+    /// An image left on the clipboard is read again on every poll, this keeps it from
+    /// being compressed each time. Only a differing hash pays for the encoding.
+    ///
+    /// # Errors
+    ///
+    /// * Same as `from_rgba`
+    pub fn from_rgba_or_last(
         width: usize,
         height: usize,
-        bytes: Vec<u8>,
-    },
+        rgba: &[u8],
+        last: Option<&ClipboardImage>,
+    ) -> Result<Self, ClipboardError> {
+        match last {
+            Some(last)
+                if last.width == width
+                    && last.height == height
+                    && last.hash == pixel_hash(width, height, rgba) =>
+            {
+                Ok(last.clone())
+            }
+            _ => Self::from_rgba(width, height, rgba),
+        }
+    }
+
+    /// Decodes the image back to raw RGBA pixels.
+    ///
+    /// # Errors
+    ///
+    /// * `ClipboardError::InvalidImage` - The stored PNG is broken
+    pub fn to_rgba(&self) -> Result<Vec<u8>, ClipboardError> {
+        if self.png.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        image::load_from_memory_with_format(&self.png, ImageFormat::Png)
+            .map(|decoded| decoded.into_rgba8().into_raw())
+            .map_err(|err| ClipboardError::InvalidImage(err.to_string()))
+    }
+}
+
+impl PartialEq for ClipboardImage {
+    fn eq(&self, other: &Self) -> bool {
+        // The hash only picks out candidates, different images can share it
+        self.width == other.width
+            && self.height == other.height
+            && self.hash == other.hash
+            && self.png == other.png
+    }
+}
+
+// Stored as a MessagePack array with binary fields:
+// (width, height, png, hash, thumbnail)
+impl Serialize for ClipboardImage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (
+            self.width,
+            self.height,
+            Bytes(&self.png),
+            self.hash,
+            Bytes(&self.thumbnail),
+        )
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClipboardImage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(ImageVisitor)
+    }
+}
+
+struct ImageVisitor;
+
+impl<'de> Visitor<'de> for ImageVisitor {
+    type Value = ClipboardImage;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an image")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let width = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let height = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let ByteBuf(data) = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+        // Images saved before they were compressed end here, with raw RGBA as data
+        let Some(hash) = seq.next_element()? else {
+            return ClipboardImage::from_rgba(width, height, &data).map_err(de::Error::custom);
+        };
+        let ByteBuf(thumbnail) = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(4, &self))?;
+
+        Ok(ClipboardImage {
+            width,
+            height,
+            png: data,
+            hash,
+            thumbnail,
+        })
+    }
+}
+
+// Serializes as MessagePack bin instead of an array of numbers
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// Reads bin, or an array of numbers like older versions wrote
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ByteBufVisitor;
+
+        impl<'de> Visitor<'de> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                Ok(ByteBuf(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(ByteBuf(bytes))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                // The length comes from the sender, don't trust it with the allocation
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 16));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteBuf(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+fn encode_png(pixels: &RgbaImage) -> Result<Vec<u8>, ClipboardError> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(
+            pixels.as_raw(),
+            pixels.width(),
+            pixels.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(|err| ClipboardError::InvalidImage(err.to_string()))?;
+    Ok(png)
+}
+
+// The size is part of the hash, the same bytes can be different images
+fn pixel_hash(width: usize, height: usize, rgba: &[u8]) -> u64 {
+    let mut bytes = Vec::with_capacity(16);
+    bytes.extend_from_slice(&(width as u64).to_be_bytes());
+    bytes.extend_from_slice(&(height as u64).to_be_bytes());
+    fnv1a_from(fnv1a(&bytes), rgba)
+}
+
+/// The selections an item can be copied from.
+///
+/// **Available**:
+/// * **Clipboard** - The regular clipboard (Ctrl+C).
+/// * **Primary** - The primary selection (selected text, middle click paste).
+#[allow(unused)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Selection {
+    #[default]
+    Clipboard,
+    Primary,
+}
+
+impl ClipboardItem {
+    /// Size of the item's content in bytes.
+    pub fn byte_size(&self) -> usize {
+        match self {
+            ClipboardItem::Text(text) => text.len(),
+            ClipboardItem::Image(image) => image.png.len() + image.thumbnail.len(),
+            ClipboardItem::Html { html, alt_text } => html.len() + alt_text.len(),
+            ClipboardItem::Rtf { rtf, alt_text } => rtf.len() + alt_text.len(),
+            ClipboardItem::Files(paths) => paths.iter().map(|path| path.as_os_str().len()).sum(),
+        }
+    }
+
+    /// The plain text of the item, if it has any.
+    ///
+    /// This is the text itself, or the alt text of formatted items.
+    pub fn plain_text(&self) -> Option<&str> {
+        match self {
+            ClipboardItem::Text(text) => Some(text),
+            ClipboardItem::Html { alt_text, .. } | ClipboardItem::Rtf { alt_text, .. } => {
+                Some(alt_text)
+            }
+            ClipboardItem::Image(_) | ClipboardItem::Files(_) => None,
+        }
+    }
 }
 
 // Make the item printable
@@ -126,17 +757,61 @@ impl fmt::Display for ClipboardItem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ClipboardItem::Text(s) => write!(f, "{}", s.replace('\n', "\r\n")),
-            ClipboardItem::Image { width, height, .. } => write!(f, "[Image: {width}x{height}]"),
+            ClipboardItem::Image(image) => {
+                write!(f, "[Image: {}x{}]", image.width, image.height)
+            }
+            ClipboardItem::Html { alt_text, .. } | ClipboardItem::Rtf { alt_text, .. } => {
+                write!(f, "{}", alt_text.replace('\n', "\r\n"))
+            }
+            ClipboardItem::Files(paths) => {
+                let names: Vec<String> = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(f, "[Files: {}]", names.join(", "))
+            }
         }
     }
 }
 
-/// Trait for retrieving clipboard content as a ClipboardItem.
+// -------------------------------------------------------------------
+
+// -------------------------- Hashing --------------------------------
+/// 64-bit FNV-1a. Fast and stable across builds, but not collision resistant against attackers.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    fnv1a_from(OFFSET_BASIS, bytes)
+}
+
+// Continues an FNV-1a hash with more bytes
+fn fnv1a_from(hash: u64, bytes: &[u8]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+// -------------------------------------------------------------------
+
+// --------------------- Clipboard Backends --------------------------
+/// # ClipboardSource
+/// What the clipboard owner tells about a copy, besides its content.
 ///
-/// This trait provides a unified interface for getting clipboard content,
-/// automatically detecting whether the content is text or an image.
+/// Fields:
+/// - mime_types: Flavours (targets) the owner offers, e.g. `text/plain` or `x-kde-passwordManagerHint`.
+/// - app: The application that copied, where the platform can tell, e.g. from `WM_CLASS`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClipboardSource {
+    pub mime_types: Vec<String>,
+    pub app: Option<String>,
+}
+
+/// Trait for reading and writing clipboard content as a ClipboardItem.
+///
+/// The daemon only talks to the clipboard through this trait, so it can run
+/// against the system clipboard or a `MemoryClipboard` without a display.
 #[allow(unused)]
-pub trait GetItem {
+pub trait ClipboardBackend: Send {
     /// Retrieves the current clipboard content.
     ///
     /// # Returns
@@ -144,26 +819,405 @@ pub trait GetItem {
     /// * `Ok(ClipboardItem)` - The clipboard content as either Text or Image
     /// * `Err(ClipboardErr::ClipboardEmpty)` - If the clipboard is empty
     fn get_item(&mut self) -> Result<ClipboardItem, ClipboardError>;
+
+    /// Retrieves the current PRIMARY selection as text.
+    ///
+    /// Backends without a primary selection report it as empty.
+    fn get_primary(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        Err(ClipboardError::ClipboardEmpty)
+    }
+
+    /// Replaces the clipboard content with `item`.
+    ///
+    /// # Errors
+    ///
+    /// * `ClipboardError::Unavailable` - If the clipboard could not be written
+    fn set_item(&mut self, item: ClipboardItem) -> Result<(), ClipboardError>;
+
+    /// Who copied the current content of `selection` and which flavours they offer.
+    ///
+    /// Read it before the content, so a copy made in between isn't credited to the
+    /// previous owner. Backends that can't tell report an empty source.
+    fn get_source(&mut self, _selection: Selection) -> ClipboardSource {
+        ClipboardSource::default()
+    }
+
+    /// Reads `selection` together with who copied it. An empty selection reads as empty text.
+    ///
+    /// **Behavior**:
+    /// - The owner is read before the content, so a copy is never credited to whoever
+    ///   owns the selection after it.
+    /// - Content other than `last` is only returned if the owner is still the same afterwards,
+    ///   so a copy made while reading is picked up next time instead of being credited to the
+    ///   owner before it.
+    ///
+    /// Returns `None` if the owner changed while reading.
+    fn get_copy(
+        &mut self,
+        selection: Selection,
+        last: &ClipboardItem,
+    ) -> Option<(ClipboardItem, ClipboardSource)> {
+        let source = self.get_source(selection);
+        let item = match selection {
+            Selection::Clipboard => self.get_item(),
+            Selection::Primary => self.get_primary(),
+        }
+        .unwrap_or(ClipboardItem::Text("".to_string()));
+
+        if &item != last && self.get_source(selection) != source {
+            return None;
+        }
+        Some((item, source))
+    }
 }
 
-impl GetItem for Clipboard {
-    /// Implementation of GetItem for arboard's Clipboard.
+impl<T: ClipboardBackend + ?Sized> ClipboardBackend for Box<T> {
+    fn get_item(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        (**self).get_item()
+    }
+
+    fn get_primary(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        (**self).get_primary()
+    }
+
+    fn set_item(&mut self, item: ClipboardItem) -> Result<(), ClipboardError> {
+        (**self).set_item(item)
+    }
+
+    fn get_source(&mut self, selection: Selection) -> ClipboardSource {
+        (**self).get_source(selection)
+    }
+
+    fn get_copy(
+        &mut self,
+        selection: Selection,
+        last: &ClipboardItem,
+    ) -> Option<(ClipboardItem, ClipboardSource)> {
+        (**self).get_copy(selection, last)
+    }
+}
+
+impl ClipboardBackend for Clipboard {
+    /// Implementation of ClipboardBackend for arboard's Clipboard.
     ///
     /// Attempts to retrieve clipboard content in the following order:
-    /// 1. Image data (if available)
-    /// 2. Text data (if available)
-    /// 3. Returns ClipboardEmpty error if neither is available
+    /// 1. Copied files (if available)
+    /// 2. Image data (if available)
+    /// 3. HTML, with the plain text as alt text (if available)
+    /// 4. RTF, with the plain text as alt text (if available)
+    /// 5. Text data (if available)
+    /// 6. Returns ClipboardEmpty error if none is available
+    ///
+    /// Apps that offer both HTML and RTF are recorded as HTML. File managers
+    /// also offer the paths as text, so files are checked first.
+    ///
+    /// The owner's `TARGETS` are read once over a single X11 connection, and
+    /// flavours it doesn't list are skipped without asking for them.
     fn get_item(&mut self) -> Result<ClipboardItem, ClipboardError> {
-        if let Ok(img_dat) = self.get_image() {
-            Ok(ClipboardItem::Image {
-                width: img_dat.width,
-                height: img_dat.height,
-                bytes: img_dat.bytes.to_vec(),
-            })
-        } else if let Ok(str_data) = self.get_text() {
-            Ok(ClipboardItem::Text(str_data))
-        } else {
-            Err(ClipboardError::ClipboardEmpty)
+        // Without X11 there is nothing to tell the flavours, so arboard tries them all
+        let reader = SelectionReader::open(None, Selection::Clipboard).ok();
+        read_clipboard(self, reader.as_ref(), None)
+    }
+
+    fn get_primary(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        self.get()
+            .clipboard(LinuxClipboardKind::Primary)
+            .text()
+            .map(ClipboardItem::Text)
+            .map_err(|_| ClipboardError::ClipboardEmpty)
+    }
+
+    fn set_item(&mut self, item: ClipboardItem) -> Result<(), ClipboardError> {
+        let result = match item {
+            ClipboardItem::Text(text) => self.set_text(text),
+            ClipboardItem::Image(image) => self.set_image(ImageData {
+                width: image.width,
+                height: image.height,
+                bytes: Cow::from(image.to_rgba()?),
+            }),
+            ClipboardItem::Html { html, alt_text } => self.set().html(html, Some(alt_text)),
+            // arboard can't offer RTF, serve every flavour ourselves.
+            // Without X11 (e.g. pure Wayland) the plain text is all we can offer.
+            ClipboardItem::Rtf { rtf, alt_text } => {
+                let offered = x11_clipboard::offer_flavours(
+                    None,
+                    vec![
+                        (x11_clipboard::RTF, rtf.into_bytes()),
+                        (x11_clipboard::UTF8_TEXT, alt_text.clone().into_bytes()),
+                        (x11_clipboard::PLAIN_TEXT, alt_text.clone().into_bytes()),
+                    ],
+                );
+                match offered {
+                    Ok(()) => return Ok(()),
+                    Err(_) => self.set_text(alt_text),
+                }
+            }
+            // Without X11, arboard offers the URI list, or at least the paths as text
+            ClipboardItem::Files(paths) => {
+                let text = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let offered = x11_clipboard::offer_flavours(
+                    None,
+                    vec![
+                        (
+                            x11_clipboard::URI_LIST,
+                            x11_clipboard::paths_to_uri_list(&paths).into_bytes(),
+                        ),
+                        (
+                            x11_clipboard::GNOME_COPIED_FILES,
+                            x11_clipboard::paths_to_gnome_copied_files(&paths).into_bytes(),
+                        ),
+                        (x11_clipboard::UTF8_TEXT, text.clone().into_bytes()),
+                        (x11_clipboard::PLAIN_TEXT, text.clone().into_bytes()),
+                    ],
+                );
+                match offered {
+                    Ok(()) => return Ok(()),
+                    Err(_) => self
+                        .set()
+                        .file_list(&paths)
+                        .or_else(|_| self.set_text(text)),
+                }
+            }
+        };
+        result.map_err(|err| ClipboardError::Unavailable(err.to_string()))
+    }
+
+    /// Asks the owner of `selection` for its `TARGETS` and looks up which application it is.
+    ///
+    /// Both go through one X11 (or XWayland) connection. Anything that can't be read is left empty.
+    fn get_source(&mut self, selection: Selection) -> ClipboardSource {
+        SelectionReader::open(None, selection)
+            .map(|reader| selection_source(&reader))
+            .unwrap_or_default()
+    }
+
+    /// Reads the owner, its `TARGETS`, the content and whether the owner is still the
+    /// same through one X11 (or XWayland) connection. The owner is compared by window.
+    fn get_copy(
+        &mut self,
+        selection: Selection,
+        last: &ClipboardItem,
+    ) -> Option<(ClipboardItem, ClipboardSource)> {
+        let reader = SelectionReader::open(None, selection).ok();
+        let item = match selection {
+            Selection::Clipboard => read_clipboard(self, reader.as_ref(), Some(last)),
+            Selection::Primary => self.get_primary(),
+        }
+        .unwrap_or(ClipboardItem::Text("".to_string()));
+
+        // Without X11 nobody tells who copied
+        let Some(reader) = reader else {
+            return Some((item, ClipboardSource::default()));
+        };
+        if &item != last && reader.owner_changed() {
+            return None;
+        }
+        Some((item, selection_source(&reader)))
+    }
+}
+
+// The clipboard content, with the flavours `reader` lists (see `get_item`).
+// An image with the pixels of `last` isn't encoded again.
+fn read_clipboard(
+    clipboard: &mut Clipboard,
+    reader: Option<&SelectionReader>,
+    last: Option<&ClipboardItem>,
+) -> Result<ClipboardItem, ClipboardError> {
+    let offers = |matches: fn(&str) -> bool| reader.is_none_or(|reader| reader.offers(matches));
+
+    if let Some(paths) = reader.and_then(SelectionReader::copied_files) {
+        Ok(ClipboardItem::Files(paths))
+    } else if offers(|target| target.starts_with("image/"))
+        && let Ok(img_dat) = clipboard.get_image()
+    {
+        let last = match last {
+            Some(ClipboardItem::Image(image)) => Some(image),
+            _ => None,
+        };
+        ClipboardImage::from_rgba_or_last(img_dat.width, img_dat.height, &img_dat.bytes, last)
+            .map(ClipboardItem::Image)
+    } else if offers(|target| target == "text/html")
+        && let Ok(html) = clipboard.get().html()
+    {
+        Ok(ClipboardItem::Html {
+            html,
+            alt_text: clipboard.get_text().unwrap_or_default(),
+        })
+    } else if let Some(rtf) = reader.and_then(|reader| reader.read(x11_clipboard::RTF).ok()) {
+        Ok(ClipboardItem::Rtf {
+            rtf: String::from_utf8_lossy(&rtf).into_owned(),
+            alt_text: clipboard.get_text().unwrap_or_default(),
+        })
+    } else if let Ok(str_data) = clipboard.get_text() {
+        Ok(ClipboardItem::Text(str_data))
+    } else {
+        Err(ClipboardError::ClipboardEmpty)
+    }
+}
+
+fn selection_source(reader: &SelectionReader) -> ClipboardSource {
+    ClipboardSource {
+        mime_types: reader.targets().to_vec(),
+        app: reader.owner_app(),
+    }
+}
+
+/// # MemoryClipboard
+/// In-memory clipboard for tests and headless runs.
+///
+/// Clones share their content, like two handles to the system clipboard.
+/// Keep a clone to change what the daemon sees.
+#[allow(unused)]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    item: Arc<Mutex<Option<ClipboardItem>>>,
+    primary: Arc<Mutex<Option<ClipboardItem>>>,
+    source: Arc<Mutex<ClipboardSource>>,
+    primary_source: Arc<Mutex<ClipboardSource>>,
+}
+
+impl MemoryClipboard {
+    /// Creates an empty clipboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the PRIMARY selection, like selecting text with the mouse.
+    pub fn set_primary(&mut self, item: ClipboardItem) -> Result<(), ClipboardError> {
+        Self::store(&self.primary, item)?;
+        Self::store_source(&self.primary_source, ClipboardSource::default())
+    }
+
+    /// Replaces the PRIMARY selection as if text was selected in `source`.
+    pub fn select_from(
+        &mut self,
+        item: ClipboardItem,
+        source: ClipboardSource,
+    ) -> Result<(), ClipboardError> {
+        Self::store(&self.primary, item)?;
+        Self::store_source(&self.primary_source, source)
+    }
+
+    /// Replaces the clipboard content as if `source` had copied it, e.g. a password manager.
+    ///
+    /// `set_item` resets the source, like any other copy would.
+    pub fn copy_from(
+        &mut self,
+        item: ClipboardItem,
+        source: ClipboardSource,
+    ) -> Result<(), ClipboardError> {
+        Self::store(&self.item, item)?;
+        Self::store_source(&self.source, source)
+    }
+
+    fn store_source(
+        slot: &Mutex<ClipboardSource>,
+        source: ClipboardSource,
+    ) -> Result<(), ClipboardError> {
+        let mut current = slot
+            .lock()
+            .map_err(|_| ClipboardError::Unavailable("Clipboard lock poisoned".to_string()))?;
+        *current = source;
+        Ok(())
+    }
+
+    fn load(slot: &Mutex<Option<ClipboardItem>>) -> Result<ClipboardItem, ClipboardError> {
+        let item = slot
+            .lock()
+            .map_err(|_| ClipboardError::Unavailable("Clipboard lock poisoned".to_string()))?;
+        item.clone().ok_or(ClipboardError::ClipboardEmpty)
+    }
+
+    fn store(
+        slot: &Mutex<Option<ClipboardItem>>,
+        item: ClipboardItem,
+    ) -> Result<(), ClipboardError> {
+        let mut current = slot
+            .lock()
+            .map_err(|_| ClipboardError::Unavailable("Clipboard lock poisoned".to_string()))?;
+        *current = Some(item);
+        Ok(())
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn get_item(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        Self::load(&self.item)
+    }
+
+    fn get_primary(&mut self) -> Result<ClipboardItem, ClipboardError> {
+        Self::load(&self.primary)
+    }
+
+    fn set_item(&mut self, item: ClipboardItem) -> Result<(), ClipboardError> {
+        Self::store(&self.item, item)?;
+        Self::store_source(&self.source, ClipboardSource::default())
+    }
+
+    fn get_source(&mut self, selection: Selection) -> ClipboardSource {
+        let slot = match selection {
+            Selection::Clipboard => &self.source,
+            Selection::Primary => &self.primary_source,
+        };
+        slot.lock().map(|source| source.clone()).unwrap_or_default()
+    }
+}
+// -------------------------------------------------------------------
+
+// ---------------------------- Clock --------------------------------
+/// Where the daemon reads the time from.
+///
+/// Lets tests move time forward with a `ManualClock` instead of sleeping.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// # SystemClock
+/// The system's wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// # ManualClock
+/// A clock that only moves when told to.
+///
+/// Clones share their time. Keep a clone to move the daemon's time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    /// Creates a clock standing at `start`.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        if let Ok(mut now) = self.now.lock() {
+            *now += by;
+        }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        match self.now.lock() {
+            Ok(now) => *now,
+            Err(poisoned) => *poisoned.into_inner(),
         }
     }
 }
@@ -0,0 +1,671 @@
+// Standard Crates
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+// External Crates
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, Visitor},
+};
+
+// My Crates
+use crate::{
+    common::ConfigError,
+    history::{ExpiryPolicy, HistoryLimits, KindFilter, KindLimit},
+    sensitive::{RuleClassifier, SecretAction, SecretRule, SourcePolicy, builtin_rules},
+    services::{
+        clipboard_ipc_server::{DEFAULT_IPC_TIMEOUT, DEFAULT_MAX_REQUEST_SIZE, FrameLimits},
+        clipboard_manager::Manager,
+        clipboard_watcher::WatchOptions,
+        ydotool::{DEFAULT_SOCKET, PasteKey},
+    },
+};
+
+const APP_DIR: &str = "super_v";
+const CONFIG_FILE: &str = "config.toml";
+
+// ------------------------- Config File -----------------------------
+/// # Config
+/// Settings read from `config.toml` by the daemon and the GUI.
+///
+/// Every section and key is optional, missing ones keep their defaults.
+/// Unknown keys are errors, so typos don't go unnoticed.
+///
+/// ```toml
+/// [history]
+/// max_items = 500
+/// max_bytes = "200M"
+/// expire_after = "7d"
+/// kinds.image = { max_items = 20, expire_after = "1h" }
+///
+/// [watch]
+/// primary = true
+/// primary_debounce = "500ms"
+/// poll_interval = "100ms"
+///
+/// [secrets]
+/// concealed = "30s"
+/// deny_apps = ["keepassxc"]
+/// rules = [{ action = "mask", pattern = "corp-[0-9]{8}" }]
+///
+/// [gui]
+/// width = 360
+/// height = 400
+/// image_preview_size = 50
+/// shown_items = 100
+///
+/// [paste]
+/// ydotool_socket = "/tmp/.ydotool_socket"
+/// key = "shift-insert"
+///
+/// [ipc]
+/// max_frame_size = "1M"
+/// timeout = "5s"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub history: HistoryConfig,
+    pub watch: WatchConfig,
+    pub secrets: SecretsConfig,
+    pub gui: GuiConfig,
+    pub paste: PasteConfig,
+    pub ipc: IpcConfig,
+}
+
+/// # HistoryConfig
+/// `[history]`: how much is kept, and for how long.
+///
+/// Fields:
+/// - max_items: Unpinned items kept.
+/// - max_bytes: Bytes unpinned items may take, a number or a size like "500M".
+/// - expire_after: Entries not used for this long are removed, e.g. "8h".
+/// - kinds: The same per kind (`text`, `formatted`, `image` or `files`).
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub max_items: usize,
+    #[serde(deserialize_with = "some_size")]
+    pub max_bytes: Option<usize>,
+    #[serde(deserialize_with = "some_duration")]
+    pub expire_after: Option<Duration>,
+    #[serde(deserialize_with = "kind_map")]
+    pub kinds: BTreeMap<KindFilter, KindConfig>,
+}
+
+impl Default for HistoryConfig {
+    /// `Manager::DEFAULT_HISTORY_SIZE` items, no byte limit and no expiry.
+    fn default() -> Self {
+        Self {
+            max_items: Manager::DEFAULT_HISTORY_SIZE,
+            max_bytes: None,
+            expire_after: None,
+            kinds: BTreeMap::new(),
+        }
+    }
+}
+
+/// # KindConfig
+/// `[history.kinds.<kind>]`: limits and expiry of one kind, on top of the global ones.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KindConfig {
+    pub max_items: Option<usize>,
+    #[serde(deserialize_with = "some_size")]
+    pub max_bytes: Option<usize>,
+    #[serde(deserialize_with = "some_duration")]
+    pub expire_after: Option<Duration>,
+}
+
+/// # WatchConfig
+/// `[watch]`: what the daemon records.
+///
+/// Fields:
+/// - primary: Also record the PRIMARY selection.
+/// - primary_debounce: How long PRIMARY has to stay unchanged before it is recorded.
+/// - poll_interval: How often the clipboard is read when no change events are available.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    pub primary: bool,
+    #[serde(deserialize_with = "duration")]
+    pub primary_debounce: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchConfig {
+    /// Same as `WatchOptions::default`.
+    fn default() -> Self {
+        let options = WatchOptions::default();
+        Self {
+            primary: options.track_primary,
+            primary_debounce: options.primary_debounce,
+            poll_interval: options.poll_interval,
+        }
+    }
+}
+
+/// # SecretsConfig
+/// `[secrets]`: what counts as a secret, on top of the built-in rules.
+///
+/// Fields:
+/// - rules: `{ action, pattern }` tables. Tried in order, before the built-in rules.
+/// - concealed: What happens to copies password managers mark as concealed.
+/// - deny_apps: Applications whose copies are never recorded.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    #[serde(deserialize_with = "secret_rules")]
+    pub rules: Vec<SecretRule>,
+    #[serde(deserialize_with = "secret_action")]
+    pub concealed: SecretAction,
+    pub deny_apps: Vec<String>,
+}
+
+impl Default for SecretsConfig {
+    /// Only the built-in rules, concealed copies are skipped.
+    fn default() -> Self {
+        let sources = SourcePolicy::default();
+        Self {
+            rules: Vec::new(),
+            concealed: sources.concealed,
+            deny_apps: sources.denied_apps,
+        }
+    }
+}
+
+/// # GuiConfig
+/// `[gui]`: size of the window and how much it shows. Sizes are in pixels.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuiConfig {
+    pub width: i32,
+    pub height: i32,
+    pub image_preview_size: i32,
+    pub shown_items: usize,
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        Self {
+            width: 360,
+            height: 400,
+            image_preview_size: 50,
+            shown_items: 100,
+        }
+    }
+}
+
+/// # PasteConfig
+/// `[paste]`: how a picked item is pasted into the focused window.
+///
+/// Fields:
+/// - ydotool_socket: Where ydotoold listens.
+/// - key: The shortcut sent, "shift-insert", "ctrl-v" or "ctrl-shift-v".
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasteConfig {
+    pub ydotool_socket: PathBuf,
+    pub key: PasteKey,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        Self {
+            ydotool_socket: PathBuf::from(DEFAULT_SOCKET),
+            key: PasteKey::default(),
+        }
+    }
+}
+
+/// # IpcConfig
+/// `[ipc]`: what the daemon accepts from clients.
+///
+/// Fields:
+/// - max_frame_size: The largest request a client may send, a number or a size like "1M".
+/// - timeout: How long the daemon waits for a client to send or take a frame.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpcConfig {
+    #[serde(deserialize_with = "size")]
+    pub max_frame_size: usize,
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Duration,
+}
+
+impl Default for IpcConfig {
+    /// Same as `FrameLimits::default`.
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_REQUEST_SIZE,
+            timeout: DEFAULT_IPC_TIMEOUT,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the default config file location.
+    ///
+    /// Resolves to `$XDG_CONFIG_HOME/super_v/config.toml`, falling back to
+    /// `$HOME/.config/super_v/config.toml`. Returns `None` if neither is set.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_home.join(APP_DIR).join(CONFIG_FILE))
+    }
+
+    /// Reads and validates the config file at `path`.
+    ///
+    /// **Behavior**:
+    /// - A missing file is not an error, every setting keeps its default.
+    ///
+    /// # Errors
+    ///
+    /// * `ConfigError::Io` - The file exists but can't be read
+    /// * `ConfigError::Parse` - It is not valid TOML or a value has the wrong type, with the line
+    /// * `ConfigError::Invalid` - A value is out of range
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigError::Io(format!("{}: {err}", path.display()))),
+        }
+    }
+
+    /// Parses and validates config text.
+    ///
+    /// # Errors
+    ///
+    /// Same as `Config::load`, except for `ConfigError::Io`.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            toml::from_str(text).map_err(|err| ConfigError::Parse(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values serde can't, e.g. that sizes aren't zero.
+    ///
+    /// # Errors
+    ///
+    /// * `ConfigError::Invalid` - Names the first offending key
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid =
+            |key: &str, reason: &str| Err(ConfigError::Invalid(format!("{key} {reason}")));
+
+        if self.history.max_items == 0 {
+            return invalid("history.max_items", "must be at least 1");
+        }
+        if self.history.expire_after == Some(Duration::ZERO) {
+            return invalid("history.expire_after", "must be longer than 0s");
+        }
+        if let Some((kind, _)) = self
+            .history
+            .kinds
+            .iter()
+            .find(|(_, kind)| kind.expire_after == Some(Duration::ZERO))
+        {
+            return invalid(
+                &format!("history.kinds.{}.expire_after", kind_name(*kind)),
+                "must be longer than 0s",
+            );
+        }
+        if self.watch.poll_interval < MIN_POLL_INTERVAL {
+            return invalid("watch.poll_interval", "must be at least 10ms");
+        }
+        if self.secrets.concealed == SecretAction::Expire(Duration::ZERO) {
+            return invalid(
+                "secrets.concealed",
+                "must not expire after 0s, use \"skip\"",
+            );
+        }
+        if let Some(rule) = self
+            .secrets
+            .rules
+            .iter()
+            .find(|rule| rule.action == SecretAction::Expire(Duration::ZERO))
+        {
+            return invalid(
+                &format!("secrets.rules '{}'", rule.name),
+                "must not expire after 0s, use \"skip\"",
+            );
+        }
+        if self.gui.width <= 0 || self.gui.height <= 0 {
+            return invalid("gui.width and gui.height", "must be greater than 0");
+        }
+        if self.gui.image_preview_size <= 0 {
+            return invalid("gui.image_preview_size", "must be greater than 0");
+        }
+        if self.gui.shown_items == 0 {
+            return invalid("gui.shown_items", "must be at least 1");
+        }
+        if self.ipc.max_frame_size < MIN_FRAME_SIZE {
+            return invalid("ipc.max_frame_size", "must be at least 4K");
+        }
+        if self.ipc.timeout.is_zero() {
+            return invalid("ipc.timeout", "must be longer than 0s");
+        }
+        Ok(())
+    }
+
+    /// The history limits of `[history]`.
+    pub fn history_limits(&self) -> HistoryLimits {
+        let mut limits = HistoryLimits::new(self.history.max_items);
+        limits.max_bytes = self.history.max_bytes;
+        for (kind, config) in &self.history.kinds {
+            if config.max_items.is_some() || config.max_bytes.is_some() {
+                limits.kinds.insert(
+                    *kind,
+                    KindLimit {
+                        max_items: config.max_items,
+                        max_bytes: config.max_bytes,
+                    },
+                );
+            }
+        }
+        limits
+    }
+
+    /// The expiry policy of `[history]`.
+    pub fn expiry(&self) -> ExpiryPolicy {
+        ExpiryPolicy {
+            max_age: self.history.expire_after,
+            kinds: self
+                .history
+                .kinds
+                .iter()
+                .filter_map(|(kind, config)| Some((*kind, config.expire_after?)))
+                .collect(),
+        }
+    }
+
+    /// The `[secrets]` rules followed by the built-in ones.
+    pub fn classifier(&self) -> RuleClassifier {
+        let mut classifier = RuleClassifier::new();
+        for rule in self.secrets.rules.iter().cloned().chain(builtin_rules()) {
+            classifier.push(rule);
+        }
+        classifier
+    }
+
+    /// What happens to concealed copies and denied apps, from `[secrets]`.
+    pub fn source_policy(&self) -> SourcePolicy {
+        SourcePolicy {
+            concealed: self.secrets.concealed,
+            denied_apps: self.secrets.deny_apps.clone(),
+        }
+    }
+
+    /// What the watch loop records, from `[watch]`.
+    pub fn watch_options(&self) -> WatchOptions {
+        WatchOptions {
+            track_primary: self.watch.primary,
+            primary_debounce: self.watch.primary_debounce,
+            poll_interval: self.watch.poll_interval,
+        }
+    }
+
+    /// The limits of IPC connections, from `[ipc]`.
+    pub fn frame_limits(&self) -> FrameLimits {
+        FrameLimits {
+            max_request_size: self.ipc.max_frame_size,
+            timeout: Some(self.ipc.timeout),
+        }
+    }
+}
+
+// Reading the clipboard more often than this only burns CPU
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Every request fits in this, even the ones carrying a query
+const MIN_FRAME_SIZE: usize = 4 << 10;
+
+/// # ConfigFile
+/// A config file the daemon reloads, and the command line flags applied on top of it.
+///
+/// Fields:
+/// - path: The file, which may not exist (yet).
+/// - overrides: Applied after every load, so flags keep winning over the file.
+#[derive(Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub overrides: Arc<dyn Fn(&mut Config) + Send + Sync>,
+}
+
+impl ConfigFile {
+    /// A config file without overrides.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            overrides: Arc::new(|_| {}),
+        }
+    }
+
+    /// Loads the file (see `Config::load`) and applies the overrides.
+    ///
+    /// # Errors
+    ///
+    /// Same as `Config::load`, or `ConfigError::Invalid` if the overrides made it invalid.
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        let mut config = Config::load(&self.path)?;
+        (self.overrides)(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// When the file was last changed, and its size. `None` if it doesn't exist.
+    ///
+    /// Editors often replace the file instead of writing to it, the size catches
+    /// changes within the resolution of the modification time.
+    pub fn stamp(&self) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(&self.path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
+
+impl fmt::Debug for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigFile")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+// -------------------------------------------------------------------
+
+// ------------------------- Value Parsing ---------------------------
+/// Parses a byte count with an optional K, M or G suffix (powers of 1024).
+pub fn parse_size(text: &str) -> Result<usize, String> {
+    let text = text.trim();
+    let (number, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((at, _)) => text.split_at(at),
+        None => (text, ""),
+    };
+    let factor: usize = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit '{unit}'")),
+    };
+    number
+        .parse::<usize>()
+        .map_err(|err| err.to_string())?
+        .checked_mul(factor)
+        .ok_or_else(|| format!("{text} is too large"))
+}
+
+/// Parses a duration like 500ms, 90s, 15m, 8h or 7d. Plain numbers are seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (number, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((at, _)) => text.split_at(at),
+        None => (text, "s"),
+    };
+    let factor_ms: u64 = match unit.trim() {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => {
+            return Err(format!(
+                "unknown duration unit '{unit}', expected ms, s, m, h or d"
+            ));
+        }
+    };
+    number
+        .parse::<u64>()
+        .map_err(|err| err.to_string())?
+        .checked_mul(factor_ms)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("{text} is too long"))
+}
+
+/// Parses a kind name: text, formatted, image or files.
+pub fn parse_kind(text: &str) -> Result<KindFilter, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "text" => Ok(KindFilter::Text),
+        "formatted" => Ok(KindFilter::Formatted),
+        "image" => Ok(KindFilter::Image),
+        "files" => Ok(KindFilter::Files),
+        other => Err(format!(
+            "unknown kind '{other}', expected text, formatted, image or files"
+        )),
+    }
+}
+
+/// Parses skip, mask or a duration to expire after.
+pub fn parse_secret_action(text: &str) -> Result<SecretAction, String> {
+    match text.trim() {
+        "skip" => Ok(SecretAction::Skip),
+        "mask" => Ok(SecretAction::Mask),
+        after => parse_duration(after)
+            .map(SecretAction::Expire)
+            .map_err(|err| {
+                format!("unknown action '{after}', expected skip, mask or a duration ({err})")
+            }),
+    }
+}
+
+// The name `parse_kind` accepts for `kind`
+fn kind_name(kind: KindFilter) -> &'static str {
+    match kind {
+        KindFilter::Text => "text",
+        KindFilter::Formatted => "formatted",
+        KindFilter::Image => "image",
+        KindFilter::Files => "files",
+    }
+}
+
+// A TOML integer or string. Sizes and durations may be either.
+enum NumberOrText {
+    Number(u64),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for NumberOrText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NumberOrTextVisitor;
+
+        impl Visitor<'_> for NumberOrTextVisitor {
+            type Value = NumberOrText;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number or a string")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                u64::try_from(value)
+                    .map(NumberOrText::Number)
+                    .map_err(|_| E::custom("must not be negative"))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(NumberOrText::Number(value))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(NumberOrText::Text(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(NumberOrTextVisitor)
+    }
+}
+
+// Bytes, or a size like "500M"
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    match NumberOrText::deserialize(deserializer)? {
+        NumberOrText::Number(bytes) => usize::try_from(bytes).map_err(de::Error::custom),
+        NumberOrText::Text(text) => parse_size(&text).map_err(de::Error::custom),
+    }
+}
+
+fn some_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    size(deserializer).map(Some)
+}
+
+// Seconds, or a duration like "8h"
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    match NumberOrText::deserialize(deserializer)? {
+        NumberOrText::Number(seconds) => Ok(Duration::from_secs(seconds)),
+        NumberOrText::Text(text) => parse_duration(&text).map_err(de::Error::custom),
+    }
+}
+
+fn some_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
+
+fn kind_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<KindFilter, KindConfig>, D::Error> {
+    BTreeMap::<String, KindConfig>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(kind, config)| Ok((parse_kind(&kind).map_err(de::Error::custom)?, config)))
+        .collect()
+}
+
+fn secret_action<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SecretAction, D::Error> {
+    match NumberOrText::deserialize(deserializer)? {
+        NumberOrText::Number(seconds) => Ok(SecretAction::Expire(Duration::from_secs(seconds))),
+        NumberOrText::Text(text) => parse_secret_action(&text).map_err(de::Error::custom),
+    }
+}
+
+fn secret_rules<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SecretRule>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct RawRule {
+        #[serde(default)]
+        name: Option<String>,
+        action: String,
+        pattern: String,
+    }
+
+    // Unnamed rules are numbered, their pattern may hold part of a secret
+    Vec::<RawRule>::deserialize(deserializer)?
+        .into_iter()
+        .enumerate()
+        .map(|(n, rule)| {
+            let action = parse_secret_action(&rule.action).map_err(de::Error::custom)?;
+            let name = rule.name.unwrap_or_else(|| format!("custom-{}", n + 1));
+            SecretRule::new(name, &rule.pattern, action).map_err(de::Error::custom)
+        })
+        .collect()
+}
+// -------------------------------------------------------------------
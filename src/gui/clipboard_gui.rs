@@ -1,15 +1,27 @@
 use crate::{
-    common::ClipboardItem,
-    history::ClipboardHistory,
+    common::Selection,
+    config::GuiConfig,
+    history::{
+        ClipboardHistory, EntrySummary, HistoryEntry, HistoryEvent, HistoryQuery, HistorySummary,
+        ItemKind, KindFilter, PREVIEW_CHARS,
+    },
     services::clipboard_ipc_server::{
-        CmdIPC, IPCRequest, Payload, create_default_stream, read_payload, send_payload,
+        CmdIPC, IPCRequest, IPCResult, Payload, create_stream, read_payload, send_payload,
     },
 };
-use arboard::{Clipboard, ImageData};
-use gdk_pixbuf::{InterpType, Pixbuf};
+use arboard::Clipboard;
 use gtk::gdk::Texture;
 use gtk4::{self as gtk, Application, gdk::Key, prelude::*};
-use std::{borrow::Cow, collections::HashMap, rc::Rc, sync::mpsc::Sender, thread, time::Duration};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, SystemTime},
+};
 
 pub enum MainThreadMsg {
     AutoPaste,
@@ -26,17 +38,29 @@ struct Gui {
     window: gtk::ApplicationWindow,
     stack: gtk::Stack,
     clear_all_btn: gtk::Button,
+    origin_filter: gtk::DropDown,
+    kind_filter: gtk::DropDown,
     search_entry: gtk::Entry,
     items_box: gtk::Box,
     emoji_flow_box: gtk::FlowBox,
-    image_cache: Rc<std::cell::RefCell<HashMap<Vec<u8>, Texture>>>,
+    image_cache: Rc<std::cell::RefCell<HashMap<u64, Texture>>>,
     main_thread_tx: Sender<MainThreadMsg>,
+    config: GuiConfig,
+    socket_path: PathBuf,
 }
 
 impl Gui {
     const APP_ID: &str = "com.ecstra.super_v";
 
-    fn new(app: &Application, main_thread_tx: Sender<MainThreadMsg>) -> Rc<Self> {
+    // Shown instead of the text of secrets, until they are clicked
+    const MASKED_LABEL: &str = "•••• (secret, click to reveal)";
+
+    fn new(
+        app: &Application,
+        main_thread_tx: Sender<MainThreadMsg>,
+        config: GuiConfig,
+        socket_path: PathBuf,
+    ) -> Rc<Self> {
         // -------------------- Window Creation ----------------------
         let window = gtk::ApplicationWindow::builder().build();
         window.set_application(Some(app));
@@ -44,12 +68,10 @@ impl Gui {
         // -----------------------------------------------------------
 
         // -------------------- Window Settings ----------------------
-        const WIDTH: i32 = 360;
-        const HEIGHT: i32 = 400;
         const TOP_PANEL: bool = false;
         const MODAL: bool = true;
 
-        window.set_default_size(WIDTH, HEIGHT);
+        window.set_default_size(config.width, config.height);
         window.set_decorated(TOP_PANEL);
         window.set_modal(MODAL);
         // -----------------------------------------------------------
@@ -81,14 +103,30 @@ impl Gui {
         clear_all_btn.set_halign(gtk::Align::End);
         clear_all_btn.set_visible(true); // Visible by default
 
+        // Which items to list: all, copied (CLIPBOARD) or selected (PRIMARY)
+        let origin_filter = gtk::DropDown::from_strings(&["All", "Copied", "Selected"]);
+        origin_filter.add_css_class("origin-filter");
+        origin_filter.set_valign(gtk::Align::Center);
+
         header_box.append(&clear_all_btn);
+        header_box.append(&origin_filter);
         main_box.append(&header_box);
 
+        let search_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+
         let search_entry = gtk::Entry::new();
-        search_entry.set_placeholder_text(Some("Search emojis..."));
+        search_entry.set_placeholder_text(Some("Search clipboard..."));
         search_entry.add_css_class("search-entry");
-        search_entry.set_visible(false); // Hidden by default
-        main_box.append(&search_entry);
+        search_entry.set_hexpand(true);
+        search_box.append(&search_entry);
+
+        // Which kinds of items to list, clipboard tab only
+        let kind_filter =
+            gtk::DropDown::from_strings(&["All types", "Text", "Formatted", "Images", "Files"]);
+        kind_filter.add_css_class("kind-filter");
+        kind_filter.set_valign(gtk::Align::Center);
+        search_box.append(&kind_filter);
+        main_box.append(&search_box);
 
         // Create the Stack
         let stack = gtk::Stack::new();
@@ -143,11 +181,15 @@ impl Gui {
             window: window.clone(), // Clone for the struct
             stack: stack.clone(),   // Clone for the struct
             clear_all_btn,
+            origin_filter,
+            kind_filter,
             search_entry,
             items_box: items_box.clone(), // Clone for the struct
             emoji_flow_box,
             image_cache: Rc::new(std::cell::RefCell::new(HashMap::new())),
             main_thread_tx,
+            config,
+            socket_path,
         })
     }
 
@@ -157,16 +199,18 @@ impl Gui {
         }
     }
 
-    fn schedule_emoji_cleanup(tx: Sender<MainThreadMsg>, emoji_text: String) {
+    fn schedule_emoji_cleanup(tx: Sender<MainThreadMsg>, socket_path: PathBuf, emoji_text: String) {
         thread::spawn(move || {
-            let target_item = ClipboardItem::Text(emoji_text);
             for attempt in 0..5 {
                 thread::sleep(Duration::from_millis(120 * (attempt + 1) as u64));
-                if let Some(history) = Self::send_command(CmdIPC::Snapshot)
-                    && history.get_items().iter().any(|item| item == &target_item)
+                // Emojis are short, the preview is the whole text
+                if let Some(entry) = Self::fetch_summary(&socket_path, CmdIPC::Summary)
+                    .items
+                    .iter()
+                    .find(|entry| entry.kind == ItemKind::Text && entry.preview == emoji_text)
                 {
-                    // If emoji is found, delete that
-                    let _ = Self::send_command(CmdIPC::DeleteThis(target_item.clone()));
+                    // If emoji is found, delete that entry
+                    let _ = Self::send_command(&socket_path, CmdIPC::Delete(entry.id));
 
                     // break out of the for loop
                     break;
@@ -198,38 +242,96 @@ impl Gui {
         window.close();
     }
 
-    fn fetch_history() -> ClipboardHistory {
-        let new_clipboard = ClipboardHistory::new(25);
-
-        match create_default_stream() {
+    // Previews and thumbnails only, so opening stays fast with large images in history
+    fn fetch_summary(socket_path: &Path, cmd: CmdIPC) -> HistorySummary {
+        match create_stream(socket_path) {
             Ok(mut stream) => {
-                send_payload(
-                    &mut stream,
-                    Payload::Request(IPCRequest {
-                        cmd: CmdIPC::Snapshot,
-                    }),
-                );
+                let _ = send_payload(&mut stream, Payload::Request(IPCRequest { cmd }));
 
                 let received_payload = read_payload(&mut stream);
                 match received_payload {
-                    Payload::Response(ipc_resp) => {
-                        ipc_resp.history_snapshot.unwrap_or(new_clipboard)
-                    }
-                    _ => new_clipboard,
+                    Ok(Payload::Response(ipc_resp)) => ipc_resp.summary.unwrap_or_default(),
+                    _ => HistorySummary::default(),
+                }
+            }
+            Err(_) => HistorySummary::default(),
+        }
+    }
+
+    // Asks the daemon to put entry `id` back on the clipboard. Returns whether it did.
+    fn restore_entry(socket_path: &Path, id: u64) -> bool {
+        let Ok(mut stream) = create_stream(socket_path) else {
+            return false;
+        };
+        let cmd = CmdIPC::Restore(id);
+        if send_payload(&mut stream, Payload::Request(IPCRequest { cmd })).is_err() {
+            return false;
+        }
+
+        match read_payload(&mut stream) {
+            Ok(Payload::Response(ipc_resp)) => match ipc_resp.result {
+                IPCResult::Ok => true,
+                IPCResult::Err(err) => {
+                    eprintln!("Could not restore item: {}", err.message);
+                    false
                 }
+            },
+            _ => false,
+        }
+    }
+
+    // The full entry, once it's been picked. Secrets only come with their text if `reveal` is set.
+    fn fetch_entry(socket_path: &Path, id: u64, reveal: bool) -> Option<HistoryEntry> {
+        let mut stream = create_stream(socket_path).ok()?;
+        let cmd = match reveal {
+            true => CmdIPC::Reveal(id),
+            false => CmdIPC::Fetch(id),
+        };
+        send_payload(&mut stream, Payload::Request(IPCRequest { cmd })).ok()?;
+
+        match read_payload(&mut stream) {
+            Ok(Payload::Response(ipc_resp)) => ipc_resp.entry.map(|entry| *entry),
+            _ => None,
+        }
+    }
+
+    // Tells `tx` whenever entries expired, or may have, until the GUI goes away
+    fn watch_history(socket_path: &Path, tx: Sender<()>) {
+        let Ok(mut stream) = create_stream(socket_path) else {
+            return;
+        };
+        if send_payload(
+            &mut stream,
+            Payload::Request(IPCRequest {
+                cmd: CmdIPC::Subscribe,
+            }),
+        )
+        .is_err()
+        {
+            return;
+        }
+
+        // Ends when the daemon goes away, or the GUI does
+        while let Ok(payload) = read_payload(&mut stream) {
+            // Missed events may have been expiries too
+            let expired = matches!(
+                payload,
+                Payload::Event(HistoryEvent::Expired(_)) | Payload::Lagged(_)
+            );
+            if expired && tx.send(()).is_err() {
+                break;
             }
-            Err(_) => new_clipboard,
         }
     }
 
-    pub fn send_command(cmd: CmdIPC) -> Option<ClipboardHistory> {
-        match create_default_stream() {
+    pub fn send_command(socket_path: &Path, cmd: CmdIPC) -> Option<ClipboardHistory> {
+        match create_stream(socket_path) {
             Ok(mut stream) => {
-                send_payload(&mut stream, Payload::Request(IPCRequest { cmd }));
+                let _ = send_payload(&mut stream, Payload::Request(IPCRequest { cmd }));
 
                 let received_payload = read_payload(&mut stream);
-                if let Payload::Response(ipc_resp) = received_payload {
-                    return ipc_resp.history_snapshot;
+                if let Ok(Payload::Response(ipc_resp)) = received_payload {
+                    return ipc_resp.history_snapshot.map(|snapshot| *snapshot);
                 }
                 None
             }
@@ -254,62 +356,81 @@ impl Gui {
         items_box.append(&empty_box);
     }
 
+    fn no_matches_state(items_box: &gtk::Box) {
+        let empty_box = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        empty_box.set_valign(gtk::Align::Center);
+        empty_box.set_vexpand(true);
+        empty_box.set_margin_top(-10);
+
+        let empty_title = gtk::Label::new(Some("No matches"));
+        empty_title.add_css_class("empty-title");
+
+        let empty_subtitle = gtk::Label::new(Some("Try another search or filter"));
+        empty_subtitle.add_css_class("empty-subtitle");
+
+        empty_box.append(&empty_title);
+        empty_box.append(&empty_subtitle);
+        items_box.append(&empty_box);
+    }
+
+    // Icon and name of one copied file
+    fn construct_file_row(path: &Path) -> gtk::Box {
+        let file_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+
+        let icon = if path.is_dir() {
+            gtk::Image::from_icon_name("folder-symbolic")
+        } else {
+            let (content_type, _) = gtk::gio::content_type_guess(Some(path), &[]);
+            gtk::Image::from_gicon(&gtk::gio::content_type_get_symbolic_icon(&content_type))
+        };
+        icon.add_css_class("file-icon");
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let name_label = gtk::Label::new(Some(&name));
+        name_label.add_css_class("content-label");
+        name_label.set_xalign(0.0);
+        name_label.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+        name_label.set_max_width_chars(40);
+        name_label.set_tooltip_text(Some(&path.display().to_string()));
+
+        file_box.append(&icon);
+        file_box.append(&name_label);
+        file_box
+    }
+
     fn construct_image(
-        width: usize,
-        height: usize,
-        bytes: Vec<u8>,
-        cache: &Rc<std::cell::RefCell<HashMap<Vec<u8>, Texture>>>,
+        hash: u64,
+        thumbnail: &[u8],
+        cache: &Rc<std::cell::RefCell<HashMap<u64, Texture>>>,
+        size: i32,
     ) -> Option<gtk::Picture> {
-        const IMAGE_PREVIEW_TEXTURE_MAX_SIZE: usize = 200;
-        const IMAGE_PREVIEW_DISPLAY_SIZE: i32 = 50;
-
         // 1. Check cache first
-        if let Some(texture) = cache.borrow().get(&bytes) {
+        if let Some(texture) = cache.borrow().get(&hash) {
             let picture = gtk::Picture::for_paintable(texture);
             picture.set_can_shrink(true);
             picture.set_keep_aspect_ratio(true);
-            picture.set_size_request(IMAGE_PREVIEW_DISPLAY_SIZE, IMAGE_PREVIEW_DISPLAY_SIZE);
+            picture.set_size_request(size, size);
             picture.set_halign(gtk::Align::Start);
             picture.add_css_class("image-preview");
             return Some(picture);
         }
 
-        // 2. If not in cache, create it
-        let stride = width.checked_mul(4)?;
-        let expected_len = stride.checked_mul(height)?;
-
-        let bytes_owned = gtk::glib::Bytes::from_owned(bytes[..expected_len].to_vec());
-        let mut pixbuf = Pixbuf::from_bytes(
-            &bytes_owned,
-            gdk_pixbuf::Colorspace::Rgb,
-            true,
-            8,
-            width as i32,
-            height as i32,
-            stride as i32,
-        );
-
-        let max_dim = width.max(height) as f32;
-        if max_dim > IMAGE_PREVIEW_TEXTURE_MAX_SIZE as f32 {
-            let scale = IMAGE_PREVIEW_TEXTURE_MAX_SIZE as f32 / max_dim;
-            let target_width = (width as f32 * scale).round().max(1.0) as i32;
-            let target_height = (height as f32 * scale).round().max(1.0) as i32;
-            if let Some(resized) =
-                pixbuf.scale_simple(target_width, target_height, InterpType::Hyper)
-            {
-                pixbuf = resized;
-            }
+        // 2. If not in cache, create it from the thumbnail the daemon rendered
+        if thumbnail.is_empty() {
+            return None;
         }
-
-        let texture = gtk::gdk::Texture::for_pixbuf(&pixbuf);
+        let texture = Texture::from_bytes(&gtk::glib::Bytes::from(thumbnail)).ok()?;
 
         // 3. Add the new texture to the cache
-        cache.borrow_mut().insert(bytes, texture.clone()); // <-- Store it
+        cache.borrow_mut().insert(hash, texture.clone()); // <-- Store it
 
         let picture = gtk::Picture::for_paintable(&texture);
         picture.set_can_shrink(true);
         picture.set_keep_aspect_ratio(true);
-        picture.set_size_request(IMAGE_PREVIEW_DISPLAY_SIZE, IMAGE_PREVIEW_DISPLAY_SIZE);
+        picture.set_size_request(size, size);
         picture.set_halign(gtk::Align::Start);
         picture.add_css_class("image-preview");
         Some(picture)
@@ -345,6 +466,7 @@ impl Gui {
         let emoji_flow_box = self.emoji_flow_box.clone();
         let window = self.window.clone();
         let tx = self.main_thread_tx.clone();
+        let socket_path = self.socket_path.clone();
 
         // 4. Start the async loader
         gtk::glib::idle_add_local(move || {
@@ -359,6 +481,7 @@ impl Gui {
 
                     let window_clone = window.clone();
                     let tx_clone = tx.clone();
+                    let socket_path = socket_path.clone();
                     let emoji_str = emoji.clone(); // Clone for the closure
 
                     emoji_entry.connect_clicked(move |_| {
@@ -366,7 +489,11 @@ impl Gui {
                             let emoji_str = emoji_str.clone();
                             let _ = clipboard.set_text(&emoji_str);
 
-                            Self::schedule_emoji_cleanup(tx_clone.clone(), emoji_str.clone());
+                            Self::schedule_emoji_cleanup(
+                                tx_clone.clone(),
+                                socket_path.clone(),
+                                emoji_str.clone(),
+                            );
                             Self::signal_auto_paste(tx_clone.clone());
 
                             // manually close window, but don't quit program
@@ -395,9 +522,51 @@ impl Gui {
         });
     }
 
-    fn render_clipboard_items(&self) {
-        let history = Self::fetch_history();
-        let items = history.get_items();
+    /// The search text and the kind and origin picked in the filter dropdowns.
+    fn clipboard_query(&self) -> HistoryQuery {
+        let origin = match self.origin_filter.selected() {
+            1 => Some(Selection::Clipboard),
+            2 => Some(Selection::Primary),
+            _ => None,
+        };
+        let kind = match self.kind_filter.selected() {
+            1 => Some(KindFilter::Text),
+            2 => Some(KindFilter::Formatted),
+            3 => Some(KindFilter::Image),
+            4 => Some(KindFilter::Files),
+            _ => None,
+        };
+
+        HistoryQuery {
+            text: self.search_entry.text().trim().to_string(),
+            kind,
+            origin,
+            limit: Some(self.config.shown_items),
+            ..HistoryQuery::default()
+        }
+    }
+
+    /// Pango markup for `text` with the `highlights` (byte ranges) emphasized.
+    fn highlight_markup(text: &str, highlights: &[Range<usize>]) -> String {
+        let mut markup = String::new();
+        let mut last = 0;
+        for range in highlights {
+            markup += &gtk::glib::markup_escape_text(&text[last..range.start]);
+            markup += "<span weight=\"bold\" foreground=\"#8ab4ff\">";
+            markup += &gtk::glib::markup_escape_text(&text[range.clone()]);
+            markup += "</span>";
+            last = range.end;
+        }
+        markup += &gtk::glib::markup_escape_text(&text[last..]);
+        markup
+    }
+
+    fn render_clipboard_items(self: &Rc<Self>) {
+        // Searching and filtering happen in the daemon
+        let query = self.clipboard_query();
+        let summary = Self::fetch_summary(&self.socket_path, CmdIPC::Query(query.clone()));
+        let pinned = &summary.pinned;
+        let items = &summary.items;
 
         // Clear all items
         // much easier to just clear and update
@@ -405,112 +574,235 @@ impl Gui {
         Self::clear_items_box(&self.items_box);
 
         // Check if it's empty
-        if items.is_empty() {
-            Self::clipboard_empty_state(&self.items_box);
+        if items.is_empty() && pinned.is_empty() {
+            let filtered = !query.text.is_empty() || query.kind.is_some() || query.origin.is_some();
+            if !filtered {
+                Self::clipboard_empty_state(&self.items_box);
+            } else {
+                Self::no_matches_state(&self.items_box);
+            }
             return;
         }
 
-        for item in items.iter() {
-            let revealer = gtk::Revealer::new();
-            revealer.set_transition_type(gtk::RevealerTransitionType::SlideUp);
-            revealer.set_transition_duration(220);
-            revealer.set_reveal_child(true);
+        // Pinned items get their own section on top.
+        // It's a plain Box, so "Clear All" (which only looks at Revealers) leaves it alone.
+        if !pinned.is_empty() {
+            let pinned_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+            pinned_box.add_css_class("pinned-box");
 
-            let item_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
-            item_box.add_css_class("clipboard-item");
+            let pinned_title = gtk::Label::new(Some("Pinned"));
+            pinned_title.add_css_class("section-title");
+            pinned_title.set_xalign(0.0);
+            pinned_box.append(&pinned_title);
 
-            let content_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
-            content_box.set_hexpand(true);
+            for entry in pinned {
+                pinned_box.append(&self.clipboard_row(entry, true));
+            }
+            self.items_box.append(&pinned_box);
+        }
 
-            match item {
-                ClipboardItem::Text(text) => {
-                    let preview = if text.len() > 60 {
-                        format!("{}...", &text[..60])
-                    } else {
-                        text.clone()
-                    };
+        for entry in items {
+            self.items_box.append(&self.clipboard_row(entry, false));
+        }
+
+        // In a Revealer, so "Clear All" takes it along with the rows
+        if summary.total > items.len() {
+            let more_label = gtk::Label::new(Some(&format!(
+                "Showing {} of {}. Search to find the rest.",
+                items.len(),
+                summary.total
+            )));
+            more_label.add_css_class("meta-label");
+
+            let more_revealer = gtk::Revealer::new();
+            more_revealer.set_transition_type(gtk::RevealerTransitionType::SlideUp);
+            more_revealer.set_reveal_child(true);
+            more_revealer.set_child(Some(&more_label));
+            self.items_box.append(&more_revealer);
+        }
+    }
 
+    fn clipboard_row(self: &Rc<Self>, entry: &EntrySummary, pinned: bool) -> gtk::Revealer {
+        let revealer = gtk::Revealer::new();
+        revealer.set_transition_type(gtk::RevealerTransitionType::SlideUp);
+        revealer.set_transition_duration(220);
+        revealer.set_reveal_child(true);
+
+        let item_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        item_box.add_css_class("clipboard-item");
+
+        let content_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        content_box.set_hexpand(true);
+
+        // Label of a secret, whose text is only fetched when it's clicked
+        let mut masked_label = None;
+
+        match &entry.kind {
+            // Formatted text is previewed through its plain text
+            ItemKind::Text | ItemKind::Html | ItemKind::Rtf => {
+                let content_label = gtk::Label::new(None);
+                if entry.masked {
+                    content_label.set_text(Self::MASKED_LABEL);
+                    content_label.add_css_class("masked-label");
+                    masked_label = Some(content_label.clone());
+                } else {
+                    content_label
+                        .set_markup(&Self::highlight_markup(&entry.preview, &entry.highlights));
+                }
+                content_label.set_valign(gtk::Align::Center);
+                content_label.add_css_class("content-label");
+                content_label.set_xalign(0.0);
+                content_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+                content_label.set_max_width_chars(40);
+
+                content_box.append(&content_label);
+            }
+            ItemKind::Image {
+                width,
+                height,
+                hash,
+            } => {
+                // Replace with image preview
+                if let Some(picture) = Self::construct_image(
+                    *hash,
+                    &entry.thumbnail,
+                    &self.image_cache,
+                    self.config.image_preview_size,
+                ) {
+                    content_box.append(&picture);
+                } else {
+                    let preview = format!("Image: {} x {}", width, height);
                     let content_label = gtk::Label::new(Some(&preview));
                     content_label.set_valign(gtk::Align::Center);
                     content_label.add_css_class("content-label");
                     content_label.set_xalign(0.0);
                     content_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
                     content_label.set_max_width_chars(40);
-
                     content_box.append(&content_label);
                 }
-                ClipboardItem::Image {
-                    width,
-                    height,
-                    bytes,
-                } => {
-                    // Replace with image preview
-                    if let Some(picture) =
-                        Self::construct_image(*width, *height, bytes.clone(), &self.image_cache)
-                    {
-                        content_box.append(&picture);
-                    } else {
-                        let preview = format!("Image: {width} x {height}");
-                        let content_label = gtk::Label::new(Some(&preview));
-                        content_label.set_valign(gtk::Align::Center);
-                        content_label.add_css_class("content-label");
-                        content_label.set_xalign(0.0);
-                        content_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
-                        content_label.set_max_width_chars(40);
-                        content_box.append(&content_label);
-                    }
+            }
+            ItemKind::Files { shown, count } => {
+                for path in shown {
+                    content_box.append(&Self::construct_file_row(path));
+                }
+                if *count > shown.len() {
+                    let more_label =
+                        gtk::Label::new(Some(&format!("+{} more", count - shown.len())));
+                    more_label.add_css_class("meta-label");
+                    more_label.set_xalign(0.0);
+                    content_box.append(&more_label);
                 }
             }
+        }
 
-            // Make each item clickable
-            let gesture = gtk::GestureClick::new();
-            let item_clone = item.clone();
-            let window_clone = self.window.clone();
-            let tx = self.main_thread_tx.clone();
+        // When it was last used, and how often
+        let mut meta = entry.age_label(SystemTime::now());
+        if entry.promote_count > 0 {
+            meta += &format!(" · used {}×", entry.promote_count + 1);
+        }
+        if entry.origin == Selection::Primary {
+            meta += " · selection";
+        }
+        match entry.kind {
+            ItemKind::Html => meta += " · HTML",
+            ItemKind::Rtf => meta += " · RTF",
+            _ => {}
+        }
+        let meta_label = gtk::Label::new(Some(&meta));
+        meta_label.add_css_class("meta-label");
+        meta_label.set_xalign(0.0);
+        content_box.append(&meta_label);
+
+        // Make each item clickable
+        let gesture = gtk::GestureClick::new();
+        let kind = entry.kind.clone();
+        let entry_id = entry.id;
+        let window_clone = self.window.clone();
+        let tx = self.main_thread_tx.clone();
+        let socket_path = self.socket_path.clone();
+        let revealed = Cell::new(false);
 
-            gesture.connect_released(move |_, _, _, _| {
-                if let ClipboardItem::Text(text) = &item_clone
-                    && let Ok(mut clipboard) = Self::get_clipboard()
-                    && !text.trim().is_empty()
+        gesture.connect_released(move |_, _, _, _| {
+            // The first click on a secret only shows it
+            if let Some(label) = &masked_label
+                && !revealed.replace(true)
+            {
+                if let Some(entry) = Self::fetch_entry(&socket_path, entry_id, true)
+                    && let Some(text) = entry.item.plain_text()
                 {
-                    // Update system clipboard
-                    // This says I'm dropping the clipboard too fast (5ms)
-                    // eh... should be just fine.
-                    let _ = clipboard.set_text(text);
-
-                    // Signal for auto paste and close the window
-                    Self::signal_auto_paste(tx.clone());
-                    Self::close_window(window_clone.clone(), tx.clone());
-                    return;
+                    label.set_text(&text.chars().take(PREVIEW_CHARS).collect::<String>());
+                    label.remove_css_class("masked-label");
                 }
+                return;
+            }
 
-                if let ClipboardItem::Image {
-                    width,
-                    height,
-                    bytes,
-                } = &item_clone
-                    && let Ok(mut clipboard) = Self::get_clipboard()
-                    && !bytes.is_empty()
-                {
-                    // Same 5ms drop here...
-                    let _ = clipboard.set_image(ImageData {
-                        width: *width,
-                        height: *height,
-                        bytes: Cow::from(bytes),
-                    });
+            // Images, formatted items and files are restored by the daemon, which keeps
+            // serving every flavour after this window is gone. It also saves us fetching
+            // and decoding the full image here. Wait for it before pasting.
+            // If it can't, e.g. RTF without X11, the plain text below is pasted instead.
+            let restorable = match &kind {
+                ItemKind::Image { width, height, .. } => *width > 0 && *height > 0,
+                ItemKind::Html | ItemKind::Rtf | ItemKind::Files { .. } => true,
+                ItemKind::Text => false,
+            };
+            if restorable && Self::restore_entry(&socket_path, entry_id) {
+                // Signal for auto paste and close the window
+                Self::signal_auto_paste(tx.clone());
+                Self::close_window(window_clone.clone(), tx.clone());
+                return;
+            }
 
-                    // Signal for auto paste and close the window
-                    Self::signal_auto_paste(tx.clone());
-                    Self::close_window(window_clone.clone(), tx.clone());
-                    return;
-                }
+            // The summary only has a preview, get the whole text. A secret was revealed by now.
+            if let Some(entry) = Self::fetch_entry(&socket_path, entry_id, masked_label.is_some())
+                && let Some(text) = entry.item.plain_text()
+                && let Ok(mut clipboard) = Self::get_clipboard()
+                && !text.trim().is_empty()
+            {
+                // Update system clipboard
+                // This says I'm dropping the clipboard too fast (5ms)
+                // eh... should be just fine.
+                let _ = clipboard.set_text(text);
 
-                // Close the window
+                // Signal for auto paste and close the window
+                Self::signal_auto_paste(tx.clone());
                 Self::close_window(window_clone.clone(), tx.clone());
-            });
+                return;
+            }
 
-            item_box.add_controller(gesture);
+            // Close the window
+            Self::close_window(window_clone.clone(), tx.clone());
+        });
+
+        item_box.add_controller(gesture);
+        item_box.append(&content_box);
+
+        // Pin / Unpin button for each item
+        let pin_btn = gtk::Button::new();
+        pin_btn.set_icon_name("view-pin-symbolic");
+        pin_btn.add_css_class("pin-btn");
+        pin_btn.set_valign(gtk::Align::Start);
+        if pinned {
+            pin_btn.add_css_class("pinned");
+            pin_btn.set_tooltip_text(Some("Unpin"));
+        } else {
+            pin_btn.set_tooltip_text(Some("Pin"));
+        }
+
+        // Pinning moves the item between sections, so just re-render
+        let gui = self.clone();
+        pin_btn.connect_clicked(move |_| {
+            let cmd = if pinned {
+                CmdIPC::Unpin(entry_id)
+            } else {
+                CmdIPC::Pin(entry_id)
+            };
+            Self::send_command(&gui.socket_path, cmd);
+            gui.render_clipboard_items();
+        });
+        item_box.append(&pin_btn);
 
+        // Pinned items have to be unpinned before they can be deleted
+        if !pinned {
             // Delete button for each item
             let delete_btn = gtk::Button::new();
             delete_btn.set_icon_name("user-trash-symbolic");
@@ -520,23 +812,14 @@ impl Gui {
             // Make the delete button functional
             let items_box = self.items_box.clone();
             let item_revealer = revealer.clone();
+            let socket_path = self.socket_path.clone();
 
             delete_btn.connect_clicked(move |_| {
-                let current_index = (0..items_box.observe_children().n_items())
-                    .find(|&i| {
-                        items_box
-                            .observe_children()
-                            .item(i)
-                            .and_then(|obj| obj.downcast::<gtk::Revealer>().ok())
-                            .as_ref()
-                            == Some(&item_revealer)
-                    })
-                    .unwrap_or(0) as usize;
-
                 item_revealer.set_reveal_child(false);
 
                 let items_box_for_removal = items_box.clone();
                 let item_revealer_for_removal = item_revealer.clone();
+                let socket_path = socket_path.clone();
 
                 gtk::glib::timeout_add_local_once(Duration::from_millis(220), move || {
                     items_box_for_removal.remove(&item_revealer_for_removal);
@@ -546,27 +829,35 @@ impl Gui {
                     }
 
                     thread::spawn(move || {
-                        Self::send_command(CmdIPC::Delete(current_index));
+                        Self::send_command(&socket_path, CmdIPC::Delete(entry_id));
                     });
                 });
             });
-
-            item_box.append(&content_box);
             item_box.append(&delete_btn);
-
-            revealer.set_child(Some(&item_box));
-            self.items_box.append(&revealer);
         }
+
+        revealer.set_child(Some(&item_box));
+        revealer
     }
 
     /// Handles logic for when the active tab (Stack page) changes.
-    fn handle_tab_switch(&self, stack: &gtk::Stack) {
+    fn handle_tab_switch(self: &Rc<Self>, stack: &gtk::Stack) {
         if let Some(name) = stack.visible_child_name() {
             let is_clipboard = name == "clipboard";
 
             // Toggle visibility of page-specific controls
             self.clear_all_btn.set_visible(is_clipboard);
-            self.search_entry.set_visible(!is_clipboard);
+            self.origin_filter.set_visible(is_clipboard);
+            self.kind_filter.set_visible(is_clipboard);
+            self.search_entry
+                .set_placeholder_text(Some(if is_clipboard {
+                    "Search clipboard..."
+                } else {
+                    "Search emojis..."
+                }));
+
+            // Searches don't carry over between tabs
+            self.search_entry.set_text("");
 
             // Call the appropriate render function
             if is_clipboard {
@@ -582,6 +873,7 @@ impl Gui {
     fn build(self: Rc<Self>, initial_tab: InitialTab) {
         // -------------------- Connect Events ------------------------
         let all_items = self.items_box.clone();
+        let socket_path = self.socket_path.clone();
 
         // Clear all btn connector
         self.clear_all_btn.connect_clicked(move |_| {
@@ -597,11 +889,11 @@ impl Gui {
                 }
             }
 
+            // Nothing to animate. Either it's already empty or only pinned items are left.
             if revealers.is_empty() {
-                Self::clear_items_box(&all_items);
-                Self::clipboard_empty_state(&all_items);
-                thread::spawn(|| {
-                    Self::send_command(CmdIPC::Clear);
+                let socket_path = socket_path.clone();
+                thread::spawn(move || {
+                    Self::send_command(&socket_path, CmdIPC::Clear);
                 });
                 return;
             }
@@ -620,22 +912,36 @@ impl Gui {
             let items_box_after = all_items.clone();
             let spacing_restore = original_spacing;
             let total_delay = 240 + (revealers.len() as u64 * 16);
+            let socket_path = socket_path.clone();
 
             gtk::glib::timeout_add_local_once(Duration::from_millis(total_delay), move || {
-                while let Some(child) = items_box_after.first_child() {
-                    items_box_after.remove(&child);
+                // Only the unpinned rows go, the pinned section stays
+                for revealer in &revealers {
+                    items_box_after.remove(revealer);
                 }
 
                 items_box_after.set_spacing(spacing_restore);
 
-                thread::spawn(|| {
-                    Self::send_command(CmdIPC::Clear);
+                thread::spawn(move || {
+                    Self::send_command(&socket_path, CmdIPC::Clear);
                 });
 
-                Self::clipboard_empty_state(&items_box_after);
+                if items_box_after.first_child().is_none() {
+                    Self::clipboard_empty_state(&items_box_after);
+                }
             });
         });
 
+        // Origin and kind filters, re-list with the new selection
+        let gui_clone_filter = self.clone();
+        self.origin_filter.connect_selected_notify(move |_| {
+            gui_clone_filter.render_clipboard_items();
+        });
+        let gui_clone_filter = self.clone();
+        self.kind_filter.connect_selected_notify(move |_| {
+            gui_clone_filter.render_clipboard_items();
+        });
+
         // Tab Switching
         // `self` is Rc<GUI>, so `self.clone()` clones the Rc
         let gui_clone_stack = self.clone();
@@ -674,12 +980,30 @@ impl Gui {
             }
         });
 
-        // Emoji Search
+        // Re-list when the daemon removes expired entries
+        let (expired_tx, expired_rx) = std::sync::mpsc::channel::<()>();
+        let socket_path = self.socket_path.clone();
+        thread::spawn(move || Self::watch_history(&socket_path, expired_tx));
+        let gui_clone_watch = self.clone();
+        gtk::glib::timeout_add_local(Duration::from_millis(500), move || {
+            if expired_rx.try_iter().count() > 0
+                && gui_clone_watch.stack.visible_child_name().as_deref() == Some("clipboard")
+            {
+                gui_clone_watch.render_clipboard_items();
+            }
+            gtk::glib::ControlFlow::Continue
+        });
+
+        // Clipboard and Emoji Search
         // Clone the Rc for the search entry closure
         let gui_clone_search = self.clone();
         self.search_entry.connect_changed(move |_| {
-            // Re-render the emoji list every time the text changes
-            gui_clone_search.render_emojis();
+            // Re-render the visible list every time the text changes
+            if gui_clone_search.stack.visible_child_name().as_deref() == Some("clipboard") {
+                gui_clone_search.render_clipboard_items();
+            } else {
+                gui_clone_search.render_emojis();
+            }
         });
         // -----------------------------------------------------------
 
@@ -688,22 +1012,33 @@ impl Gui {
     }
 }
 
-fn build_ui(app: &Application, tx: Sender<MainThreadMsg>, initial_tab: InitialTab) {
+fn build_ui(
+    app: &Application,
+    tx: Sender<MainThreadMsg>,
+    initial_tab: InitialTab,
+    config: GuiConfig,
+    socket_path: PathBuf,
+) {
     // Create the Gui. This struct now owns all the widgets.
     // The `Rc` will keep `gui` alive as long as the closures
     // (event handlers) are alive.
-    let gui = Gui::new(app, tx);
+    let gui = Gui::new(app, tx, config, socket_path);
     gui.build(initial_tab);
 }
 
-pub fn run_gui(tx: Sender<MainThreadMsg>, tab: InitialTab) {
+pub fn run_gui(
+    tx: Sender<MainThreadMsg>,
+    tab: InitialTab,
+    config: GuiConfig,
+    socket_path: PathBuf,
+) {
     gtk::glib::set_application_name("Super V");
     gtk::glib::set_prgname(Some("super_v"));
 
     let app = Application::builder().application_id(Gui::APP_ID).build();
 
     app.connect_activate(move |app| {
-        build_ui(app, tx.clone(), tab);
+        build_ui(app, tx.clone(), tab, config.clone(), socket_path.clone());
     });
     app.run_with_args(&Vec::<String>::new());
 }
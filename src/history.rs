@@ -1,21 +1,712 @@
 // Standard Crates
 #[allow(unused)]
-use std::{collections::VecDeque, fmt};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    ops::Range,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 // External Crates
-use crate::common::{ClipboardError, ClipboardItem};
+use crate::{
+    common::{ClipboardError, ClipboardItem, Selection},
+    search::{self, QueryTerms, SearchIndex},
+    sensitive::SecretAction,
+};
 use serde::{Deserialize, Serialize};
 
+// ----------------------- History Entry -----------------------------
+/// A clipboard item together with its bookkeeping.
+///
+/// Entries compare equal to a `ClipboardItem` when their content matches,
+/// so lookups by content don't have to unwrap the record.
+#[allow(unused)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HistoryEntry {
+    /// Unique within a history and never reused.
+    pub id: u64,
+
+    /// The copied content.
+    pub item: ClipboardItem,
+
+    /// When the item was first copied.
+    pub first_copied: SystemTime,
+
+    /// When the item was last copied again or promoted.
+    pub last_used: SystemTime,
+
+    /// How many times the item was reused after it was first copied.
+    pub promote_count: u32,
+
+    /// Size of the content in bytes.
+    pub byte_size: usize,
+
+    /// Application the item was copied from, if the platform can tell.
+    pub source: Option<String>,
+
+    /// Where the item was recorded from. Entries saved before origins existed are from the clipboard.
+    #[serde(default)]
+    pub origin: Selection,
+
+    /// Whether the item was flagged as a secret. Masked entries are listed without their
+    /// text and can't be searched.
+    #[serde(default)]
+    pub masked: bool,
+
+    /// How long the entry is kept after it was last used, on top of the `ExpiryPolicy`.
+    #[serde(default)]
+    pub expire_after: Option<Duration>,
+}
+
+impl HistoryEntry {
+    fn new(id: u64, item: ClipboardItem, origin: Selection, now: SystemTime) -> Self {
+        Self {
+            id,
+            byte_size: item.byte_size(),
+            item,
+            first_copied: now,
+            last_used: now,
+            promote_count: 0,
+            source: None,
+            origin,
+            masked: false,
+            expire_after: None,
+        }
+    }
+
+    /// Records another use of the entry.
+    fn touch(&mut self, now: SystemTime) {
+        self.last_used = now;
+        self.promote_count = self.promote_count.saturating_add(1);
+    }
+
+    /// Records that the item was seen again through `origin`.
+    ///
+    /// An explicit copy outranks a selection, so a PRIMARY entry that is
+    /// copied to the clipboard becomes a clipboard entry, never the reverse.
+    fn merge_origin(&mut self, origin: Selection) {
+        if origin == Selection::Clipboard {
+            self.origin = Selection::Clipboard;
+        }
+    }
+
+    /// Human readable time since the item was last used, e.g. "2 min ago".
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time to measure against
+    pub fn age_label(&self, now: SystemTime) -> String {
+        age_label(self.last_used, now)
+    }
+
+    /// A lightweight copy of the entry for listing, see `EntrySummary`.
+    pub fn summary(&self) -> EntrySummary {
+        self.summary_matching(&[])
+    }
+
+    /// A copy of the entry that is safe to hand out or print.
+    ///
+    /// Masked entries have their text replaced by `MASKED_PREVIEW`, keeping the kind of item.
+    /// Everything else is copied as is.
+    pub fn without_secret(&self) -> HistoryEntry {
+        let mut entry = self.clone();
+        if !self.masked {
+            return entry;
+        }
+
+        let hidden = || MASKED_PREVIEW.to_string();
+        entry.item = match &self.item {
+            ClipboardItem::Text(_) => ClipboardItem::Text(hidden()),
+            ClipboardItem::Html { .. } => ClipboardItem::Html {
+                html: hidden(),
+                alt_text: hidden(),
+            },
+            ClipboardItem::Rtf { .. } => ClipboardItem::Rtf {
+                rtf: hidden(),
+                alt_text: hidden(),
+            },
+            // Only text is ever masked
+            item @ (ClipboardItem::Image(_) | ClipboardItem::Files(_)) => item.clone(),
+        };
+        entry
+    }
+
+    /// Whether the entry passes the kind, origin and date filters of `query`.
+    fn admitted_by(&self, query: &HistoryQuery) -> bool {
+        query.kind.is_none_or(|kind| kind.matches(&self.item))
+            && query.origin.is_none_or(|origin| origin == self.origin)
+            && query.since.is_none_or(|since| self.last_used >= since)
+            && query.until.is_none_or(|until| self.last_used < until)
+    }
+
+    /// Text the entry is found by: its plain text, or the paths of copied files.
+    /// Masked entries have none.
+    ///
+    /// Files are listed by name, not path, so matches in them are never highlighted.
+    fn search_text(&self) -> Cow<'_, str> {
+        if self.masked {
+            return Cow::Borrowed("");
+        }
+        match &self.item {
+            ClipboardItem::Files(paths) => Cow::Owned(
+                paths
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            item => Cow::Borrowed(item.plain_text().unwrap_or_default()),
+        }
+    }
+
+    // Summary with the `matched` characters of the text highlighted
+    fn summary_matching(&self, matched: &[Range<usize>]) -> EntrySummary {
+        let (kind, thumbnail) = match &self.item {
+            ClipboardItem::Text(_) => (ItemKind::Text, Vec::new()),
+            ClipboardItem::Html { .. } => (ItemKind::Html, Vec::new()),
+            ClipboardItem::Rtf { .. } => (ItemKind::Rtf, Vec::new()),
+            ClipboardItem::Image(image) => (
+                ItemKind::Image {
+                    width: image.width,
+                    height: image.height,
+                    hash: image.hash,
+                },
+                image.thumbnail.clone(),
+            ),
+            ClipboardItem::Files(paths) => (
+                ItemKind::Files {
+                    shown: paths.iter().take(PREVIEW_FILES).cloned().collect(),
+                    count: paths.len(),
+                },
+                Vec::new(),
+            ),
+        };
+
+        let (preview, highlights) = match self.masked {
+            true => (MASKED_PREVIEW.to_string(), Vec::new()),
+            false => preview(&self.item, matched),
+        };
+        EntrySummary {
+            id: self.id,
+            kind,
+            preview,
+            highlights,
+            thumbnail,
+            last_used: self.last_used,
+            promote_count: self.promote_count,
+            byte_size: self.byte_size,
+            origin: self.origin,
+            masked: self.masked,
+        }
+    }
+}
+
+impl PartialEq<ClipboardItem> for HistoryEntry {
+    fn eq(&self, other: &ClipboardItem) -> bool {
+        &self.item == other
+    }
+}
+// -------------------------------------------------------------------
+
+// ---------------------- History Summary ----------------------------
+/// How many characters of text a summary keeps.
+pub const PREVIEW_CHARS: usize = 60;
+
+/// How many paths of copied files a summary keeps.
+pub const PREVIEW_FILES: usize = 4;
+
+// Characters shown before a match that would be past the preview
+const PREVIEW_CONTEXT: usize = 10;
+
+/// Shown instead of the text of masked entries, wherever they leave the daemon.
+pub const MASKED_PREVIEW: &str = "••••";
+
+/// What kind of item an `EntrySummary` stands for.
+///
+/// **Available**:
+/// * **Text**, **Html**, **Rtf** - Text, previewed through its plain text
+/// * **Image { width, height, hash }** - An image. `hash` identifies the content, e.g. for caching thumbnails.
+/// * **Files { shown, count }** - Copied files. Only the first `PREVIEW_FILES` paths are kept.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ItemKind {
+    Text,
+    Html,
+    Rtf,
+    Image {
+        width: usize,
+        height: usize,
+        hash: u64,
+    },
+    Files {
+        shown: Vec<PathBuf>,
+        count: usize,
+    },
+}
+
+/// A history entry without its content, small enough to list the whole history quickly.
+///
+/// The full item is fetched by `id` once it is actually needed.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct EntrySummary {
+    /// Id of the entry, see `HistoryEntry::id`.
+    pub id: u64,
+
+    pub kind: ItemKind,
+
+    /// Start of the (plain) text, at most `PREVIEW_CHARS` characters. Empty for images and
+    /// files, `MASKED_PREVIEW` for masked entries.
+    ///
+    /// In query results, it starts shortly before the first match if the match would be cut off.
+    pub preview: String,
+
+    /// Parts of `preview` that matched the query, as byte ranges.
+    pub highlights: Vec<Range<usize>>,
+
+    /// The image thumbnail as PNG. Empty for everything else.
+    pub thumbnail: Vec<u8>,
+
+    pub last_used: SystemTime,
+    pub promote_count: u32,
+    pub byte_size: usize,
+    pub origin: Selection,
+
+    /// Whether the entry is a secret, whose text has to be fetched to be shown.
+    #[serde(default)]
+    pub masked: bool,
+}
+
+impl EntrySummary {
+    /// Human readable time since the item was last used, see `HistoryEntry::age_label`.
+    pub fn age_label(&self, now: SystemTime) -> String {
+        age_label(self.last_used, now)
+    }
+}
+
+/// Summaries of the entries in a `ClipboardHistory`.
+///
+/// **Contains**:
+/// * **pinned** - Pinned entries
+/// * **items** - Unpinned entries, possibly cut short by `HistoryQuery::limit`
+/// * **total** - How many unpinned entries there are (or matched a query) in all
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct HistorySummary {
+    pub pinned: Vec<EntrySummary>,
+    pub items: Vec<EntrySummary>,
+    pub total: usize,
+}
+
+// Human readable time between `last_used` and `now`
+fn age_label(last_used: SystemTime, now: SystemTime) -> String {
+    // A clock that went backwards counts as "just now"
+    let age = now
+        .duration_since(last_used)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+
+    match age {
+        0..60 => "Just now".to_string(),
+        60..3_600 => format!("{} min ago", age / 60),
+        3_600..86_400 => format!("{} h ago", age / 3_600),
+        _ => format!("{} d ago", age / 86_400),
+    }
+}
+
+// Start of the item's text, or the part around the first match, cut at character boundaries.
+// Returns the preview and the `matched` characters as byte ranges into it.
+fn preview(item: &ClipboardItem, matched: &[Range<usize>]) -> (String, Vec<Range<usize>>) {
+    let Some(text) = item.plain_text() else {
+        return (String::new(), Vec::new());
+    };
+
+    // Move the preview up to the first match, if it would be cut off
+    let first = match matched.first() {
+        Some(range) if range.end > PREVIEW_CHARS => range.start.saturating_sub(PREVIEW_CONTEXT),
+        _ => 0,
+    };
+
+    let mut preview = String::new();
+    if first > 0 {
+        preview += "...";
+    }
+
+    // Where each shown character starts in the preview, and where the last one ends
+    let mut offsets = Vec::with_capacity(PREVIEW_CHARS + 1);
+    let mut chars = text.chars().skip(first);
+    for c in chars.by_ref().take(PREVIEW_CHARS) {
+        offsets.push(preview.len());
+        preview.push(c);
+    }
+    offsets.push(preview.len());
+    if chars.next().is_some() {
+        preview += "...";
+    }
+
+    let shown = offsets.len() - 1;
+    let highlights = matched
+        .iter()
+        .map(|range| {
+            let start = range.start.saturating_sub(first).min(shown);
+            let end = range.end.saturating_sub(first).min(shown);
+            offsets[start]..offsets[end]
+        })
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    (preview, highlights)
+}
+// -------------------------------------------------------------------
+
+// ----------------------- History Query -----------------------------
+/// Kinds of items a query can be limited to.
+///
+/// **Available**:
+/// * **Text** - Plain text
+/// * **Formatted** - HTML and RTF
+/// * **Image** - Images
+/// * **Files** - Copied files
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub enum KindFilter {
+    Text,
+    Formatted,
+    Image,
+    Files,
+}
+
+impl KindFilter {
+    /// Whether `item` is of this kind.
+    pub fn matches(self, item: &ClipboardItem) -> bool {
+        matches!(
+            (self, item),
+            (Self::Text, ClipboardItem::Text(_))
+                | (
+                    Self::Formatted,
+                    ClipboardItem::Html { .. } | ClipboardItem::Rtf { .. }
+                )
+                | (Self::Image, ClipboardItem::Image(_))
+                | (Self::Files, ClipboardItem::Files(_))
+        )
+    }
+}
+
+/// # HistoryQuery
+/// Which entries `ClipboardHistory::query` returns.
+///
+/// Fields:
+/// - text: Searched for in the text of entries and the paths of copied files.
+///   Words match the start of words ("clip" finds "clipboard"), `"quoted words"` match as a phrase.
+///   Empty matches everything, images only match an empty text.
+/// - kind: Only entries of this kind. `None` for all.
+/// - origin: Only entries recorded from this selection. `None` for all.
+/// - since: Only entries last used at or after this time.
+/// - until: Only entries last used before this time.
+/// - limit: Return at most this many unpinned entries, the best ones. `None` for all.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub text: String,
+    pub kind: Option<KindFilter>,
+    pub origin: Option<Selection>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    pub limit: Option<usize>,
+}
+
+/// How many of the most recent entries a search looks through for parts of words and fuzzy
+/// matches, once the index found nothing. Those need the text of every entry they look at.
+pub const FALLBACK_ENTRIES: usize = 500;
+
+// The entries admitted by `query` that `scorer` scores, best first. Ties keep the history order.
+fn rank<'a>(
+    entries: impl Iterator<Item = &'a HistoryEntry>,
+    query: &HistoryQuery,
+    scorer: impl Fn(&HistoryEntry) -> Option<u32>,
+) -> Vec<&'a HistoryEntry> {
+    let mut found: Vec<_> = entries
+        .filter(|entry| entry.admitted_by(query))
+        .filter_map(|entry| scorer(entry).map(|score| (entry, score)))
+        .collect();
+
+    // Stable, so equal scores stay most recent first
+    found.sort_by(|(_, a), (_, b)| b.cmp(a));
+    found.into_iter().map(|(entry, _)| entry).collect()
+}
+// -------------------------------------------------------------------
+
+// ----------------------- History Limits ----------------------------
+/// # KindLimit
+/// How much of one kind of item the history keeps.
+///
+/// Fields:
+/// - max_items: At most this many entries of the kind. `None` for no limit.
+/// - max_bytes: At most this many bytes over all entries of the kind. `None` for no limit.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct KindLimit {
+    #[serde(default)]
+    pub max_items: Option<usize>,
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+impl KindLimit {
+    /// Whether `items` entries taking `bytes` bytes are too much.
+    pub fn exceeded_by(&self, items: usize, bytes: usize) -> bool {
+        self.max_items.is_some_and(|max| items > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+/// # HistoryLimits
+/// How much the history keeps. Only unpinned entries count.
+///
+/// Fields:
+/// - max_items: At most this many entries.
+/// - max_bytes: At most this many bytes over all entries. `None` for no limit.
+/// - kinds: Limits for single kinds, e.g. at most 10 images. They apply on top of the others.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct HistoryLimits {
+    pub max_items: usize,
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    #[serde(default)]
+    pub kinds: BTreeMap<KindFilter, KindLimit>,
+}
+
+impl HistoryLimits {
+    /// Limits only the number of entries.
+    pub fn new(max_items: usize) -> Self {
+        Self {
+            max_items,
+            max_bytes: None,
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    /// Whether `entry` fits the byte limits on its own. Entries that don't are never kept.
+    pub fn fits(&self, entry: &HistoryEntry) -> bool {
+        let fits_in = |max_bytes: Option<usize>| max_bytes.is_none_or(|max| entry.byte_size <= max);
+        fits_in(self.max_bytes)
+            && self
+                .kinds
+                .iter()
+                .filter(|(kind, _)| kind.matches(&entry.item))
+                .all(|(_, limit)| fits_in(limit.max_bytes))
+    }
+}
+
+/// # ExpiryPolicy
+/// How long entries are kept after they were last used.
+///
+/// Unlike the `HistoryLimits`, this applies to pinned entries too, so nothing is
+/// kept past it. An entry's own `expire_after` applies as well.
+///
+/// Fields:
+/// - max_age: Entries unused for longer are removed. `None` to keep them.
+/// - kinds: Ages for single kinds, e.g. images after an hour. The shortest age that applies wins.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct ExpiryPolicy {
+    #[serde(default)]
+    pub max_age: Option<Duration>,
+    #[serde(default)]
+    pub kinds: BTreeMap<KindFilter, Duration>,
+}
+
+impl ExpiryPolicy {
+    /// How long `item` may be kept. `None` if it never expires.
+    pub fn max_age_of(&self, item: &ClipboardItem) -> Option<Duration> {
+        self.kinds
+            .iter()
+            .filter(|(kind, _)| kind.matches(item))
+            .map(|(_, max_age)| *max_age)
+            .chain(self.max_age)
+            .min()
+    }
+
+    /// Whether `entry` is past its age, or its own `expire_after`, at `now`.
+    pub fn is_expired(&self, entry: &HistoryEntry, now: SystemTime) -> bool {
+        let max_age = self
+            .max_age_of(&entry.item)
+            .into_iter()
+            .chain(entry.expire_after)
+            .min();
+        max_age.is_some_and(|max_age| {
+            now.duration_since(entry.last_used)
+                .is_ok_and(|age| age > max_age)
+        })
+    }
+}
+// -------------------------------------------------------------------
+
+// ----------------------- History Events ----------------------------
+/// A change to a `ClipboardHistory`, as streamed to subscribers.
+///
+/// **Available**:
+/// * **Added(u64)** - A new entry is at the top.
+/// * **Promoted(u64)** - An existing entry moved to the top, e.g. because it was copied again.
+/// * **Deleted(Vec<u64>)** - Entries were removed, by a client or because they no longer fit the limits.
+/// * **Cleared(Vec<u64>)** - The unpinned entries were cleared.
+/// * **Pinned(u64)** / **Unpinned(u64)** - An entry was pinned or unpinned.
+/// * **Expired(Vec<u64>)** - Entries past the `ExpiryPolicy` were removed.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub enum HistoryEvent {
+    Added(u64),
+    Promoted(u64),
+    Deleted(Vec<u64>),
+    Cleared(Vec<u64>),
+    Pinned(u64),
+    Unpinned(u64),
+    Expired(Vec<u64>),
+}
+
+/// The ids of a `ClipboardHistory` in order, to tell what a change did.
+///
+/// Taken with `ClipboardHistory::ids` before and after a change, see `HistoryIds::events_to`.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct HistoryIds {
+    pub pinned: Vec<u64>,
+    pub items: Vec<u64>,
+}
+
+impl HistoryIds {
+    /// The events that turn this history into `after`.
+    ///
+    /// **Behavior**:
+    /// - New ids are `Added`, ids that moved between the pinned and unpinned entries `Pinned` or `Unpinned`.
+    /// - An unpinned entry that was already there and is now at the top is `Promoted`.
+    /// - Ids that are gone are `Deleted`, in one event after the others.
+    ///   Callers that know better, e.g. clearing or expiring, send their own event instead.
+    pub fn events_to(&self, after: &HistoryIds) -> Vec<HistoryEvent> {
+        let pinned_before: HashSet<u64> = self.pinned.iter().copied().collect();
+        let items_before: HashSet<u64> = self.items.iter().copied().collect();
+        let mut events = Vec::new();
+
+        for id in &after.pinned {
+            if items_before.contains(id) {
+                events.push(HistoryEvent::Pinned(*id));
+            } else if !pinned_before.contains(id) {
+                events.push(HistoryEvent::Added(*id));
+            }
+        }
+        for (pos, id) in after.items.iter().enumerate() {
+            if pinned_before.contains(id) {
+                events.push(HistoryEvent::Unpinned(*id));
+            } else if !items_before.contains(id) {
+                events.push(HistoryEvent::Added(*id));
+            } else if pos == 0 && self.items.first() != Some(id) {
+                events.push(HistoryEvent::Promoted(*id));
+            }
+        }
+
+        let remaining: HashSet<u64> = after.pinned.iter().chain(&after.items).copied().collect();
+        let deleted: Vec<u64> = self
+            .pinned
+            .iter()
+            .chain(&self.items)
+            .filter(|id| !remaining.contains(id))
+            .copied()
+            .collect();
+        if !deleted.is_empty() {
+            events.push(HistoryEvent::Deleted(deleted));
+        }
+        events
+    }
+}
+// -------------------------------------------------------------------
+
 // --------------------- Hist Implementation -------------------------
 /// A clipboard history manager that maintains a fixed-size queue of clipboard items.
 ///
 /// This structure keeps track of clipboard items in a VecDeque, automatically managing
 /// the history size and handling duplicate items by promoting them to the top.
+///
+/// Pinned items live in their own queue. They are never evicted or cleared and
+/// don't count toward the `HistoryLimits`.
+///
+/// Entries past the `ExpiryPolicy`, pinned or not, are removed by `purge_expired`.
+///
+/// The text of every entry is kept in a `SearchIndex`, which is rebuilt on load.
+/// Two histories are equal when their saved fields are.
 #[allow(unused)]
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "StoredHistory")]
 pub struct ClipboardHistory {
-    history: VecDeque<ClipboardItem>,
-    max_size: usize,
+    history: VecDeque<HistoryEntry>,
+    limits: HistoryLimits,
+    pinned: VecDeque<HistoryEntry>,
+
+    // Id handed to the next new entry
+    next_id: u64,
+
+    #[serde(default)]
+    expiry: ExpiryPolicy,
+
+    #[serde(skip)]
+    index: SearchIndex,
+
+    // Entries moved to the front of `history` or `pinned` get the next stamp, so both
+    // are sorted by descending stamp and an entry is found by its id without a scan
+    #[serde(skip)]
+    stamps: HashMap<u64, u64>,
+    #[serde(skip)]
+    next_stamp: u64,
+}
+
+impl PartialEq for ClipboardHistory {
+    fn eq(&self, other: &Self) -> bool {
+        self.history == other.history
+            && self.limits == other.limits
+            && self.pinned == other.pinned
+            && self.next_id == other.next_id
+            && self.expiry == other.expiry
+    }
+}
+
+// The saved fields of a ClipboardHistory, in the same order
+#[derive(Deserialize)]
+struct StoredHistory {
+    history: VecDeque<HistoryEntry>,
+    limits: StoredLimits,
+    pinned: VecDeque<HistoryEntry>,
+    next_id: u64,
+    #[serde(default)]
+    expiry: ExpiryPolicy,
+}
+
+// Histories saved before byte and kind limits only kept a maximum size
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredLimits {
+    MaxSize(usize),
+    Limits(HistoryLimits),
+}
+
+impl From<StoredLimits> for HistoryLimits {
+    fn from(stored: StoredLimits) -> Self {
+        match stored {
+            StoredLimits::MaxSize(max_size) => Self::new(max_size),
+            StoredLimits::Limits(limits) => limits,
+        }
+    }
+}
+
+impl From<StoredHistory> for ClipboardHistory {
+    fn from(stored: StoredHistory) -> Self {
+        let mut history = Self {
+            history: stored.history,
+            limits: stored.limits.into(),
+            pinned: stored.pinned,
+            next_id: stored.next_id,
+            expiry: stored.expiry,
+            index: SearchIndex::new(),
+            stamps: HashMap::new(),
+            next_stamp: 0,
+        };
+        for entry in history.pinned.iter().chain(history.history.iter()) {
+            history.index.insert(entry.id, &entry.search_text());
+        }
+        history.restamp();
+        history
+    }
 }
 
 #[allow(unused)]
@@ -26,36 +717,164 @@ impl ClipboardHistory {
     ///
     /// * `max_size` - The maximum number of items to keep in history
     pub fn new(max_size: usize) -> Self {
+        Self::with_limits(HistoryLimits::new(max_size))
+    }
+
+    /// Creates a new ClipboardHistory that keeps what `limits` allow.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - How many items and bytes to keep in history
+    pub fn with_limits(limits: HistoryLimits) -> Self {
         Self {
-            history: VecDeque::with_capacity(max_size),
-            max_size,
+            history: VecDeque::new(),
+            limits,
+            pinned: VecDeque::new(),
+            next_id: 1,
+            expiry: ExpiryPolicy::default(),
+            index: SearchIndex::new(),
+            stamps: HashMap::new(),
+            next_stamp: 0,
         }
     }
 
+    /// Builds a history from bare items, e.g. ones saved before entries had metadata.
+    ///
+    /// Every item gets a fresh id and the current time as its timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - Unpinned items, most recent first
+    /// * `pinned` - Pinned items, most recently pinned first
+    /// * `max_size` - The maximum number of unpinned items to keep
+    pub fn from_items(
+        items: impl IntoIterator<Item = ClipboardItem>,
+        pinned: impl IntoIterator<Item = ClipboardItem>,
+        max_size: usize,
+    ) -> Self {
+        let now = SystemTime::now();
+        let mut history = Self::new(max_size);
+
+        for item in pinned {
+            let entry = history.new_entry(item, Selection::Clipboard, now);
+            history.pinned.push_back(entry);
+        }
+        for item in items {
+            let entry = history.new_entry(item, Selection::Clipboard, now);
+            history.history.push_back(entry);
+        }
+
+        history.restamp();
+        history.evict();
+        history
+    }
+
     /// Adds a new clipboard item to the history.
     ///
     /// If the item already exists in history, it will be promoted to the front
-    /// instead of creating a duplicate. If the history exceeds max_size after
-    /// adding, the oldest item is removed.
+    /// instead of creating a duplicate. If the history exceeds its limits after
+    /// adding, the oldest items are removed. Items that are already pinned are left
+    /// where they are.
     ///
     /// # Arguments
     ///
     /// * `item` - The ClipboardItem to add to history
     pub fn add(&mut self, item: ClipboardItem) {
-        // Check for item duplicates
-        if let Some(pos) = self.history.iter().position(|i| i == &item) {
-            // It already exists. Promote it.
-            self.promote(pos);
+        self.add_from(item, Selection::Clipboard);
+    }
+
+    /// Adds an item recorded from the given selection.
+    ///
+    /// Works like `add`. A duplicate keeps one entry whose origin is the
+    /// clipboard if either copy came from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The ClipboardItem to add to history
+    /// * `origin` - The selection the item was read from
+    pub fn add_from(&mut self, item: ClipboardItem, origin: Selection) {
+        self.add_at(item, origin, SystemTime::now());
+    }
+
+    /// Adds an item recorded from the given selection at the time `now`.
+    ///
+    /// Works like `add_from`, for callers with their own clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The ClipboardItem to add to history
+    /// * `origin` - The selection the item was read from
+    /// * `now` - When the item was copied
+    pub fn add_at(&mut self, item: ClipboardItem, origin: Selection, now: SystemTime) {
+        self.add_copy(item, origin, now, None, None);
+    }
+
+    /// Adds an item a `Classifier` flagged as sensitive.
+    ///
+    /// Works like `add_at`, then applies `action`:
+    /// - `Skip` doesn't add the item.
+    /// - `Mask` marks the entry `masked` and takes its text out of the search index.
+    /// - `Expire` sets the entry's `expire_after`, or shortens it.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The ClipboardItem to add to history
+    /// * `origin` - The selection the item was read from
+    /// * `now` - When the item was copied
+    /// * `action` - What the classifier asked for
+    pub fn add_secret(
+        &mut self,
+        item: ClipboardItem,
+        origin: Selection,
+        now: SystemTime,
+        action: SecretAction,
+    ) {
+        self.add_copy(item, origin, now, None, Some(action));
+    }
+
+    /// Adds an item as the watcher saw it: with the application it was copied from and
+    /// what the classifiers decided, if anything.
+    ///
+    /// Works like `add_at`, or `add_secret` if `secret` is set. A copy of an item that is
+    /// already there keeps the application it was last copied from, if that is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The ClipboardItem to add to history
+    /// * `origin` - The selection the item was read from
+    /// * `now` - When the item was copied
+    /// * `source` - The application that copied it, see `ClipboardSource::app`
+    /// * `secret` - What a classifier asked for, `None` for ordinary copies
+    pub fn add_copy(
+        &mut self,
+        item: ClipboardItem,
+        origin: Selection,
+        now: SystemTime,
+        source: Option<String>,
+        secret: Option<SecretAction>,
+    ) {
+        if secret == Some(SecretAction::Skip) {
             return;
         }
 
-        // Add to 0 (front)
-        self.history.push_front(item);
+        let entry = self.record(item, origin, now);
+        if source.is_some() {
+            entry.source = source;
+        }
+        match secret {
+            Some(SecretAction::Mask) => {
+                entry.masked = true;
+                let id = entry.id;
+                self.index.remove(id);
+            }
+            Some(SecretAction::Expire(after)) => {
+                entry.expire_after = Some(entry.expire_after.map_or(after, |old| old.min(after)));
+            }
+            Some(SecretAction::Skip) | None => {}
+        }
 
         // Remove old items as size exceeds
-        if self.history.len() > self.max_size {
-            self.history.pop_back();
-        }
+        self.evict();
     }
 
     /// Promotes an item at the given position to the front of the history.
@@ -64,37 +883,44 @@ impl ClipboardHistory {
     ///
     /// * `pos` - The index of the item to promote
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the position is out of bounds
+    /// Returns `ClipboardError::IndexOutOfBound` if there is no item at `pos`.
     pub fn promote(&mut self, pos: usize) -> Result<(), ClipboardError> {
         // Remove item as 'pos'th index
         match self.history.remove(pos) {
-            Some(item) => {
-                self.history.push_front(item);
+            Some(mut entry) => {
+                entry.touch(SystemTime::now());
+                self.move_to_front(entry, false);
                 Ok(())
             }
             None => Err(ClipboardError::IndexOutOfBound),
         }
     }
 
-    /// Delets an item at the given position from history.
+    /// Deletes an item at the given position from history.
     ///
     /// # Arguments
     ///
     /// * `pos` - The index of the item to delete
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the position is out of bounds
+    /// Returns `ClipboardError::IndexOutOfBound` if there is no item at `pos`.
     pub fn delete(&mut self, pos: usize) -> Result<(), ClipboardError> {
         match self.history.remove(pos) {
-            Some(_) => Ok(()),
+            Some(entry) => {
+                self.index.remove(entry.id);
+                Ok(())
+            }
             None => Err(ClipboardError::IndexOutOfBound),
         }
     }
 
-    /// Delets an item at the given item from history.
+    /// Deletes the first unpinned entry holding `item_to_remove` from history.
+    ///
+    /// The daemon doesn't use this: clients name entries by id (`delete_by_id`), which can't hit
+    /// an equal copy recorded in the meantime. Kept for callers that own a `ClipboardHistory` directly.
     ///
     /// # Arguments
     ///
@@ -104,7 +930,10 @@ impl ClipboardHistory {
     ///
     /// Returns `ClipboardError::IndexOutOfBound` if the item does not exist in the history.
     pub fn delete_this(&mut self, item_to_remove: ClipboardItem) -> Result<(), ClipboardError> {
-        if let Some(index_to_remove) = self.history.iter().position(|item| *item == item_to_remove)
+        if let Some(index_to_remove) = self
+            .history
+            .iter()
+            .position(|entry| *entry == item_to_remove)
         {
             self.delete(index_to_remove)
         } else {
@@ -112,16 +941,449 @@ impl ClipboardHistory {
         }
     }
 
-    /// Returns a reference to all items in the clipboard history.
+    /// Pins the item at the given position so it survives eviction and `clear`.
+    ///
+    /// The item moves out of the rolling history to the front of the pinned items.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - The index of the item in the (unpinned) history
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClipboardError::IndexOutOfBound` if the position does not exist.
+    pub fn pin(&mut self, pos: usize) -> Result<(), ClipboardError> {
+        match self.history.remove(pos) {
+            Some(entry) => {
+                self.move_to_front(entry, true);
+                Ok(())
+            }
+            None => Err(ClipboardError::IndexOutOfBound),
+        }
+    }
+
+    /// Unpins the item at the given pinned position.
+    ///
+    /// The item goes back to the front of the rolling history, which may evict
+    /// the oldest unpinned item.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - The index of the item in the pinned items
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClipboardError::IndexOutOfBound` if the position does not exist.
+    pub fn unpin(&mut self, pos: usize) -> Result<(), ClipboardError> {
+        match self.pinned.remove(pos) {
+            Some(entry) => {
+                self.move_to_front(entry, false);
+                self.evict();
+                Ok(())
+            }
+            None => Err(ClipboardError::IndexOutOfBound),
+        }
+    }
+
+    /// Promotes the entry with the given id to the front of the history.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClipboardError::ItemNotFound` if no unpinned entry has that id.
+    pub fn promote_by_id(&mut self, id: u64) -> Result<(), ClipboardError> {
+        let pos = Self::position(&self.history, id)?;
+        self.promote(pos)
+    }
+
+    /// Deletes the entry with the given id from history.
+    ///
+    /// # Errors
     ///
-    /// Items are ordered from most recent (front) to oldest (back).
-    pub fn get_items(&self) -> &VecDeque<ClipboardItem> {
+    /// Returns `ClipboardError::ItemNotFound` if no unpinned entry has that id.
+    pub fn delete_by_id(&mut self, id: u64) -> Result<(), ClipboardError> {
+        let pos = Self::position(&self.history, id)?;
+        self.delete(pos)
+    }
+
+    /// Pins the entry with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClipboardError::ItemNotFound` if no unpinned entry has that id.
+    pub fn pin_by_id(&mut self, id: u64) -> Result<(), ClipboardError> {
+        let pos = Self::position(&self.history, id)?;
+        self.pin(pos)
+    }
+
+    /// Unpins the entry with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClipboardError::ItemNotFound` if no pinned entry has that id.
+    pub fn unpin_by_id(&mut self, id: u64) -> Result<(), ClipboardError> {
+        let pos = Self::position(&self.pinned, id)?;
+        self.unpin(pos)
+    }
+
+    /// Looks up an entry by id, pinned or not.
+    pub fn get_by_id(&self, id: u64) -> Option<&HistoryEntry> {
+        self.pinned
+            .iter()
+            .chain(self.history.iter())
+            .find(|entry| entry.id == id)
+    }
+
+    /// A copy of the history with every masked entry hidden, see `HistoryEntry::without_secret`.
+    ///
+    /// This is what clients get to see, the real text is only handed out one entry at a time.
+    pub fn without_secrets(&self) -> ClipboardHistory {
+        let mut copy = self.clone();
+        for entry in copy.pinned.iter_mut().chain(copy.history.iter_mut()) {
+            if entry.masked {
+                *entry = entry.without_secret();
+            }
+        }
+        copy
+    }
+
+    /// Summaries of all entries, without the full content.
+    ///
+    /// Listing the history through this stays cheap however large the copied images are.
+    pub fn summary(&self) -> HistorySummary {
+        HistorySummary {
+            pinned: self.pinned.iter().map(HistoryEntry::summary).collect(),
+            items: self.history.iter().map(HistoryEntry::summary).collect(),
+            total: self.history.len(),
+        }
+    }
+
+    /// Summaries of the entries matching `query`, with the matches highlighted.
+    ///
+    /// **Behavior**:
+    /// - Words and phrases are looked up in the search index, and only the entries containing
+    ///   all of them are looked at, by id. Whole words rank above prefixes, phrases above words.
+    /// - If no entry has the words, parts of words and fuzzy matches are searched instead
+    ///   (see `search::find`), in the `FALLBACK_ENTRIES` most recent pinned and unpinned
+    ///   entries only. Entries containing the text rank above fuzzy matches.
+    /// - Equally good matches keep the history order, most recent first.
+    /// - Pinned entries are returned separately and never limited, as in `summary`.
+    pub fn query(&self, query: &HistoryQuery) -> HistorySummary {
+        let text = query.text.trim();
+        let terms = QueryTerms::parse(text);
+        let candidates = self.index.candidates(&terms);
+        let phrases = QueryTerms {
+            words: Vec::new(),
+            phrases: terms.phrases.clone(),
+        };
+
+        // Words are scored by the index alone, only phrases need a look at the text
+        let by_words = |entry: &HistoryEntry| {
+            if !phrases.is_empty() {
+                search::match_terms(&entry.search_text(), &phrases)?;
+            }
+            Some(self.index.score(entry.id, &terms))
+        };
+
+        let (mut pinned, mut items) = match &candidates {
+            Some(ids) => (
+                rank(
+                    self.entries_with_ids(&self.pinned, ids).into_iter(),
+                    query,
+                    by_words,
+                ),
+                rank(
+                    self.entries_with_ids(&self.history, ids).into_iter(),
+                    query,
+                    by_words,
+                ),
+            ),
+            // No text lists everything the filters admit
+            None if text.is_empty() => (
+                rank(self.pinned.iter(), query, |_| Some(0)),
+                rank(self.history.iter(), query, |_| Some(0)),
+            ),
+            // Text without words (e.g. "#") can only match through the fallback
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let fallback = pinned.is_empty() && items.is_empty() && !text.is_empty();
+        if fallback {
+            let by_text = |entry: &HistoryEntry| {
+                search::find(&entry.search_text(), text).map(|found| u32::from(found.exact))
+            };
+            pinned = rank(self.pinned.iter().take(FALLBACK_ENTRIES), query, by_text);
+            items = rank(self.history.iter().take(FALLBACK_ENTRIES), query, by_text);
+        }
+
+        let total = items.len();
+        items.truncate(query.limit.unwrap_or(usize::MAX));
+
+        // Highlights are only worked out for the entries that are returned
+        let summarize = |entry: &HistoryEntry| {
+            let search_text = entry.search_text();
+            let ranges = if fallback {
+                search::find(&search_text, text).map(|found| found.ranges)
+            } else {
+                search::match_terms(&search_text, &terms).map(|found| found.ranges)
+            };
+            entry.summary_matching(&ranges.unwrap_or_default())
+        };
+        HistorySummary {
+            pinned: pinned.into_iter().map(summarize).collect(),
+            items: items.into_iter().map(summarize).collect(),
+            total,
+        }
+    }
+
+    /// Returns a reference to all unpinned entries in the clipboard history.
+    ///
+    /// Entries are ordered from most recent (front) to oldest (back).
+    pub fn get_items(&self) -> &VecDeque<HistoryEntry> {
         &self.history
     }
 
-    /// Clears all items from the clipboard history.
+    /// Returns a reference to the pinned entries, most recently pinned first.
+    pub fn get_pinned(&self) -> &VecDeque<HistoryEntry> {
+        &self.pinned
+    }
+
+    /// The ids of the pinned and unpinned entries, in order.
+    pub fn ids(&self) -> HistoryIds {
+        HistoryIds {
+            pinned: self.pinned.iter().map(|entry| entry.id).collect(),
+            items: self.history.iter().map(|entry| entry.id).collect(),
+        }
+    }
+
+    /// Clears all unpinned items from the clipboard history.
     pub fn clear(&mut self) {
-        self.history.clear();
+        for entry in self.history.drain(..) {
+            self.index.remove(entry.id);
+        }
+    }
+
+    /// How many unpinned items are kept.
+    pub fn max_size(&self) -> usize {
+        self.limits.max_items
+    }
+
+    /// Changes how many unpinned items are kept, dropping the oldest ones if there are too many.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.limits.max_items = max_size;
+        self.evict();
+    }
+
+    /// How much of the history is kept.
+    pub fn limits(&self) -> &HistoryLimits {
+        &self.limits
+    }
+
+    /// Changes how much of the history is kept, dropping the oldest items that don't fit.
+    pub fn set_limits(&mut self, limits: HistoryLimits) {
+        self.limits = limits;
+        self.evict();
+    }
+
+    /// How long entries are kept.
+    pub fn expiry(&self) -> &ExpiryPolicy {
+        &self.expiry
+    }
+
+    /// Changes how long entries are kept. Nothing is removed until `purge_expired`.
+    pub fn set_expiry(&mut self, expiry: ExpiryPolicy) {
+        self.expiry = expiry;
+    }
+
+    /// Removes the entries, pinned ones included, that are past the `ExpiryPolicy` at `now`.
+    ///
+    /// # Returns
+    ///
+    /// * The ids of the removed entries, pinned ones first
+    pub fn purge_expired(&mut self, now: SystemTime) -> Vec<u64> {
+        let mut expired = Vec::new();
+        for entries in [&mut self.pinned, &mut self.history] {
+            entries.retain(|entry| {
+                let keep = !self.expiry.is_expired(entry, now);
+                if !keep {
+                    expired.push(entry.id);
+                }
+                keep
+            });
+        }
+        for id in &expired {
+            self.index.remove(*id);
+        }
+        expired
+    }
+
+    /// Size of all unpinned items in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.history.iter().map(|entry| entry.byte_size).sum()
+    }
+
+    /// Index of the entry with `id` in `entries`.
+    fn position(entries: &VecDeque<HistoryEntry>, id: u64) -> Result<usize, ClipboardError> {
+        entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or(ClipboardError::ItemNotFound(id))
+    }
+
+    /// Puts `item` at the front, or promotes its existing entry, without evicting anything.
+    ///
+    /// # Returns
+    ///
+    /// * The entry holding the item
+    fn record(
+        &mut self,
+        item: ClipboardItem,
+        origin: Selection,
+        now: SystemTime,
+    ) -> &mut HistoryEntry {
+        // Pinned items are kept permanently already
+        if let Some(pos) = self.pinned.iter().position(|entry| *entry == item) {
+            let entry = &mut self.pinned[pos];
+            entry.touch(now);
+            entry.merge_origin(origin);
+            return entry;
+        }
+
+        // Check for item duplicates
+        let existing = self
+            .history
+            .iter()
+            .position(|entry| *entry == item)
+            .and_then(|pos| self.history.remove(pos));
+        let entry = match existing {
+            // It already exists. Promote it.
+            Some(mut entry) => {
+                entry.merge_origin(origin);
+                entry.touch(now);
+                entry
+            }
+            None => self.new_entry(item, origin, now),
+        };
+
+        // Add to 0 (front)
+        self.move_to_front(entry, false);
+        &mut self.history[0]
+    }
+
+    /// Puts `entry` in front of the pinned or the unpinned entries with the next stamp.
+    fn move_to_front(&mut self, entry: HistoryEntry, pinned: bool) {
+        self.stamps.insert(entry.id, self.next_stamp);
+        self.next_stamp += 1;
+        match pinned {
+            true => self.pinned.push_front(entry),
+            false => self.history.push_front(entry),
+        }
+
+        // Removed entries leave their stamps behind, drop them once they pile up
+        let live = self.pinned.len() + self.history.len();
+        if self.stamps.len() > 2 * live + 64 {
+            let ids: HashSet<u64> = self
+                .pinned
+                .iter()
+                .chain(self.history.iter())
+                .map(|entry| entry.id)
+                .collect();
+            self.stamps.retain(|id, _| ids.contains(id));
+        }
+    }
+
+    /// Stamps every entry afresh, back to front, after entries were added anywhere but the front.
+    fn restamp(&mut self) {
+        self.stamps.clear();
+        self.next_stamp = 0;
+        for entry in self.pinned.iter().rev().chain(self.history.iter().rev()) {
+            self.stamps.insert(entry.id, self.next_stamp);
+            self.next_stamp += 1;
+        }
+    }
+
+    /// The entries of `entries` (the pinned or the unpinned ones) with an id in `ids`, in
+    /// their order. Only looks at the entries asked for.
+    fn entries_with_ids<'a>(
+        &self,
+        entries: &'a VecDeque<HistoryEntry>,
+        ids: &BTreeSet<u64>,
+    ) -> Vec<&'a HistoryEntry> {
+        let mut found: Vec<(u64, &HistoryEntry)> = ids
+            .iter()
+            .filter_map(|id| {
+                let stamp = *self.stamps.get(id)?;
+                let pos = entries
+                    .binary_search_by(|entry| stamp.cmp(self.stamps.get(&entry.id).unwrap_or(&0)))
+                    .ok()?;
+                Some((stamp, &entries[pos]))
+            })
+            .collect();
+        found.sort_by(|(a, _), (b, _)| b.cmp(a));
+        found.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Wraps an item in a new entry with the next id.
+    fn new_entry(
+        &mut self,
+        item: ClipboardItem,
+        origin: Selection,
+        now: SystemTime,
+    ) -> HistoryEntry {
+        let entry = HistoryEntry::new(self.next_id, item, origin, now);
+        self.index.insert(entry.id, &entry.search_text());
+        self.next_id += 1;
+        entry
+    }
+
+    /// Drops the oldest unpinned items until the history fits its limits.
+    ///
+    /// Items too large for a byte limit on their own go first, so one huge image
+    /// doesn't push out everything else before being dropped itself.
+    fn evict(&mut self) {
+        let limits = &self.limits;
+        let index = &mut self.index;
+        self.history.retain(|entry| {
+            let fits = limits.fits(entry);
+            if !fits {
+                index.remove(entry.id);
+            }
+            fits
+        });
+
+        // Oldest of a kind that has too much
+        for (kind, limit) in &self.limits.kinds {
+            let (mut items, mut bytes) = self
+                .history
+                .iter()
+                .filter(|entry| kind.matches(&entry.item))
+                .fold((0, 0), |(items, bytes), entry| {
+                    (items + 1, bytes + entry.byte_size)
+                });
+
+            let mut pos = self.history.len();
+            while limit.exceeded_by(items, bytes) && pos > 0 {
+                pos -= 1;
+                if kind.matches(&self.history[pos].item)
+                    && let Some(entry) = self.history.remove(pos)
+                {
+                    self.index.remove(entry.id);
+                    items -= 1;
+                    bytes -= entry.byte_size;
+                }
+            }
+        }
+
+        // Oldest overall
+        let mut bytes = self.byte_size();
+        while self.history.len() > self.limits.max_items
+            || self.limits.max_bytes.is_some_and(|max| bytes > max)
+        {
+            let Some(entry) = self.history.pop_back() else {
+                break;
+            };
+            self.index.remove(entry.id);
+            bytes -= entry.byte_size;
+        }
     }
 }
 
@@ -130,20 +1392,40 @@ impl fmt::Display for ClipboardHistory {
     /// Formats the clipboard history as a human-readable table.
     ///
     /// Displays each item with its position and content. Text items show their
-    /// content, while image items show their dimensions.
+    /// content, while image items show their dimensions. Masked entries show
+    /// `MASKED_PREVIEW`, so printing the history never leaks a secret.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut printable = String::from("POS     | ITEM     ");
         printable += "\r\n---------------";
 
+        for (pos, entry) in self.pinned.iter().enumerate() {
+            match entry.masked {
+                true => printable += &format!("\r\nP{}      | {}     ", pos, MASKED_PREVIEW),
+                false => printable += &format!("\r\nP{}      | {}     ", pos, entry.item),
+            }
+        }
+
         // No sorting needed! Just iterate.
-        for (pos, item) in self.history.iter().enumerate() {
-            match item {
-                ClipboardItem::Image { width, height, .. } => {
-                    printable += &format!("\r\n{}       | Image ({}, {})     ", pos, width, height);
+        for (pos, entry) in self.history.iter().enumerate() {
+            if entry.masked {
+                printable += &format!("\r\n{}       | {}     ", pos, MASKED_PREVIEW);
+                continue;
+            }
+            match &entry.item {
+                ClipboardItem::Image(image) => {
+                    printable += &format!(
+                        "\r\n{}       | Image ({}, {})     ",
+                        pos, image.width, image.height
+                    );
                 }
                 ClipboardItem::Text(string) => {
                     printable += &format!("\r\n{}       | {}     ", pos, string);
                 }
+                ClipboardItem::Html { .. }
+                | ClipboardItem::Rtf { .. }
+                | ClipboardItem::Files(_) => {
+                    printable += &format!("\r\n{}       | {}     ", pos, entry.item);
+                }
             }
         }
 
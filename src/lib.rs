@@ -1,5 +1,9 @@
 // Module declarations - these make your modules available to tests and other crates
 pub mod common;
+pub mod config;
 pub mod gui;
 pub mod history;
+pub mod search;
+pub mod sensitive;
 pub mod services;
+pub mod storage;
@@ -1,16 +1,39 @@
 // Standard Crates
-use std::{fs, process, thread, time::Duration};
+use std::{
+    env,
+    ffi::CString,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 // External Crates
 use clap::{Parser, Subcommand};
 
 // My Crates
 use super_v::{
-    common::{LOCK_PATH, SOCKET_PATH},
+    common::{DaemonError, StorageError, default_socket_path, lock_path},
+    config::{
+        Config, ConfigFile, PasteConfig, parse_duration, parse_kind, parse_secret_action,
+        parse_size,
+    },
     gui::clipboard_gui::{InitialTab, MainThreadMsg, run_gui},
-    services::{clipboard_manager::Manager, ydotool::send_shift_insert},
+    history::KindFilter,
+    sensitive::{SecretAction, SecretRule},
+    services::{
+        clipboard_ipc_server::AccessPolicy,
+        clipboard_manager::{Manager, ManagerOptions},
+        ydotool::send_paste,
+    },
+    storage::{HistoryStore, StoreKey},
 };
 
+// Env var checked by `start --passphrase` before falling back to stdin
+const PASSPHRASE_ENV: &str = "SUPER_V_PASSPHRASE";
+
 /*
 Notes:
 - Add tests
@@ -24,7 +47,7 @@ Notes:
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Start the process
-    Start,
+    Start(Box<StartArgs>),
 
     /// Open the GUI
     OpenGui,
@@ -36,6 +59,112 @@ enum Command {
     Clean,
 }
 
+/// Flags of `start`. Settings given here win over the config file, also after it is reloaded.
+#[derive(clap::Args, Debug)]
+struct StartArgs {
+    /// Encrypt the history file with a key derived from this file's contents
+    #[arg(long, value_name = "PATH", conflicts_with = "passphrase")]
+    keyfile: Option<PathBuf>,
+
+    /// Encrypt the history file with a passphrase read from $SUPER_V_PASSPHRASE or stdin
+    #[arg(long)]
+    passphrase: bool,
+
+    /// Also record the PRIMARY selection (text selected with the mouse)
+    #[arg(long)]
+    primary: bool,
+
+    /// How many items to keep in history (pinned items don't count) [default: 1000]
+    #[arg(long, value_name = "N")]
+    max_items: Option<usize>,
+
+    /// How many bytes the history may take, e.g. 500M (pinned items don't count)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_bytes: Option<usize>,
+
+    /// Keep at most N items of a kind (text, formatted, image or files), e.g. image=10
+    #[arg(long, value_name = "KIND=N", value_parser = parse_kind_items)]
+    kind_max_items: Vec<(KindFilter, usize)>,
+
+    /// Let the items of a kind take at most SIZE bytes, e.g. text=1M
+    #[arg(long, value_name = "KIND=SIZE", value_parser = parse_kind_bytes)]
+    kind_max_bytes: Vec<(KindFilter, usize)>,
+
+    /// Remove entries, pinned ones too, that were not used for this long, e.g. 8h
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    expire_after: Option<Duration>,
+
+    /// Remove entries of a kind that were not used for this long, e.g. image=30m
+    #[arg(long, value_name = "KIND=DURATION", value_parser = parse_kind_duration)]
+    kind_expire_after: Vec<(KindFilter, Duration)>,
+
+    /// Treat text matching REGEX as a secret: skip it, mask it, or let it expire after a duration, e.g. 'corp-id:mask=corp-[0-9]{8}'
+    #[arg(long, value_name = "[NAME:]ACTION=REGEX", value_parser = parse_secret_rule)]
+    secret_rule: Vec<SecretRule>,
+
+    /// What happens to copies password managers mark as concealed: skip, mask or a duration to expire after [default: skip]
+    #[arg(long, value_name = "ACTION", value_parser = parse_secret_action)]
+    concealed: Option<SecretAction>,
+
+    /// Never record copies from this application, e.g. keepassxc (X11/XWayland only)
+    #[arg(long, value_name = "APP")]
+    deny_app: Vec<String>,
+
+    /// Let this user read, but not change, the history over the socket. Needs --socket in a
+    /// directory they can enter, and --socket-group with them in it
+    #[arg(long, value_name = "UID", requires = "socket_group")]
+    reader_uid: Vec<u32>,
+
+    /// Give the socket to this group (name or GID) and let its members connect (mode 0660)
+    #[arg(long, value_name = "GROUP", value_parser = parse_group)]
+    socket_group: Option<u32>,
+}
+
+impl StartArgs {
+    /// Puts the flags that were given into `config`.
+    fn apply(&self, config: &mut Config) {
+        let history = &mut config.history;
+        if let Some(max_items) = self.max_items {
+            history.max_items = max_items;
+        }
+        if self.max_bytes.is_some() {
+            history.max_bytes = self.max_bytes;
+        }
+        if self.expire_after.is_some() {
+            history.expire_after = self.expire_after;
+        }
+        for (kind, max) in &self.kind_max_items {
+            history.kinds.entry(*kind).or_default().max_items = Some(*max);
+        }
+        for (kind, max) in &self.kind_max_bytes {
+            history.kinds.entry(*kind).or_default().max_bytes = Some(*max);
+        }
+        for (kind, after) in &self.kind_expire_after {
+            history.kinds.entry(*kind).or_default().expire_after = Some(*after);
+        }
+
+        // Rules from the command line come first, so they win over the config's.
+        // Unnamed ones are numbered, their pattern may hold part of a secret.
+        let secrets = &mut config.secrets;
+        let rules = self.secret_rule.iter().enumerate().map(|(n, rule)| {
+            let mut rule = rule.clone();
+            if rule.name.is_empty() {
+                rule.name = format!("cli-{}", n + 1);
+            }
+            rule
+        });
+        secrets.rules.splice(0..0, rules);
+        if let Some(concealed) = self.concealed {
+            secrets.concealed = concealed;
+        }
+        secrets.deny_apps.extend(self.deny_app.iter().cloned());
+
+        if self.primary {
+            config.watch.primary = true;
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(
     name = "super_v",
@@ -44,84 +173,258 @@ enum Command {
     long_about = None
 )]
 struct Args {
+    /// Read settings from this file instead of $XDG_CONFIG_HOME/super_v/config.toml
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Talk to the daemon on this socket instead of $XDG_RUNTIME_DIR/super_v.sock
+    #[arg(long, value_name = "PATH", global = true)]
+    socket: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Command,
 }
 
-fn start_manager_daemon() {
-    let mut c_manager = match Manager::new() {
+/// Parses `KIND=VALUE` for the per-kind limits.
+fn parse_kind_limit<T>(
+    text: &str,
+    parse_value: impl Fn(&str) -> Result<T, String>,
+) -> Result<(KindFilter, T), String> {
+    let (kind, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected KIND=VALUE, got '{text}'"))?;
+    Ok((parse_kind(kind)?, parse_value(value)?))
+}
+
+fn parse_kind_items(text: &str) -> Result<(KindFilter, usize), String> {
+    parse_kind_limit(text, |value| {
+        value.trim().parse().map_err(|err| format!("{err}"))
+    })
+}
+
+fn parse_kind_bytes(text: &str) -> Result<(KindFilter, usize), String> {
+    parse_kind_limit(text, parse_size)
+}
+
+/// Parses `[NAME:]ACTION=REGEX`, where ACTION is skip, mask or a duration to expire after.
+/// Rules without a name get one in `apply`.
+fn parse_secret_rule(text: &str) -> Result<SecretRule, String> {
+    let (rule, pattern) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected [NAME:]ACTION=REGEX, got '{text}'"))?;
+    let (name, action) = rule.rsplit_once(':').unwrap_or(("", rule));
+    let action = parse_secret_action(action)?;
+    if action == SecretAction::Expire(Duration::ZERO) {
+        return Err("a rule must not expire after 0s, use 'skip'".to_string());
+    }
+    SecretRule::new(name.trim(), pattern, action).map_err(|err| err.to_string())
+}
+
+fn parse_kind_duration(text: &str) -> Result<(KindFilter, Duration), String> {
+    parse_kind_limit(text, parse_duration)
+}
+
+/// Parses a group name or GID.
+fn parse_group(text: &str) -> Result<u32, String> {
+    if let Ok(gid) = text.parse() {
+        return Ok(gid);
+    }
+
+    let name = CString::new(text).map_err(|_| format!("invalid group name '{text}'"))?;
+    // Arguments are parsed before any other thread runs, so nobody else touches getgrnam's buffer
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if group.is_null() {
+        return Err(format!("no group named '{text}'"));
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+/// Reads the config for the GUI. Errors are logged and the defaults used,
+/// so the clipboard still opens with a broken config.
+fn gui_config(path: Option<&Path>) -> Config {
+    match path.map(Config::load).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("{err}. Using the default settings.");
+            Config::default()
+        }
+    }
+}
+
+/// Builds the history store for `start`, encrypted if a key was given.
+fn history_store(
+    keyfile: Option<PathBuf>,
+    passphrase: bool,
+) -> Result<Option<HistoryStore>, StorageError> {
+    let Some(path) = HistoryStore::default_path() else {
+        eprintln!("Neither XDG_DATA_HOME nor HOME is set. History will not be saved.");
+        return Ok(None);
+    };
+
+    let key = if let Some(keyfile) = keyfile {
+        Some(StoreKey::from_keyfile(&keyfile)?)
+    } else if passphrase {
+        let passphrase = match env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) => passphrase,
+            Err(_) => {
+                let mut line = String::new();
+                io::stdin()
+                    .read_line(&mut line)
+                    .map_err(|err| StorageError::InvalidKey(err.to_string()))?;
+                line
+            }
+        };
+        Some(StoreKey::from_passphrase(&passphrase)?)
+    } else {
+        None
+    };
+
+    match key {
+        Some(key) => HistoryStore::encrypted(path, key).map(Some),
+        None => Ok(Some(HistoryStore::new(path))),
+    }
+}
+
+fn start_manager_daemon(start: StartArgs, config_path: Option<PathBuf>, socket: Option<PathBuf>) {
+    // The default socket sits in our private runtime directory, which readers can't enter
+    if !start.reader_uid.is_empty() && socket.is_none() {
+        eprintln!("--reader-uid needs a --socket in a directory the readers can enter.");
+        process::exit(1);
+    }
+
+    let (keyfile, passphrase) = (start.keyfile.clone(), start.passphrase);
+    let access = AccessPolicy {
+        readers: start.reader_uid.clone(),
+        group: start.socket_group,
+        ..AccessPolicy::default()
+    };
+
+    // The flags are applied again on every reload
+    let overrides: Arc<dyn Fn(&mut Config) + Send + Sync> =
+        Arc::new(move |config: &mut Config| start.apply(config));
+    let config_file = config_path.map(|path| ConfigFile {
+        path,
+        overrides: overrides.clone(),
+    });
+    let config = match &config_file {
+        Some(config_file) => config_file.load(),
+        None => {
+            eprintln!("Neither XDG_CONFIG_HOME nor HOME is set. Using the default settings.");
+            let mut config = Config::default();
+            overrides(&mut config);
+            config.validate().map(|_| config)
+        }
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+
+    let store = match history_store(keyfile, passphrase) {
+        Ok(store) => store,
+        Err(err) => {
+            eprintln!("Could not open history: {err}");
+            process::exit(1);
+        }
+    };
+
+    let mut c_manager = match Manager::with_options(ManagerOptions {
+        store,
+        watcher: None,
+        clipboard: None,
+        watch: config.watch_options(),
+        history_limits: config.history_limits(),
+        expiry: config.expiry(),
+        clock: None,
+        classifier: Some(Arc::new(config.classifier())),
+        sources: config.source_policy(),
+        config: config_file,
+        socket,
+        access,
+        frame_limits: config.frame_limits(),
+    }) {
         Ok(manager) => {
             println!("Starting service...");
             manager
         }
-        Err(_) => {
+        Err(DaemonError::ManagerMultiSpawn) => {
             eprintln!("Another instance of Manager already running.");
             process::exit(0);
         }
+        Err(err) => {
+            eprintln!("Could not start the Manager: {err}");
+            process::exit(1);
+        }
     };
 
     c_manager.start_daemon();
 }
 
+/// The socket the daemon listens on: `--socket`, or the per-user default.
+fn socket_path(socket: Option<PathBuf>) -> PathBuf {
+    match socket.map(Ok).unwrap_or_else(default_socket_path) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Opens the GUI on `tab` and pastes picked items the way `[paste]` says.
+fn open_gui(tab: InitialTab, config: Config, socket_path: PathBuf) {
+    use std::sync::mpsc::channel;
+
+    // Create a simple streaming channel
+    let (tx, rx) = channel::<MainThreadMsg>();
+
+    let PasteConfig {
+        ydotool_socket,
+        key,
+    } = config.paste;
+    let ydotool_handle = std::thread::spawn(move || {
+        while let Ok(msg) = rx.recv() {
+            match msg {
+                MainThreadMsg::AutoPaste => {
+                    thread::sleep(Duration::from_millis(100));
+                    send_paste(&ydotool_socket, key);
+                }
+                MainThreadMsg::Close => {
+                    break;
+                }
+            }
+        }
+    });
+
+    // Should be in main thread
+    run_gui(tx, tab, config.gui, socket_path);
+    let _ = ydotool_handle.join();
+}
+
 // ----------------------------- Main --------------------------------
 fn main() {
     // Daemon
     let args = Args::parse();
+    let config_path = args.config.or_else(Config::default_path);
     match args.command {
-        Command::Start => {
-            start_manager_daemon();
-        }
-        Command::OpenGui => {
-            use std::sync::mpsc::channel;
-
-            // Create a simple streaming channel
-            let (tx, rx) = channel::<MainThreadMsg>();
-
-            let ydotool_handle = std::thread::spawn(move || {
-                while let Ok(msg) = rx.recv() {
-                    match msg {
-                        MainThreadMsg::AutoPaste => {
-                            thread::sleep(Duration::from_millis(100));
-                            send_shift_insert();
-                        }
-                        MainThreadMsg::Close => {
-                            break;
-                        }
-                    }
-                }
-            });
-
-            // Should be in main thread
-            run_gui(tx, InitialTab::Clipboard);
-            let _ = ydotool_handle.join();
-        }
-        Command::OpenEmoji => {
-            use std::sync::mpsc::channel;
-
-            // Create a simple streaming channel
-            let (tx, rx) = channel::<MainThreadMsg>();
-
-            let ydotool_handle = std::thread::spawn(move || {
-                while let Ok(msg) = rx.recv() {
-                    match msg {
-                        MainThreadMsg::AutoPaste => {
-                            thread::sleep(Duration::from_millis(100));
-                            send_shift_insert();
-                        }
-                        MainThreadMsg::Close => {
-                            break;
-                        }
-                    }
-                }
-            });
-
-            // Should be in main thread
-            run_gui(tx, InitialTab::Emoji);
-            let _ = ydotool_handle.join();
-        }
+        Command::Start(start) => start_manager_daemon(*start, config_path, args.socket),
+        Command::OpenGui => open_gui(
+            InitialTab::Clipboard,
+            gui_config(config_path.as_deref()),
+            socket_path(args.socket),
+        ),
+        Command::OpenEmoji => open_gui(
+            InitialTab::Emoji,
+            gui_config(config_path.as_deref()),
+            socket_path(args.socket),
+        ),
         Command::Clean => {
-            let _ = fs::remove_file(SOCKET_PATH);
-            let _ = fs::remove_file(LOCK_PATH);
+            let socket_path = socket_path(args.socket);
+            let _ = fs::remove_file(lock_path(&socket_path));
+            let _ = fs::remove_file(socket_path);
         }
     }
 }
@@ -0,0 +1,358 @@
+// System Crates
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::{Bound, Range},
+};
+
+// ------------------------ Text Matching ----------------------------
+/// How much longer than the query a fuzzy match may be, e.g. "clpbrd" may span
+/// up to 18 characters.
+pub const FUZZY_SPAN_FACTOR: usize = 3;
+
+/// # TextMatch
+/// Where a query was found in a text.
+///
+/// Fields:
+/// - ranges: Matched parts as *character* ranges, in order and not overlapping.
+/// - exact: Whether the query was found as a whole (a substring), not just fuzzily.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    pub ranges: Vec<Range<usize>>,
+    pub exact: bool,
+}
+
+/// Finds `query` in `text`, ignoring case.
+///
+/// **Behavior**:
+/// - Every occurrence of `query` as a substring is matched (`exact`).
+/// - Otherwise the characters of `query` have to appear in order, e.g. "clpbrd"
+///   in "clipboard". Gaps are allowed, but the match may span at most
+///   `FUZZY_SPAN_FACTOR` times the length of the query. The shortest such span is used.
+/// - An empty query matches everything, with nothing to highlight.
+///
+/// # Returns
+///
+/// * `None` if `text` doesn't match
+pub fn find(text: &str, query: &str) -> Option<TextMatch> {
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return Some(TextMatch {
+            ranges: Vec::new(),
+            exact: true,
+        });
+    }
+
+    let text: Vec<char> = text.chars().map(fold).collect();
+    let ranges = find_exact(&text, &query);
+    if !ranges.is_empty() {
+        return Some(TextMatch {
+            ranges,
+            exact: true,
+        });
+    }
+
+    find_fuzzy(&text, &query).map(|ranges| TextMatch {
+        ranges,
+        exact: false,
+    })
+}
+
+// All non-overlapping occurrences of `query`
+fn find_exact(text: &[char], query: &[char]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= text.len() {
+        if text[start..start + query.len()] == *query {
+            ranges.push(start..start + query.len());
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    ranges
+}
+
+// The shortest span holding the characters of `query` in order
+fn find_fuzzy(text: &[char], query: &[char]) -> Option<Vec<Range<usize>>> {
+    let max_span = query.len() * FUZZY_SPAN_FACTOR;
+    let mut best: Option<Vec<usize>> = None;
+
+    for start in (0..text.len()).filter(|&start| text[start] == query[0]) {
+        let window = &text[start..text.len().min(start + max_span)];
+
+        // Greedily take the next wanted character
+        let mut positions = Vec::with_capacity(query.len());
+        let mut wanted = query.iter();
+        let mut next = wanted.next();
+        for (offset, c) in window.iter().enumerate() {
+            match next {
+                Some(w) if w == c => {
+                    positions.push(start + offset);
+                    next = wanted.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        if next.is_some() {
+            continue;
+        }
+
+        let span = |positions: &[usize]| positions[positions.len() - 1] - positions[0];
+        if best
+            .as_deref()
+            .is_none_or(|best| span(&positions) < span(best))
+        {
+            best = Some(positions);
+        }
+    }
+
+    // Neighbouring characters become one range
+    best.map(|positions| {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for position in positions {
+            match ranges.last_mut() {
+                Some(range) if range.end == position => range.end += 1,
+                _ => ranges.push(position..position + 1),
+            }
+        }
+        ranges
+    })
+}
+
+// Compares characters case-insensitively, one char for one char so positions stay put
+fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+    c.to_lowercase().next().unwrap_or(c)
+}
+// -------------------------------------------------------------------
+
+// ------------------------ Query Terms ------------------------------
+/// # QueryTerms
+/// A search text split into what the index can look up.
+///
+/// Fields:
+/// - words: Words that have to start a word of the entry, e.g. "clip" finds "clipboard".
+/// - phrases: Quoted parts, whose words have to appear in the entry in this order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryTerms {
+    pub words: Vec<String>,
+    pub phrases: Vec<Vec<String>>,
+}
+
+impl QueryTerms {
+    /// Splits `text` into words and `"quoted phrases"`. An unclosed quote runs to the end.
+    pub fn parse(text: &str) -> Self {
+        let mut terms = Self::default();
+        for (n, part) in text.split('"').enumerate() {
+            let words: Vec<String> = tokenize(part).map(|(_, word)| word).collect();
+
+            // Every second part is inside quotes
+            if n % 2 == 1 && words.len() > 1 {
+                terms.phrases.push(words);
+            } else {
+                terms.words.extend(words);
+            }
+        }
+        terms
+    }
+
+    /// Whether there is nothing to look up.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.phrases.is_empty()
+    }
+}
+
+/// # TermsMatch
+/// How well a text matched some `QueryTerms`.
+///
+/// Fields:
+/// - ranges: Matched words as *character* ranges, in order and not overlapping.
+/// - score: Higher is better. Whole words count more than prefixes, phrases more than words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermsMatch {
+    pub ranges: Vec<Range<usize>>,
+    pub score: u32,
+}
+
+/// Matches `terms` against the words of `text`.
+///
+/// # Returns
+///
+/// * `None` if a word or phrase of `terms` is missing from `text`
+pub fn match_terms(text: &str, terms: &QueryTerms) -> Option<TermsMatch> {
+    let words: Vec<(Range<usize>, String)> = tokenize(text).collect();
+    let mut ranges = Vec::new();
+    let mut score = 0;
+
+    for term in &terms.words {
+        let mut best = 0;
+        for (range, word) in words
+            .iter()
+            .filter(|(_, word)| word.starts_with(term.as_str()))
+        {
+            ranges.push(range.start..range.start + term.chars().count());
+            best = best.max(if word == term { 2 } else { 1 });
+        }
+        if best == 0 {
+            return None;
+        }
+        score += best;
+    }
+
+    for phrase in &terms.phrases {
+        let mut found = false;
+        for start in 0..words.len().saturating_sub(phrase.len() - 1) {
+            let window = &words[start..start + phrase.len()];
+            if window
+                .iter()
+                .zip(phrase)
+                .all(|((_, word), term)| word == term)
+            {
+                ranges.push(window[0].0.start..window[phrase.len() - 1].0.end);
+                found = true;
+            }
+        }
+        if !found {
+            return None;
+        }
+        score += 3;
+    }
+
+    // A phrase and its words may overlap, merge them
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    Some(TermsMatch {
+        ranges: merged,
+        score,
+    })
+}
+
+/// The words of `text`: lowercased runs of letters and digits, with their *character* ranges.
+pub fn tokenize(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut chars = text.chars().map(fold).enumerate().peekable();
+    std::iter::from_fn(move || {
+        // Skip to the next word
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+
+        let (start, first) = chars.next()?;
+        let mut word = String::from(first);
+        let mut end = start + 1;
+        while let Some((position, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            word.push(c);
+            end = position + 1;
+        }
+        Some((start..end, word))
+    })
+}
+// -------------------------------------------------------------------
+
+// ----------------------- Full-text Index ---------------------------
+/// # SearchIndex
+/// Inverted index from words to the ids of the entries containing them.
+///
+/// Kept up to date as entries come and go, so a search only looks at entries that
+/// contain every word, not at the whole history. Words are kept sorted, which makes
+/// all words starting with a prefix one range lookup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, BTreeSet<u64>>,
+
+    // Words of each entry, to remove it again
+    words: HashMap<u64, Vec<String>>,
+}
+
+impl SearchIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the words of `text` under `id`, replacing what was indexed for `id` before.
+    pub fn insert(&mut self, id: u64, text: &str) {
+        self.remove(id);
+
+        let words: BTreeSet<String> = tokenize(text).map(|(_, word)| word).collect();
+        for word in &words {
+            self.postings.entry(word.clone()).or_default().insert(id);
+        }
+        self.words.insert(id, words.into_iter().collect());
+    }
+
+    /// Forgets the entry `id`. Unknown ids are ignored.
+    pub fn remove(&mut self, id: u64) {
+        for word in self.words.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.postings.get_mut(&word) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Number of indexed entries.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether nothing is indexed.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Ids of the entries that may match `terms`: every word is the prefix of one of
+    /// their words, and they contain every word of every phrase.
+    ///
+    /// Phrases still have to be checked against the text, see `match_terms`.
+    ///
+    /// # Returns
+    ///
+    /// * `None` if `terms` is empty, which doesn't narrow anything down
+    pub fn candidates(&self, terms: &QueryTerms) -> Option<BTreeSet<u64>> {
+        let mut sets = terms.words.iter().map(|word| self.with_prefix(word)).chain(
+            terms
+                .phrases
+                .iter()
+                .flatten()
+                .map(|word| self.postings.get(word).cloned().unwrap_or_default()),
+        );
+
+        let first = sets.next()?;
+        Some(sets.fold(first, |found, set| &found & &set))
+    }
+
+    /// How well the entry `id` matches `terms`, like `TermsMatch::score`.
+    ///
+    /// Assumes `id` is one of the `candidates` and its phrases were checked.
+    pub fn score(&self, id: u64, terms: &QueryTerms) -> u32 {
+        let words: u32 = terms
+            .words
+            .iter()
+            .map(|word| match self.postings.get(word) {
+                Some(ids) if ids.contains(&id) => 2,
+                _ => 1,
+            })
+            .sum();
+        words + 3 * terms.phrases.len() as u32
+    }
+
+    // Ids of the entries with a word starting with `prefix`
+    fn with_prefix(&self, prefix: &str) -> BTreeSet<u64> {
+        self.postings
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+}
+// -------------------------------------------------------------------
@@ -0,0 +1,302 @@
+// Standard Crates
+use std::time::Duration;
+
+// External Crates
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// My Crates
+use crate::common::{ClipboardItem, ClipboardSource};
+
+// ------------------------- Classifier ------------------------------
+/// How long a one-time code is kept before it expires.
+pub const ONE_TIME_CODE_EXPIRY: Duration = Duration::from_secs(2 * 60);
+
+/// What happens to an item a classifier flagged as sensitive.
+///
+/// **Available**:
+/// * **Skip** - The item is not recorded at all
+/// * **Mask** - The item is recorded, but listed as "••••" until it is revealed, and can't be searched
+/// * **Expire(Duration)** - The item is recorded, and removed once it was not used for this long
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SecretAction {
+    Skip,
+    Mask,
+    Expire(Duration),
+}
+
+impl SecretAction {
+    /// The stricter of both actions. Skipping beats masking, masking beats expiring,
+    /// and the sooner expiry wins.
+    pub fn stricter(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Skip, _) | (_, Self::Skip) => Self::Skip,
+            (Self::Mask, _) | (_, Self::Mask) => Self::Mask,
+            (Self::Expire(a), Self::Expire(b)) => Self::Expire(a.min(b)),
+        }
+    }
+}
+
+/// Decides whether a copied item is sensitive, before it goes into the history.
+///
+/// The daemon runs every new item through one. See `RuleClassifier` for the regex based one.
+pub trait Classifier: Send + Sync {
+    /// What to do with `item`.
+    ///
+    /// # Returns
+    ///
+    /// * `None` if the item is not sensitive and is recorded as usual
+    fn classify(&self, item: &ClipboardItem) -> Option<SecretAction>;
+}
+
+/// # SecretRule
+/// A pattern for one kind of secret and what to do when it is found.
+///
+/// Fields:
+/// - name: What the rule finds, e.g. "JWT".
+/// - pattern: Searched for in the plain text of items.
+/// - action: What happens to items containing a match.
+/// - check: Extra test every match has to pass, e.g. the checksum of card numbers.
+#[derive(Debug, Clone)]
+pub struct SecretRule {
+    pub name: String,
+    pub pattern: Regex,
+    pub action: SecretAction,
+    pub check: Option<fn(&str) -> bool>,
+}
+
+impl SecretRule {
+    /// Creates a rule from a regex.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `regex::Error` if `pattern` is not a valid regex.
+    pub fn new(
+        name: impl Into<String>,
+        pattern: &str,
+        action: SecretAction,
+    ) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name: name.into(),
+            pattern: Regex::new(pattern)?,
+            action,
+            check: None,
+        })
+    }
+
+    /// Whether `text` contains a match that passes `check`.
+    pub fn matches(&self, text: &str) -> bool {
+        self.pattern
+            .find_iter(text)
+            .any(|found| self.check.is_none_or(|check| check(found.as_str())))
+    }
+}
+
+/// # RuleClassifier
+/// Classifies items by the first `SecretRule` matching their plain text.
+///
+/// Only text and the plain text of formatted items are looked at, never images or files.
+#[derive(Debug, Clone, Default)]
+pub struct RuleClassifier {
+    rules: Vec<SecretRule>,
+}
+
+impl RuleClassifier {
+    /// Creates a classifier without rules, which flags nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a classifier with the built-in rules, see `builtin_rules`.
+    pub fn builtin() -> Self {
+        Self {
+            rules: builtin_rules(),
+        }
+    }
+
+    /// Adds `rule` after the existing ones. Earlier rules win.
+    pub fn push(&mut self, rule: SecretRule) {
+        self.rules.push(rule);
+    }
+
+    /// The rules, in the order they are tried.
+    pub fn rules(&self) -> &[SecretRule] {
+        &self.rules
+    }
+
+    /// The first rule matching the plain text of `item`.
+    pub fn find(&self, item: &ClipboardItem) -> Option<&SecretRule> {
+        let text = item.plain_text()?;
+        self.rules.iter().find(|rule| rule.matches(text))
+    }
+}
+
+impl Classifier for RuleClassifier {
+    fn classify(&self, item: &ClipboardItem) -> Option<SecretAction> {
+        self.find(item).map(|rule| rule.action)
+    }
+}
+// -------------------------------------------------------------------
+
+// ------------------------ Built-in Rules ---------------------------
+/// Rules for common secret formats.
+///
+/// **Behavior**:
+/// - Private keys (PEM) are skipped.
+/// - AWS access keys, GitHub, GitLab and Slack tokens, `sk-`/`sk_live_` style API keys,
+///   JWTs and credit card numbers (with a valid checksum) are masked.
+/// - One-time codes, a copy of only 6 to 8 digits, expire after `ONE_TIME_CODE_EXPIRY`.
+pub fn builtin_rules() -> Vec<SecretRule> {
+    let rule = |name: &str, pattern: &str, action| {
+        SecretRule::new(name, pattern, action).expect("built-in secret patterns are valid")
+    };
+
+    vec![
+        rule(
+            "private key",
+            r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----",
+            SecretAction::Skip,
+        ),
+        rule(
+            "AWS access key",
+            r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+            SecretAction::Mask,
+        ),
+        rule(
+            "GitHub token",
+            r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})\b",
+            SecretAction::Mask,
+        ),
+        rule(
+            "GitLab token",
+            r"\bglpat-[A-Za-z0-9_-]{20,}\b",
+            SecretAction::Mask,
+        ),
+        rule(
+            "Slack token",
+            r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b",
+            SecretAction::Mask,
+        ),
+        rule(
+            "API key",
+            r"\b(?:sk|rk)[-_](?:(?:live|test|proj)[-_])?[A-Za-z0-9_-]{20,}\b",
+            SecretAction::Mask,
+        ),
+        rule(
+            "JWT",
+            r"\beyJ[A-Za-z0-9_-]{5,}\.eyJ[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]{10,}",
+            SecretAction::Mask,
+        ),
+        SecretRule {
+            check: Some(luhn_valid),
+            ..rule(
+                "credit card",
+                r"\b\d(?:[ -]?\d){12,18}\b",
+                SecretAction::Mask,
+            )
+        },
+        rule(
+            "one-time code",
+            r"^\s*\d{6,8}\s*$",
+            SecretAction::Expire(ONE_TIME_CODE_EXPIRY),
+        ),
+    ]
+}
+
+/// Whether the digits of `text` pass the Luhn checksum used by card numbers.
+pub fn luhn_valid(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+
+    // Every second digit from the right is doubled
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(n, &digit)| match (n % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+// -------------------------------------------------------------------
+
+// ------------------------ Source Policy ----------------------------
+/// Flavours password managers and similar apps add to mark a copy as concealed.
+///
+/// KDE's `x-kde-passwordManagerHint` (KeePassXC, Bitwarden), the `nspasteboard.org`
+/// types and Windows' monitor exclusion. A `NAME=VALUE` style hint like
+/// `CLIPBOARD_STATE=secret` only counts if the value is "secret".
+pub const CONCEALED_TYPES: &[&str] = &[
+    "x-kde-passwordManagerHint",
+    "CLIPBOARD_STATE",
+    "org.nspasteboard.ConcealedType",
+    "org.nspasteboard.TransientType",
+    "ExcludeClipboardContentFromMonitorProcessing",
+];
+
+/// Whether one of `mime_types` is a `CONCEALED_TYPES` hint, ignoring case.
+pub fn is_concealed(mime_types: &[String]) -> bool {
+    mime_types.iter().any(|mime_type| {
+        let (name, value) = match mime_type.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (mime_type.as_str(), None),
+        };
+        CONCEALED_TYPES
+            .iter()
+            .any(|hint| hint.eq_ignore_ascii_case(name.trim()))
+            && value.is_none_or(|value| value.trim().eq_ignore_ascii_case("secret"))
+    })
+}
+
+/// # SourcePolicy
+/// Decides what happens to a copy by who made it, before its content is looked at.
+///
+/// Fields:
+/// - concealed: What happens to copies flagged with a `CONCEALED_TYPES` hint. Skipped by default.
+/// - denied_apps: Applications whose copies are never recorded, compared ignoring case,
+///   e.g. "keepassxc". Only works where the platform names the source (see `ClipboardSource::app`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcePolicy {
+    pub concealed: SecretAction,
+    pub denied_apps: Vec<String>,
+}
+
+impl Default for SourcePolicy {
+    /// Skips concealed copies, denies no application.
+    fn default() -> Self {
+        Self {
+            concealed: SecretAction::Skip,
+            denied_apps: Vec::new(),
+        }
+    }
+}
+
+impl SourcePolicy {
+    /// What to do with a copy from `source`.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(SecretAction::Skip)` if the app is denied
+    /// * `Some(concealed)` if the copy carries a concealed hint
+    /// * `None` if the source says nothing, the content still goes through the `Classifier`
+    pub fn classify(&self, source: &ClipboardSource) -> Option<SecretAction> {
+        let denied = source.app.as_ref().is_some_and(|app| {
+            self.denied_apps
+                .iter()
+                .any(|denied| denied.eq_ignore_ascii_case(app))
+        });
+        if denied {
+            Some(SecretAction::Skip)
+        } else if is_concealed(&source.mime_types) {
+            Some(self.concealed)
+        } else {
+            None
+        }
+    }
+}
+// -------------------------------------------------------------------
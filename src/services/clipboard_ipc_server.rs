@@ -1,8 +1,16 @@
 // System Crates
 use std::{
-    fs::remove_file,
-    io::{Read, Write},
-    os::unix::net::{UnixListener, UnixStream},
+    fs::{self, remove_file},
+    io::{self, Read, Write},
+    mem,
+    os::unix::{
+        fs::PermissionsExt,
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
 };
 
 // External Crates
@@ -11,39 +19,160 @@ use serde::{Deserialize, Serialize};
 
 // My Crates
 use crate::{
-    common::{ClipboardItem, IPCServerError, SOCKET_PATH},
-    history::ClipboardHistory,
+    common::{
+        ClipboardError, ErrorCode, IPCError, IPCServerError, current_uid, default_socket_path,
+    },
+    history::{ClipboardHistory, HistoryEntry, HistoryEvent, HistoryQuery, HistorySummary},
 };
 
 // ------------------------- IPC Items -------------------------------
+/// Version of the IPC protocol, exchanged with `Payload::Hello` when a client connects.
+///
+/// Version 1 had no handshake. Version 3 dropped `CmdIPC::DeleteThis`, use `Delete` with the entry id,
+/// and `Watch`, use `Subscribe`, whose events include expiries. It masks secrets in snapshots and
+/// fetched entries, use `Reveal` to read one. Its responses only report failures in `result`,
+/// there are no separate `message` and `error` fields.
+/// Clients and daemons only talk if their versions are equal.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// How long a client waits for the daemon to answer its `Payload::Hello`.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Largest frame `read_payload` accepts, in bytes. Big enough for a snapshot of a full history with images.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 256 << 20;
+
+/// Largest request the daemon accepts, in bytes. Requests are commands and queries, never clipboard data.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1 << 20;
+
+/// How long the daemon waits for a client to send or take a frame.
+pub const DEFAULT_IPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Limits for the frames of one connection.
+///
+/// **Contains**:
+/// * **max_request_size** - Frames announcing more bytes are refused before they are read.
+///   `DEFAULT_MAX_REQUEST_SIZE` by default, as the daemon reads requests with it.
+/// * **timeout** - How long a read or write may block. `None` waits forever.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameLimits {
+    pub max_request_size: usize,
+    pub timeout: Option<Duration>,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            timeout: Some(DEFAULT_IPC_TIMEOUT),
+        }
+    }
+}
+
+impl FrameLimits {
+    /// Sets `timeout` as the read and write timeout of `stream`.
+    ///
+    /// # Errors
+    /// - Returns `IPCServerError::ConnectionError` if the timeouts can't be set.
+    pub fn apply(&self, stream: &UnixStream) -> Result<(), IPCServerError> {
+        stream
+            .set_read_timeout(self.timeout)
+            .and_then(|_| stream.set_write_timeout(self.timeout))
+            .map_err(|err| IPCServerError::ConnectionError(err.to_string()))
+    }
+}
+
 /// Represents the commands that IPC Supports
 ///
 /// This enum allows for the following commands:
-/// * **Promote(usize)** - Command that promotes and item to top of history.
-/// * **Delete(usize)** - Command that deletes an item from history given its pos.
-/// * **Snapshot** - Command that retrieves the snapshot of the current Clipboard History
-/// * **Clear** - Command that clears the entire clipboard History.
+/// * **Promote(u64)** - Command that promotes an item to top of history given its id.
+/// * **Delete(u64)** - Command that deletes an item from history given its id.
+/// * **Snapshot** - Command that retrieves the snapshot of the current Clipboard History, with secrets masked.
+/// * **Clear** - Command that clears the entire clipboard History, except pinned items.
+/// * **Pin(u64)** - Command that pins an item given its id.
+/// * **Unpin(u64)** - Command that unpins an item given its id.
+/// * **Restore(u64)** - Command that puts an item back on the system clipboard, with every flavour it was copied with.
+/// * **Summary** - Command that retrieves a lightweight summary of the history (previews and thumbnails only).
+/// * **Fetch(u64)** - Command that retrieves one full entry given its id. A masked entry comes with `MASKED_PREVIEW` as its text.
+/// * **Query(HistoryQuery)** - Command that retrieves the summaries of the entries matching a search, with matches highlighted.
+/// * **Subscribe** - Command that keeps the connection open. The daemon answers with the summary, then sends a `Payload::Event` for every change to the history (see `Subscribers`).
+/// * **Reveal(u64)** - Command that retrieves one full entry given its id, with the text of a masked entry. Only for the daemon's own user.
+///
+/// Ids come from `HistoryEntry::id` and stay valid while the poller adds new items,
+/// unlike positions in a snapshot.
 #[allow(unused)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CmdIPC {
-    Promote(usize),
-    Delete(usize),
-    DeleteThis(ClipboardItem),
+    Promote(u64),
+    Delete(u64),
     Snapshot,
     Clear,
     Stop,
+    Pin(u64),
+    Unpin(u64),
+    Restore(u64),
+    Summary,
+    Fetch(u64),
+    Query(HistoryQuery),
+    Subscribe,
+    Reveal(u64),
+}
+
+impl CmdIPC {
+    /// Whether the command only reads the history, so read-only clients may run it.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            CmdIPC::Snapshot
+                | CmdIPC::Summary
+                | CmdIPC::Fetch(_)
+                | CmdIPC::Query(_)
+                | CmdIPC::Subscribe
+        )
+    }
+}
+
+/// Whether a request was carried out.
+///
+/// **Available**:
+/// * **Ok** - It was. The data asked for is in the other fields of the `IPCResponse`.
+/// * **Err(IPCError)** - It was not. Match on the `ErrorCode`, the message is meant for people and may change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum IPCResult {
+    #[default]
+    Ok,
+    Err(IPCError),
 }
 
 /// A data structure representing the Response of IPC.
 ///
 /// **Contains**:
 /// * **history_snapshot** - A snapshot of the current ClipboardHistory from the Clipboard Manager Daemon
-/// * **message** - Optional message if there are any errors.
+/// * **summary** - The history summary, answer to `CmdIPC::Summary` and `CmdIPC::Query`
+/// * **entry** - The full entry, answer to `CmdIPC::Fetch`
+/// * **result** - Whether the command was carried out, with a stable `ErrorCode` if not
 #[allow(unused)]
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct IPCResponse {
-    pub history_snapshot: Option<ClipboardHistory>,
-    pub message: Option<String>,
+    pub history_snapshot: Option<Box<ClipboardHistory>>,
+    #[serde(default)]
+    pub summary: Option<HistorySummary>,
+    #[serde(default)]
+    pub entry: Option<Box<HistoryEntry>>,
+    #[serde(default)]
+    pub result: IPCResult,
+}
+
+impl IPCResponse {
+    /// A response saying the command failed with `code`.
+    pub fn failure(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            result: IPCResult::Err(IPCError {
+                code,
+                message: message.into(),
+            }),
+            ..Default::default()
+        }
+    }
 }
 
 // In case another data or id is to be sent
@@ -67,30 +196,146 @@ pub struct PayloadData {
 ///
 /// **Available**:
 /// * **Cmd(CmdIPC)** - CmdIPC for giving commands
-/// * **Resp(IPCResponse)** - IPCResponse that contains a snapshot and a message
+/// * **Resp(IPCResponse)** - IPCResponse that contains the data asked for and the result
+/// * **Hello(u32)** - The `PROTOCOL_VERSION` of each side, sent by the client first and answered by the daemon
+/// * **Event(HistoryEvent)** - A change to the history, sent to subscribers
+/// * **Lagged(u64)** - How many events a subscriber missed because it read too slowly. Fetch a `Summary` to catch up.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Payload {
     Request(IPCRequest),
     Response(IPCResponse),
+    Hello(u32),
+    Event(HistoryEvent),
+    Lagged(u64),
 }
 
 impl Payload {
     /// Constructs PayloadData for a given Payload
-    fn to_payload(&self) -> PayloadData {
+    ///
+    /// # Errors
+    /// - Returns `IPCServerError::SendError` if the payload can't be serialized.
+    /// - Returns `IPCServerError::FrameTooLarge` if it doesn't fit the 4 length bytes.
+    fn to_payload(&self) -> Result<PayloadData, IPCServerError> {
         let mut buf: Vec<u8> = Vec::new();
-        let _ = self.serialize(&mut Serializer::new(&mut buf)).ok();
-        let len: [u8; 4] = (buf.len() as u32).to_be_bytes();
+        self.serialize(&mut Serializer::new(&mut buf))
+            .map_err(|err| IPCServerError::SendError(err.to_string()))?;
+        let len: [u8; 4] = u32::try_from(buf.len())
+            .map_err(|_| IPCServerError::FrameTooLarge(buf.len()))?
+            .to_be_bytes();
 
-        PayloadData { buf, len }
+        Ok(PayloadData { buf, len })
     }
 }
 // -------------------------------------------------------------------
 
-/// Creates and binds a new Unix domain socket listener at SOCKET_PATH.
+// ---------------------- Peer Credentials ---------------------------
+/// What a client connected to the daemon may do.
+///
+/// **Available**:
+/// * **Full** - Any command. Clients of the user the daemon belongs to.
+/// * **ReadOnly** - Only commands that read the history (see `CmdIPC::is_read_only`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Full,
+    ReadOnly,
+}
+
+impl Access {
+    /// Whether a client with this access may run `cmd`.
+    pub fn allows(self, cmd: &CmdIPC) -> bool {
+        self == Access::Full || cmd.is_read_only()
+    }
+}
+
+/// Which users may talk to the daemon. Checked with `SO_PEERCRED` on every connection.
+///
+/// **Contains**:
+/// * **owner** - The user with full access. The user the daemon runs as by default.
+/// * **readers** - Other users that may read, but not change, the history.
+/// * **group** - Group the socket belongs to. Its members may connect (mode 0660), otherwise
+///   only the owner can (mode 0600). Readers have to be in it.
+///
+/// Everyone else is refused with `ClipboardError::PermissionDenied`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessPolicy {
+    pub owner: u32,
+    pub readers: Vec<u32>,
+    pub group: Option<u32>,
+}
+
+impl Default for AccessPolicy {
+    /// Only the user the daemon runs as.
+    fn default() -> Self {
+        Self {
+            owner: current_uid(),
+            readers: Vec::new(),
+            group: None,
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// What the user `uid` may do, `None` if nothing.
+    pub fn access(&self, uid: u32) -> Option<Access> {
+        if uid == self.owner {
+            Some(Access::Full)
+        } else if self.readers.contains(&uid) {
+            Some(Access::ReadOnly)
+        } else {
+            None
+        }
+    }
+
+    /// What the process on the other end of `stream` may do.
+    ///
+    /// **Errors**:
+    /// - Returns `ClipboardError::PermissionDenied` if its user may do nothing, or can't be told.
+    pub fn authorize(&self, stream: &UnixStream) -> Result<Access, ClipboardError> {
+        let uid = peer_uid(stream).map_err(|err| {
+            ClipboardError::PermissionDenied(format!("could not read peer credentials: {err}"))
+        })?;
+        self.access(uid).ok_or_else(|| {
+            ClipboardError::PermissionDenied(format!("uid {uid} may not use this clipboard"))
+        })
+    }
+}
+
+/// The user id of the process on the other end of `stream`, as the kernel saw it when connecting.
+///
+/// # Errors
+/// - Returns the `io::Error` of `getsockopt(SO_PEERCRED)`.
+pub fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: `cred` and `len` are valid for writes and `len` is the size of `cred`
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+// -------------------------------------------------------------------
+
+/// Creates and binds a new Unix domain socket listener at `socket_path`.
 ///
 /// # Behavior
 /// - If an existing server is already bound to the socket path, it returns an error.
-/// - If a stale socket file exists, it removes it before rebinding.
+/// - If a stale socket file exists, it is replaced.
+/// - The socket is bound under a temporary name, limited to mode 0600 and then renamed into place,
+///   so other users never get a chance to connect.
 /// - On success, returns a bound `UnixListener`.
 ///
 /// # Errors
@@ -99,13 +344,14 @@ impl Payload {
 ///
 /// # Example
 /// ```no_run
-/// use super_v::services::clipboard_ipc_server::create_bind;
-/// let listener = create_bind().expect("Failed to bind IPC server");
+/// use super_v::{common::default_socket_path, services::clipboard_ipc_server::create_bind};
+/// let socket_path = default_socket_path().expect("No runtime directory");
+/// let listener = create_bind(&socket_path).expect("Failed to bind IPC server");
 /// ```
-pub fn create_bind() -> Result<UnixListener, IPCServerError> {
+pub fn create_bind(socket_path: &Path) -> Result<UnixListener, IPCServerError> {
     // Check if we can connect to server.
     // If yes, then server already running and a new server should not start
-    let try_conn = create_default_stream();
+    let try_conn = connect(socket_path);
 
     let Err(IPCServerError::FileNotFound | IPCServerError::ConnectionError(_)) = try_conn else {
         return Err(IPCServerError::BindError(
@@ -113,39 +359,53 @@ pub fn create_bind() -> Result<UnixListener, IPCServerError> {
         ));
     };
 
-    // Remove the old sock file
-    let _ = remove_file(SOCKET_PATH);
+    // Bind next to the real path, so the rename below stays on one filesystem
+    let mut temp_name = socket_path.as_os_str().to_owned();
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp_path = PathBuf::from(temp_name);
+    let _ = remove_file(&temp_path);
 
-    // Create a new listener
-    let listener = match UnixListener::bind(SOCKET_PATH) {
-        Ok(listener) => listener,
-        Err(err) => {
-            return Err(IPCServerError::BindError(format!("{:?}", err)));
-        }
+    let bind_error = |err: io::Error| {
+        let _ = remove_file(&temp_path);
+        IPCServerError::BindError(format!("{:?}", err))
     };
 
+    // Create a new listener, only for us, and replace the old sock file with it
+    let listener = UnixListener::bind(&temp_path).map_err(&bind_error)?;
+    fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600)).map_err(&bind_error)?;
+    fs::rename(&temp_path, socket_path).map_err(&bind_error)?;
+
     // Return Listener
     Ok(listener)
 }
 
-/// Attempts to connect to the default Unix socket at SOCKET_PATH.
+/// Attempts to connect to the daemon's Unix socket at `socket_path`.
 ///
 /// # Behavior
-/// - Returns a connected `UnixStream` if the socket is active.
+/// - Returns a connected `UnixStream` once the daemon agreed to the protocol version (see `handshake`).
 /// - Handles typical connection failures with custom `IPCServerError` variants.
 ///
 /// # Errors
 /// - Returns `IPCServerError::ConnectionError` if connection is refused.
 /// - Returns `IPCServerError::FileNotFound` if the socket file is missing.
 /// - Returns `IPCServerError::ConnectionError` for any other I/O error.
+/// - Returns the errors of `handshake`.
 ///
 /// # Example
 /// ```no_run
-/// use super_v::services::clipboard_ipc_server::create_default_stream;
-/// let mut stream = create_default_stream().expect("Unable to connect to IPC server");
+/// use std::path::Path;
+/// use super_v::services::clipboard_ipc_server::create_stream;
+/// let mut stream = create_stream(Path::new("/run/user/1000/super_v.sock")).expect("Unable to connect to IPC server");
 /// ```
-pub fn create_default_stream() -> Result<UnixStream, IPCServerError> {
-    match UnixStream::connect(SOCKET_PATH) {
+pub fn create_stream(socket_path: &Path) -> Result<UnixStream, IPCServerError> {
+    let mut stream = connect(socket_path)?;
+    handshake(&mut stream)?;
+    Ok(stream)
+}
+
+// Connects without the handshake
+fn connect(socket_path: &Path) -> Result<UnixStream, IPCServerError> {
+    match UnixStream::connect(socket_path) {
         Ok(stream) => Ok(stream),
         Err(err) => {
             if let Some(err_code) = err.raw_os_error() {
@@ -163,6 +423,107 @@ pub fn create_default_stream() -> Result<UnixStream, IPCServerError> {
     }
 }
 
+/// Attempts to connect to the daemon at the default socket path (see `default_socket_path`).
+///
+/// # Errors
+/// - Returns `IPCServerError::UnsafePath` if there is no private runtime directory.
+/// - Otherwise the errors of `create_stream`.
+///
+/// # Example
+/// ```no_run
+/// use super_v::services::clipboard_ipc_server::create_default_stream;
+/// let mut stream = create_default_stream().expect("Unable to connect to IPC server");
+/// ```
+pub fn create_default_stream() -> Result<UnixStream, IPCServerError> {
+    create_stream(&default_socket_path()?)
+}
+
+/// The client side of the handshake: sends our `PROTOCOL_VERSION` and waits for the daemon's.
+///
+/// # Errors
+/// - Returns `IPCServerError::Rejected` if the daemon speaks another version or refused us,
+///   with `ErrorCode::Unsupported` or e.g. `ErrorCode::PermissionDenied`.
+/// - Returns `IPCServerError::RecieveError` if the daemon hung up or did not answer within
+///   `HANDSHAKE_TIMEOUT`. Daemons of protocol version 1 hang up.
+pub fn handshake(stream: &mut UnixStream) -> Result<(), IPCServerError> {
+    // A daemon refusing us may hang up before our Hello arrives, its refusal can still be read
+    let sent = send_payload(stream, Payload::Hello(PROTOCOL_VERSION));
+
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(|err| IPCServerError::RecieveError(err.to_string()))?;
+    let answer = read_payload(stream);
+    stream
+        .set_read_timeout(None)
+        .map_err(|err| IPCServerError::RecieveError(err.to_string()))?;
+
+    let answer = match (sent, answer) {
+        (_, Ok(answer)) => answer,
+        (Err(err), Err(_)) => return Err(err),
+        (Ok(()), Err(err)) => {
+            return Err(IPCServerError::RecieveError(format!(
+                "no answer to the handshake, the daemon may be too old ({err})"
+            )));
+        }
+    };
+    match answer {
+        Payload::Hello(PROTOCOL_VERSION) => Ok(()),
+        Payload::Hello(version) => Err(IPCServerError::Rejected(IPCError {
+            code: ErrorCode::Unsupported,
+            message: format!(
+                "The daemon speaks IPC protocol {version}, this client speaks {PROTOCOL_VERSION}."
+            ),
+        })),
+        Payload::Response(IPCResponse {
+            result: IPCResult::Err(err),
+            ..
+        }) => Err(IPCServerError::Rejected(err)),
+        _ => Err(IPCServerError::RecieveError(
+            "unexpected answer to the handshake".into(),
+        )),
+    }
+}
+
+/// The daemon side of the handshake: reads the client's `Payload::Hello` and answers it.
+///
+/// # Behavior
+/// - Reads the Hello like any request, refusing frames larger than `limits.max_request_size`.
+/// - Answers with our own `Payload::Hello` if the versions match.
+/// - Otherwise answers with an `ErrorCode::Unsupported` response. Clients of version 1 send their
+///   request right away and get this response instead of an answer to it.
+///
+/// # Errors
+/// - Returns the `IPCError` that was sent back if the client can't be served.
+pub fn accept_handshake(stream: &mut UnixStream, limits: &FrameLimits) -> Result<(), IPCError> {
+    let unsupported = |version: u32| IPCError {
+        code: ErrorCode::Unsupported,
+        message: format!(
+            "This daemon speaks IPC protocol {PROTOCOL_VERSION}, the client speaks {version}. Please update the older one."
+        ),
+    };
+    let bad_request = |message: String| IPCError {
+        code: ErrorCode::BadRequest,
+        message,
+    };
+
+    let err = match read_payload_limited(stream, limits.max_request_size) {
+        Ok(Payload::Hello(PROTOCOL_VERSION)) => {
+            return send_payload(stream, Payload::Hello(PROTOCOL_VERSION))
+                .map_err(|err| bad_request(err.to_string()));
+        }
+        Ok(Payload::Hello(version)) => unsupported(version),
+        Ok(Payload::Request(_)) => unsupported(1),
+        Ok(Payload::Response(_) | Payload::Event(_) | Payload::Lagged(_)) => {
+            bad_request("Expected a handshake but got a message of the daemon.".into())
+        }
+        Err(err) => bad_request(err.to_string()),
+    };
+
+    let refusal = IPCResponse::failure(err.code, err.message.clone());
+    let _ = send_payload(stream, Payload::Response(refusal));
+    Err(err)
+}
+
 /// Sends a serialized `Payload` over a connected Unix stream.
 ///
 /// # Behavior
@@ -170,32 +531,33 @@ pub fn create_default_stream() -> Result<UnixStream, IPCServerError> {
 /// - Prepends the payload length (4 bytes, big-endian).
 /// - Sends both the length and serialized data through the stream.
 /// - Flushes the stream to ensure all data is written.
+/// - Blocks at most as long as the write timeout of `stream` (see `FrameLimits::apply`).
 ///
-/// # Panics
-/// - Panics if the stream fails to write or flush.
+/// # Errors
+/// - Returns `IPCServerError::SendError` if the payload can't be serialized or the stream fails to write or flush.
+/// - Returns `IPCServerError::FrameTooLarge` if the payload is larger than 4 GiB.
+/// - Returns `IPCServerError::Timeout` if the other side did not take the frame in time.
 ///
 /// # Example
 /// ```no_run
 /// use super_v::services::clipboard_ipc_server::{create_default_stream, send_payload, Payload, CmdIPC, IPCRequest};
 /// let mut stream = create_default_stream().unwrap();
-/// send_payload(&mut stream, Payload::Request(IPCRequest{cmd: CmdIPC::Clear}));
+/// send_payload(&mut stream, Payload::Request(IPCRequest{cmd: CmdIPC::Clear})).unwrap();
 /// ```
-pub fn send_payload(stream: &mut UnixStream, item: Payload) {
+pub fn send_payload(stream: &mut UnixStream, item: Payload) -> Result<(), IPCServerError> {
     // Serialize command
-    let payload = item.to_payload();
+    let payload = item.to_payload()?;
 
     // Send len
     // We know the size of the length (4).
     // Using that, we can extract the length of actual message (x)
     // and read for that len.
     // This way sending message of changing length works.
-    stream.write_all(&payload.len).unwrap();
-
-    // Send data
-    stream.write_all(&payload.buf).unwrap();
-
-    // Ensure all buffer is written
-    stream.flush().unwrap();
+    stream
+        .write_all(&payload.len)
+        .and_then(|_| stream.write_all(&payload.buf))
+        .and_then(|_| stream.flush())
+        .map_err(|err| io_error(err, IPCServerError::SendError))
 }
 
 /// Reads and deserializes a `Payload` from a connected Unix stream.
@@ -204,28 +566,69 @@ pub fn send_payload(stream: &mut UnixStream, item: Payload) {
 /// - Reads the first 4 bytes as a big-endian `u32` payload length.
 /// - Reads the following bytes as the serialized payload.
 /// - Deserializes the payload into a `Payload` enum instance using MessagePack.
+/// - Refuses frames larger than `DEFAULT_MAX_FRAME_SIZE`, see `read_payload_limited`.
 ///
-/// # Panics
-/// - Panics if reading from the stream fails.
-/// - Panics if deserialization fails.
+/// # Errors
+/// - Returns `IPCServerError::RecieveError` if reading from the stream or deserialization fails,
+///   e.g. because the other side hung up.
+/// - Returns `IPCServerError::FrameTooLarge` if the frame is larger than allowed.
+/// - Returns `IPCServerError::Timeout` if nothing arrived within the read timeout of `stream`.
 ///
 /// # Example
 /// ```no_run
 /// use super_v::services::clipboard_ipc_server::{create_default_stream, read_payload};
 /// let mut stream = create_default_stream().unwrap();
-/// let payload = read_payload(&mut stream);
+/// let payload = read_payload(&mut stream).unwrap();
 /// println!("{:?}", payload);
 /// ```
-pub fn read_payload(stream: &mut UnixStream) -> Payload {
+pub fn read_payload(stream: &mut UnixStream) -> Result<Payload, IPCServerError> {
+    read_payload_limited(stream, DEFAULT_MAX_FRAME_SIZE)
+}
+
+/// Reads a `Payload` like `read_payload`, refusing frames larger than `max_frame_size` bytes.
+///
+/// The length is checked before the payload is read, and the buffer only grows as the bytes
+/// arrive, so a client can't make us allocate whatever it announces. The rest of a refused
+/// frame is left unread.
+///
+/// # Errors
+/// - Same as `read_payload`.
+pub fn read_payload_limited(
+    stream: &mut UnixStream,
+    max_frame_size: usize,
+) -> Result<Payload, IPCServerError> {
     // Read length of message (u32)
     let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf).unwrap();
+    stream
+        .read_exact(&mut len_buf)
+        .map_err(|err| io_error(err, IPCServerError::RecieveError))?;
     let req_len = u32::from_be_bytes(len_buf) as usize;
+    if req_len > max_frame_size {
+        return Err(IPCServerError::FrameTooLarge(req_len));
+    }
 
     // Read payload
-    let mut payload = vec![0u8; req_len];
-    stream.read_exact(&mut payload).unwrap();
+    let mut payload = Vec::new();
+    stream
+        .take(req_len as u64)
+        .read_to_end(&mut payload)
+        .map_err(|err| io_error(err, IPCServerError::RecieveError))?;
+    if payload.len() < req_len {
+        return Err(IPCServerError::RecieveError(format!(
+            "Frame ended after {} of {} bytes",
+            payload.len(),
+            req_len
+        )));
+    }
 
     // deserialize
-    rmp_serde::from_slice(&payload).expect("failed to deserialize")
+    rmp_serde::from_slice(&payload).map_err(|err| IPCServerError::RecieveError(err.to_string()))
+}
+
+// Timeouts surface as WouldBlock on Unix sockets
+fn io_error(err: io::Error, other: fn(String) -> IPCServerError) -> IPCServerError {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => IPCServerError::Timeout,
+        _ => other(err.to_string()),
+    }
 }
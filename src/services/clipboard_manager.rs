@@ -1,50 +1,149 @@
 // System Crates
 use std::{
-    fs::{File, OpenOptions, remove_file},
+    fs::{self, File, OpenOptions, Permissions, remove_file},
     io::Write,
-    os::unix::net::{UnixListener, UnixStream},
+    os::unix::{
+        fs::{self as unix_fs, OpenOptionsExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
     sync::{
-        Arc, Mutex,
+        Arc, Mutex, MutexGuard,
         atomic::{AtomicBool, Ordering},
     },
-    thread::{self, JoinHandle, sleep},
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
 // External Crates
 use arboard::Clipboard;
 use fs2::FileExt;
+use signal_hook::{consts::SIGHUP, flag, low_level};
 
 // My Crates
 use crate::{
-    common::{ClipboardItem, DaemonError, GetItem, LOCK_PATH, SOCKET_PATH},
-    history::ClipboardHistory,
-    services::clipboard_ipc_server::{
-        CmdIPC, IPCResponse, Payload, create_bind, read_payload, send_payload,
+    common::{
+        ClipboardBackend, ClipboardError, Clock, DaemonError, ErrorCode, IPCServerError,
+        StorageError, SystemClock, default_socket_path, lock_path,
+    },
+    config::ConfigFile,
+    history::{
+        ClipboardHistory, ExpiryPolicy, HistoryEntry, HistoryEvent, HistoryLimits, HistorySummary,
+    },
+    sensitive::{Classifier, RuleClassifier, SourcePolicy},
+    services::{
+        clipboard_ipc_server::{
+            AccessPolicy, CmdIPC, FrameLimits, IPCResponse, Payload, accept_handshake, create_bind,
+            read_payload_limited, send_payload,
+        },
+        clipboard_watcher::{SelectionWatcher, WatchOptions, detect_watcher, run_watch_loop},
+        subscribers::{SubscribeError, Subscribers},
     },
+    storage::HistoryStore,
 };
 
+/// # ManagerOptions
+/// Startup options for the Manager.
+///
+/// Fields:
+/// - store: Where the history is persisted between runs. `None` keeps history in memory only.
+/// - watcher: What tells the poller that the clipboard changed. `None` picks one for the session (see `detect_watcher`).
+/// - clipboard: The clipboard to watch. `None` opens the system clipboard.
+/// - watch: What gets recorded, e.g. whether the PRIMARY selection is tracked.
+/// - history_limits: How many unpinned items and bytes are kept, also per kind. Also applies to a loaded history.
+/// - expiry: How long entries are kept, also per kind. Expired entries of a loaded history are removed right away.
+/// - clock: Where the time for new entries and expiry comes from. `None` uses the system clock.
+/// - classifier: Flags secrets before they are recorded. `None` uses the built-in rules (see `RuleClassifier::builtin`).
+/// - sources: What happens to copies marked as concealed by password managers, and which apps are ignored.
+/// - config: Config file to reload on SIGHUP or when it changes. `None` keeps the options above for good.
+/// - socket: Where the IPC server listens. `None` uses `default_socket_path()`.
+/// - access: Which users may connect, and which of them may only read. Only the daemon's own user by default.
+/// - frame_limits: The largest request a client may send, and how long a read or write may block.
+pub struct ManagerOptions {
+    pub store: Option<HistoryStore>,
+    pub watcher: Option<Box<dyn SelectionWatcher>>,
+    pub clipboard: Option<Box<dyn ClipboardBackend>>,
+    pub watch: WatchOptions,
+    pub history_limits: HistoryLimits,
+    pub expiry: ExpiryPolicy,
+    pub clock: Option<Arc<dyn Clock>>,
+    pub classifier: Option<Arc<dyn Classifier>>,
+    pub sources: SourcePolicy,
+    pub config: Option<ConfigFile>,
+    pub socket: Option<PathBuf>,
+    pub access: AccessPolicy,
+    pub frame_limits: FrameLimits,
+}
+
+impl Default for ManagerOptions {
+    /// Persists history to the default location (see `HistoryStore::default_path`)
+    /// and detects the watcher when polling starts.
+    fn default() -> Self {
+        Self {
+            store: HistoryStore::default_path().map(HistoryStore::new),
+            watcher: None,
+            clipboard: None,
+            watch: WatchOptions::default(),
+            history_limits: HistoryLimits::new(Manager::DEFAULT_HISTORY_SIZE),
+            expiry: ExpiryPolicy::default(),
+            clock: None,
+            classifier: None,
+            sources: SourcePolicy::default(),
+            config: None,
+            socket: None,
+            access: AccessPolicy::default(),
+            frame_limits: FrameLimits::default(),
+        }
+    }
+}
+
 /// # Manager
 ///  Holds shared services and thread handles for the clipboard manager.
 ///
 /// Fields:
-/// - _clipboard_service: Arc-wrapped clipboard service used to read the system clipboard.
+/// - _clipboard_service: Arc-wrapped clipboard backend the history is recorded from.
 /// - _shared_history: Arc-wrapped ClipboardHistory shared between threads.
 /// - _stop_signal: Atomic flag used to request worker threads to stop.
+/// - _store: Optional on-disk store the history is written to after every change.
+/// - _watcher: Watcher handed to the polling thread when it starts.
+/// - _watch_options: What the polling thread records. Replaced when the config is reloaded.
+/// - _clock: Time source for new entries and expiry.
+/// - _classifier: Decides which new items are secrets and what happens to them. Replaced when the config is reloaded.
+/// - _source_policy: Decides by the source of new items, e.g. concealed password manager copies. Replaced when the config is reloaded.
+/// - _config: Config file the daemon reloads, if any.
+/// - _socket_path: Where the IPC server listens. The lock file is next to it.
+/// - _access: Checked against the peer credentials of every connection.
+/// - _frame_limits: Applied to every connection. Replaced when the config is reloaded.
+/// - _subscribers: Connections that get every change as a `HistoryEvent` (`CmdIPC::Subscribe`).
 /// - _polling_handle: Optional JoinHandle for the polling thread.
 /// - _command_handle: Optional JoinHandle for the command-handling thread.
+/// - _expiry_handle: Optional JoinHandle for the thread removing expired entries.
+/// - _config_handle: Optional JoinHandle for the thread reloading the config.
 ///
 /// These fields are internal to the implementation and not intended for public API use.
 /// Check implementation of Manager for usage.
 pub struct Manager {
     // Needed for operation
-    pub _clipboard_service: Arc<Mutex<Clipboard>>,
+    pub _clipboard_service: Arc<Mutex<Box<dyn ClipboardBackend>>>,
     pub _shared_history: Arc<Mutex<ClipboardHistory>>,
     pub _stop_signal: Arc<AtomicBool>,
+    pub _store: Option<Arc<HistoryStore>>,
+    pub _watcher: Option<Box<dyn SelectionWatcher>>,
+    pub _watch_options: Arc<Mutex<WatchOptions>>,
+    pub _clock: Arc<dyn Clock>,
+    pub _classifier: Arc<Mutex<Arc<dyn Classifier>>>,
+    pub _source_policy: Arc<Mutex<SourcePolicy>>,
+    pub _config: Option<ConfigFile>,
+    pub _socket_path: PathBuf,
+    pub _access: Arc<AccessPolicy>,
+    pub _frame_limits: Arc<Mutex<FrameLimits>>,
+    pub _subscribers: Arc<Subscribers>,
 
     // Thread handles
     pub _polling_handle: Option<JoinHandle<()>>,
     pub _command_handle: Option<JoinHandle<()>>,
+    pub _expiry_handle: Option<JoinHandle<()>>,
+    pub _config_handle: Option<JoinHandle<()>>,
 
     // Lock file to prevent multiple starts.
     pub _lock_file: Option<File>,
@@ -54,36 +153,50 @@ pub struct Manager {
 }
 
 impl Manager {
-    // Clipboard Size
-    const CLIPBOARD_SIZE: usize = 25;
+    /// How many unpinned items are kept unless `ManagerOptions::history_limits` says otherwise.
+    pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+    /// How often the expiry thread looks for expired entries.
+    pub const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// How often the config thread looks for a SIGHUP or a changed config file.
+    pub const CONFIG_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Create a new Manager with the default options.
+    ///
+    /// See `Manager::with_options` for details.
+    pub fn new() -> Result<Self, DaemonError> {
+        Self::with_options(ManagerOptions::default())
+    }
 
     /// Create a new Manager instance and configure global handlers.
     ///
     /// **Behavior**:
-    /// - Allocates a ClipboardHistory with a fixed capacity.
-    /// - Creates and wraps a Clipboard service in an Arc<Mutex<...>>.
+    /// - Loads the ClipboardHistory from the store, or creates an empty one, and applies `history_limits` to it.
+    /// - Applies the `expiry` policy and removes what already expired while the daemon was down.
+    /// - A corrupt state file is moved aside (`*.corrupt`) and the daemon starts with an empty history.
+    /// - Wraps the given clipboard backend, or the system clipboard, in an Arc<Mutex<...>>.
     /// - Creates an Arc<AtomicBool> stop signal used by worker threads.
     /// - Installs a ctrl-c handler that updates the stop signal.
-    /// - Has a process lock so duplicate processes can't be run.
+    /// - Has a process lock per socket so duplicate processes can't be run.
+    /// - Listens on `socket`, or on `default_socket_path()` so users sharing a machine each get their own daemon.
     ///
-    /// **Panics / errors**:
-    /// - This constructor unwraps the clipboard creation and will panic if the clipboard cannot be initialized.
+    /// **Errors**:
+    /// - Returns `DaemonError::ClipboardErr` if no backend is given and the system clipboard cannot be opened.
+    /// - Returns `DaemonError::StorageErr` if the state file exists but can't be read.
+    /// - Returns `DaemonError::IPCErr` if there is no private runtime directory, or the lock or socket can't be created.
     ///
     /// **Returns**:
     /// - A fully constructed Manager with no active thread handles.
-    pub fn new() -> Result<Self, DaemonError> {
-        // New history
-        let _shared_history: Arc<Mutex<ClipboardHistory>> =
-            Arc::new(Mutex::new(ClipboardHistory::new(Self::CLIPBOARD_SIZE)));
-
+    pub fn with_options(options: ManagerOptions) -> Result<Self, DaemonError> {
         // Clipboard service
-        let _clipboard_service: Arc<Mutex<Clipboard>> =
-            Arc::new(Mutex::new(match Clipboard::new() {
-                Ok(clipboard) => clipboard,
-                Err(err) => {
-                    panic!("ERROR: {:?}", err);
-                }
-            }));
+        let clipboard: Box<dyn ClipboardBackend> = match options.clipboard {
+            Some(clipboard) => clipboard,
+            None => Box::new(Clipboard::new().map_err(|err| {
+                DaemonError::ClipboardErr(ClipboardError::Unavailable(err.to_string()))
+            })?),
+        };
+        let _clipboard_service = Arc::new(Mutex::new(clipboard));
 
         // Stop signal
         let _stop_signal: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
@@ -95,13 +208,24 @@ impl Manager {
             ss_clone.store(true, Ordering::SeqCst);
         });
 
+        // One daemon per socket, and the default socket is per user
+        let _socket_path = match options.socket {
+            Some(socket_path) => socket_path,
+            None => default_socket_path().map_err(DaemonError::IPCErr)?,
+        };
+
         // Try lock
         let lock_file = OpenOptions::new()
             .create(true)
             .truncate(true)
             .write(true)
-            .open(LOCK_PATH)
-            .expect("Failed to open lock file");
+            .mode(0o600)
+            .open(lock_path(&_socket_path))
+            .map_err(|err| {
+                DaemonError::IPCErr(IPCServerError::BindError(format!(
+                    "Failed to open lock file: {err}"
+                )))
+            })?;
 
         // Return error if lock fails
         if lock_file.try_lock_exclusive().is_err() {
@@ -113,20 +237,65 @@ impl Manager {
         let _ = write!(&lock_file, "{}", std::process::id());
         let _ = lock_file.sync_all();
 
+        // Load the history only once we own the lock, so we never read a file another daemon is writing
+        let mut history = match &options.store {
+            Some(store) => Self::load_history(store)?,
+            None => ClipboardHistory::with_limits(options.history_limits.clone()),
+        };
+        history.set_limits(options.history_limits);
+        let _store = options.store.map(Arc::new);
+
+        // Nothing may outlive its expiry, including while the daemon was down
+        let _clock = options.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        history.set_expiry(options.expiry);
+        if !history.purge_expired(_clock.now()).is_empty() {
+            persist(&_store, &history);
+        }
+        let _shared_history: Arc<Mutex<ClipboardHistory>> = Arc::new(Mutex::new(history));
+
         // Once file lock is gotten, create a new IPC Server
         // But first clear the previous sock file. Since we know we are the main owner of the manager.
-        let _ = remove_file(SOCKET_PATH);
-        let _server = create_bind().map_err(DaemonError::IPCErr)?;
+        let _ = remove_file(&_socket_path);
+        let _server = create_bind(&_socket_path).map_err(DaemonError::IPCErr)?;
+
+        // Readers are other users, they reach the socket through its group.
+        // Other members of the group are kept out by the peer check.
+        if let Some(group) = options.access.group {
+            unix_fs::chown(&_socket_path, None, Some(group))
+                .and_then(|_| fs::set_permissions(&_socket_path, Permissions::from_mode(0o660)))
+                .map_err(|err| {
+                    DaemonError::IPCErr(IPCServerError::BindError(format!(
+                        "Could not give the socket to group {group}: {err}"
+                    )))
+                })?;
+        }
 
         // Return the manager object
         Ok(Self {
             _clipboard_service,
             _shared_history,
             _stop_signal,
+            _store,
+            _watcher: options.watcher,
+            _watch_options: Arc::new(Mutex::new(options.watch)),
+            _clock,
+            _classifier: Arc::new(Mutex::new(
+                options
+                    .classifier
+                    .unwrap_or_else(|| Arc::new(RuleClassifier::builtin())),
+            )),
+            _source_policy: Arc::new(Mutex::new(options.sources)),
+            _config: options.config,
+            _socket_path,
+            _access: Arc::new(options.access),
+            _frame_limits: Arc::new(Mutex::new(options.frame_limits)),
+            _subscribers: Arc::new(Subscribers::default()),
 
             // No handles yet.
             _polling_handle: None,
             _command_handle: None,
+            _expiry_handle: None,
+            _config_handle: None,
 
             // New Listener
             _lock_file: Some(lock_file),
@@ -136,16 +305,48 @@ impl Manager {
         })
     }
 
+    /// Read the persisted history, recovering from a corrupt file.
+    ///
+    /// **Behavior**:
+    /// - Missing file: starts with an empty history.
+    /// - Corrupt or unknown-version plaintext file: moves it aside and starts with an empty history.
+    /// - Any other error is returned so the daemon does not overwrite a file it could not read.
+    ///   This includes every failure of an encrypted store, e.g. a missing or wrong key.
+    fn load_history(store: &HistoryStore) -> Result<ClipboardHistory, DaemonError> {
+        match store.load() {
+            Ok(Some(history)) => Ok(history),
+            Ok(None) => Ok(ClipboardHistory::new(Self::DEFAULT_HISTORY_SIZE)),
+            Err(err @ (StorageError::Corrupt(_) | StorageError::UnsupportedVersion(_)))
+                if !store.is_encrypted() =>
+            {
+                match store.quarantine() {
+                    Ok(moved_to) => {
+                        eprintln!(
+                            "{err}. Moved it to {} and starting fresh.",
+                            moved_to.display()
+                        )
+                    }
+                    Err(move_err) => eprintln!("{err}. Could not move it aside: {move_err}"),
+                }
+                Ok(ClipboardHistory::new(Self::DEFAULT_HISTORY_SIZE))
+            }
+            Err(err) => Err(DaemonError::StorageErr(err)),
+        }
+    }
+
     /// Start the polling service in a new background thread.
     ///
     /// **Behavior**:
     /// - Returns early with a log if a polling thread is already running.
-    /// - Clones required Arcs for use inside the spawned thread.
-    /// - The thread repeatedly:
-    ///     * Sleeps for a fixed interval (500 ms).
-    ///     * Attempts to read the current clipboard item (falling back to an empty item on error).
-    ///     * Compares it with the last seen item and, if different, attempts to push it into ClipboardHistory.
-    /// - Uses try_lock on locks to avoid blocking other threads; if a lock is unavailable it skips that iteration.
+    /// - Takes the configured watcher, or detects one for the session (Wayland data-control, else polling).
+    /// - The thread runs `run_watch_loop`, which reads the clipboard only when the watcher reports a change
+    ///   and falls back to 100 ms polling if the watcher disconnects.
+    /// - New, non-empty items are pushed into ClipboardHistory, tagged with their selection, and persisted.
+    ///   Subscribers are told what changed (added, promoted or evicted entries).
+    /// - Items go through `_source_policy` and `_classifier` first, which may skip them, mask them or
+    ///   let them expire soon. If both flag an item, the stricter action wins.
+    /// - The PRIMARY selection is only recorded if `ManagerOptions::watch` asks for it.
+    /// - Uses try_lock on the history to avoid blocking other threads; if it is unavailable the item is retried.
     /// - Exits when the stop signal is set.
     ///
     /// **Notes**:
@@ -162,62 +363,65 @@ impl Manager {
         let clipboard_service = self._clipboard_service.clone();
         let stop_signal = self._stop_signal.clone();
         let shared_history = self._shared_history.clone();
+        let store = self._store.clone();
+        let watch_options = self._watch_options.clone();
+        let poll_interval = watch_options
+            .lock()
+            .map(|options| options.poll_interval)
+            .unwrap_or(WatchOptions::default().poll_interval);
+        let watcher = self
+            ._watcher
+            .take()
+            .unwrap_or_else(|| detect_watcher(poll_interval));
+        let clock = self._clock.clone();
+        let classifier = self._classifier.clone();
+        let source_policy = self._source_policy.clone();
+        let subscribers = self._subscribers.clone();
 
         // Start the polling in a thread and store the handle
         self._polling_handle = Some(thread::spawn(move || {
-            let empty_item = ClipboardItem::Text("".to_string());
-
-            // Get the current item in clipboard. This will be compared with and edited
-            let mut last_item = match clipboard_service.try_lock() {
-                Ok(mut unlocked_clipboard) => match unlocked_clipboard.get_item() {
-                    Ok(item) => item,
-                    Err(_) => empty_item.clone(),
-                },
-                Err(_) => empty_item.clone(),
-            };
-
-            while !stop_signal.load(Ordering::SeqCst) {
-                // Item Checking
-                let current_item = match clipboard_service.try_lock() {
-                    Ok(mut unlocked_clipboard) => match unlocked_clipboard.get_item() {
-                        Ok(item) => item,
-                        Err(_) => empty_item.clone(),
-                    },
-                    Err(_) => empty_item.clone(),
-                };
-
-                // This should be fine since _polling_service and _command_service both exist in the same process.
-                // So no need for thread-to-thread communication management and can purely focus on IPC management.
-                // Checks if item is new or not.
-                if current_item != last_item {
-                    // Check if the item is worth adding (not an empty text string)
-                    let is_empty_text = if let ClipboardItem::Text(text) = &current_item {
-                        text.trim().is_empty()
-                    } else {
-                        false // It's an Image, so it's not empty text
+            run_watch_loop(
+                watcher,
+                &clipboard_service,
+                &stop_signal,
+                &watch_options,
+                |item, origin, source| {
+                    let by_source = source_policy
+                        .lock()
+                        .ok()
+                        .and_then(|policy| policy.classify(source));
+                    let by_content = classifier
+                        .lock()
+                        .map(|classifier| classifier.clone())
+                        .ok()
+                        .and_then(|classifier| classifier.classify(&item));
+                    let secret = match (by_source, by_content) {
+                        (Some(by_source), Some(by_content)) => Some(by_source.stricter(by_content)),
+                        (by_source, by_content) => by_source.or(by_content),
                     };
 
-                    if !is_empty_text {
-                        // It's either an Image or non-empty Text.
-                        // Acquire Lock and add it.
-                        match shared_history.try_lock() {
-                            Ok(mut unlocked_history) => {
-                                // Add item to history
-                                unlocked_history.add(current_item.clone());
-
-                                // Update the last item within this
-                                last_item = current_item
-                                // So last item wont be written if mutex fails
-                            }
-                            Err(_) => { /* Failed To Get Lock, Skip */ }
+                    // This should be fine since _polling_service and _command_service both exist in the same process.
+                    // So no need for thread-to-thread communication management and can purely focus on IPC management.
+                    match shared_history.try_lock() {
+                        Ok(mut unlocked_history) => {
+                            let before = unlocked_history.ids();
+
+                            // Add item to history
+                            unlocked_history.add_copy(
+                                item,
+                                origin,
+                                clock.now(),
+                                source.app.clone(),
+                                secret,
+                            );
+                            persist(&store, &unlocked_history);
+                            subscribers.publish(&before.events_to(&unlocked_history.ids()));
+                            true
                         }
+                        Err(_) => false, /* Failed To Get Lock, Retry */
                     }
-                    // else: It's an empty text item, so we skip adding it.
-                }
-
-                // Poll every 100ms
-                sleep(Duration::from_millis(100));
-            }
+                },
+            );
         }));
     }
 
@@ -225,11 +429,20 @@ impl Manager {
     ///
     /// **Behavior**:
     /// - Listens for incoming IPC messages from external processes.
-    /// - Parses commands serialized as CmdIPC variants (e.g., Promote, Delete, Snapshot, Clear).
+    /// - Answers the protocol handshake of every connection (see `accept_handshake`) before reading its request.
+    /// - Checks the peer credentials of every connection against `_access`. Unknown users get a
+    ///   `ClipboardError::PermissionDenied` response, read-only users the same for commands that change anything.
+    /// - Applies `_frame_limits` to every connection. A request that is too large, malformed or doesn't
+    ///   arrive in time gets an `ErrorCode::BadRequest` response and the connection is closed.
+    /// - Parses commands serialized as CmdIPC variants (e.g., Promote, Delete, Snapshot, Clear, Pin).
     /// - Executes the requested operation on the shared ClipboardHistory instance.
+    /// - Restore writes an entry to the clipboard backend instead, with all of its flavours.
+    /// - Summary and Query answer with previews and thumbnails only, Fetch with one full entry.
+    /// - Subscribe answers with the summary and adds the connection to `_subscribers`, which get a
+    ///   `HistoryEvent` for every change made here, by the poller, the expiry or a config reload.
     /// - Constructs an IPCResponse containing:
     ///     - A current snapshot of the ClipboardHistory.
-    ///     - An optional message describing the operation result.
+    ///     - The result, with an `ErrorCode` if the command failed.
     /// - Sends the serialized IPCResponse back through IPC to the caller.
     ///
     /// **Notes**:
@@ -239,31 +452,104 @@ impl Manager {
         // Clone the items needed.
         let stop_signal_reader = self._stop_signal.clone();
         let shared_history: Arc<Mutex<ClipboardHistory>> = self._shared_history.clone();
+        let store = self._store.clone();
+        let clipboard_service = self._clipboard_service.clone();
+        let access_policy = self._access.clone();
+        let frame_limits = self._frame_limits.clone();
+        let subscribers = self._subscribers.clone();
 
         // Find another way to just own the server instead of cloning.
         let ipc_server = self._server.try_clone().unwrap();
 
         // Helper functions to send snapshot and err
         fn _send_snapshot(s: &mut UnixStream, snapshot: ClipboardHistory) {
-            send_payload(
+            let _ = send_payload(
+                s,
+                Payload::Response(IPCResponse {
+                    history_snapshot: Some(Box::new(snapshot)),
+                    ..Default::default()
+                }),
+            );
+        }
+
+        fn _send_summary(s: &mut UnixStream, summary: HistorySummary) {
+            let _ = send_payload(
                 s,
                 Payload::Response(IPCResponse {
-                    history_snapshot: Some(snapshot),
-                    message: None,
+                    summary: Some(summary),
+                    ..Default::default()
                 }),
             );
         }
 
-        fn _send_msg(s: &mut UnixStream, msg: &str) {
-            send_payload(
+        fn _send_entry(s: &mut UnixStream, entry: HistoryEntry) {
+            let _ = send_payload(
                 s,
                 Payload::Response(IPCResponse {
-                    history_snapshot: None,
-                    message: Some(msg.to_string()),
+                    entry: Some(Box::new(entry)),
+                    ..Default::default()
                 }),
             );
         }
 
+        fn _send_ok(s: &mut UnixStream) {
+            let _ = send_payload(s, Payload::Response(IPCResponse::default()));
+        }
+
+        fn _send_failure(s: &mut UnixStream, code: ErrorCode, msg: &str) {
+            let _ = send_payload(s, Payload::Response(IPCResponse::failure(code, msg)));
+        }
+
+        fn _send_err(s: &mut UnixStream, context: &str, err: ClipboardError) {
+            _send_failure(s, ErrorCode::from(&err), &format!("{} {}", context, err));
+        }
+
+        // Locks the history, or tells the client it couldn't
+        fn _lock_history<'a>(
+            history: &'a Mutex<ClipboardHistory>,
+            s: &mut UnixStream,
+        ) -> Option<MutexGuard<'a, ClipboardHistory>> {
+            match history.lock() {
+                Ok(history) => Some(history),
+                Err(_) => {
+                    _send_failure(s, ErrorCode::Locked, "Could not unlock history");
+                    None
+                }
+            }
+        }
+
+        // Applies `change` under the history lock, then persists, publishes and sends a snapshot.
+        // `change` may return the event to publish instead of the ones `HistoryIds::events_to` finds.
+        // On error, the client gets it with `context` and nothing is persisted or published.
+        fn _mutate(
+            history: &Mutex<ClipboardHistory>,
+            store: &Option<Arc<HistoryStore>>,
+            subscribers: &Subscribers,
+            s: &mut UnixStream,
+            context: &str,
+            change: impl FnOnce(&mut ClipboardHistory) -> Result<Option<HistoryEvent>, ClipboardError>,
+        ) {
+            let Some(mut history) = _lock_history(history, s) else {
+                return;
+            };
+            let before = history.ids();
+            match change(&mut history) {
+                Ok(event) => {
+                    persist(store, &history);
+                    match event {
+                        Some(event) => subscribers.publish(&[event]),
+                        None => subscribers.publish(&before.events_to(&history.ids())),
+                    }
+
+                    // Create snapshot, drop guard, send snapshot
+                    let snapshot = history.without_secrets();
+                    drop(history);
+                    _send_snapshot(s, snapshot);
+                }
+                Err(err) => _send_err(s, context, err),
+            }
+        }
+
         // Run the command service in a new thread
         // The thread will consume the only UnixListener (since it's not an Arc) which is fine
         // Then it will listen for streams which send CmdIpc as Payload
@@ -281,128 +567,265 @@ impl Manager {
                 match stream {
                     Ok(mut s) => {
                         let history_for_thread = shared_history.clone();
+                        let store = store.clone();
+                        let clipboard_for_thread = clipboard_service.clone();
+                        let access_policy = access_policy.clone();
+                        let subscribers = subscribers.clone();
+                        let limits = frame_limits
+                            .lock()
+                            .map(|limits| *limits)
+                            .unwrap_or_default();
 
                         // Handle payload in another thread
                         thread::spawn(move || {
-                            // Read the payload
-                            let payload = read_payload(&mut s);
+                            // A client that stalls must not keep this thread forever
+                            if limits.apply(&s).is_err() {
+                                return;
+                            }
+
+                            // Only users the policy knows get to send anything
+                            let access = match access_policy.authorize(&s) {
+                                Ok(access) => access,
+                                Err(err) => {
+                                    _send_err(&mut s, "Connection refused.", err);
+                                    return;
+                                }
+                            };
+
+                            // Both sides have to speak the same protocol version.
+                            // The client has its answer already, nothing more to do here.
+                            if accept_handshake(&mut s, &limits).is_err() {
+                                return;
+                            }
+
+                            // Read the payload. Whatever can't be read gets an answer, not a panic.
+                            let payload =
+                                match read_payload_limited(&mut s, limits.max_request_size) {
+                                    Ok(payload) => payload,
+                                    Err(err) => {
+                                        _send_failure(
+                                            &mut s,
+                                            ErrorCode::BadRequest,
+                                            &format!("Could not read the request: {err}"),
+                                        );
+                                        return;
+                                    }
+                                };
 
                             // Match the payload and execute command
                             match payload {
                                 Payload::Request(ipc_request) => {
+                                    // Read-only clients may look, but not change anything
+                                    if !access.allows(&ipc_request.cmd) {
+                                        _send_err(
+                                            &mut s,
+                                            "Command refused.",
+                                            ClipboardError::PermissionDenied(
+                                                "this client may only read the history".into(),
+                                            ),
+                                        );
+                                        return;
+                                    }
+
                                     match ipc_request.cmd {
                                         CmdIPC::Clear => {
-                                            // Get mutex guard
-                                            match history_for_thread.lock() {
-                                                Ok(mut unlocked_history) => {
-                                                    // Clear the history
-                                                    unlocked_history.clear();
-
-                                                    // Create snapshot, drop guard, send snapshot
-                                                    let snapshot = unlocked_history.clone();
-                                                    _send_snapshot(&mut s, snapshot);
-                                                }
-                                                Err(_) => {
-                                                    _send_msg(&mut s, "Could not unlock history");
+                                            _mutate(
+                                                &history_for_thread,
+                                                &store,
+                                                &subscribers,
+                                                &mut s,
+                                                "Could not clear history.",
+                                                |history| {
+                                                    // Clearing is one event, not a deletion per entry
+                                                    let cleared = history.ids().items;
+                                                    history.clear();
+                                                    Ok((!cleared.is_empty())
+                                                        .then_some(HistoryEvent::Cleared(cleared)))
+                                                },
+                                            );
+                                        }
+                                        CmdIPC::Delete(id) => {
+                                            _mutate(
+                                                &history_for_thread,
+                                                &store,
+                                                &subscribers,
+                                                &mut s,
+                                                "Could not delete item.",
+                                                |history| history.delete_by_id(id).map(|_| None),
+                                            );
+                                        }
+                                        CmdIPC::Promote(id) => {
+                                            _mutate(
+                                                &history_for_thread,
+                                                &store,
+                                                &subscribers,
+                                                &mut s,
+                                                "Could not promote item.",
+                                                |history| history.promote_by_id(id).map(|_| None),
+                                            );
+                                        }
+                                        CmdIPC::Pin(id) => {
+                                            _mutate(
+                                                &history_for_thread,
+                                                &store,
+                                                &subscribers,
+                                                &mut s,
+                                                "Could not pin item.",
+                                                |history| history.pin_by_id(id).map(|_| None),
+                                            );
+                                        }
+                                        CmdIPC::Unpin(id) => {
+                                            _mutate(
+                                                &history_for_thread,
+                                                &store,
+                                                &subscribers,
+                                                &mut s,
+                                                "Could not unpin item.",
+                                                |history| history.unpin_by_id(id).map(|_| None),
+                                            );
+                                        }
+                                        CmdIPC::Restore(id) => {
+                                            // Copy the item out, the clipboard isn't written under the history lock
+                                            let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            else {
+                                                return;
+                                            };
+                                            let item = unlocked_history
+                                                .get_by_id(id)
+                                                .map(|entry| entry.item.clone())
+                                                .ok_or(ClipboardError::ItemNotFound(id));
+                                            drop(unlocked_history);
+
+                                            // The poller sees the new clipboard content and promotes the entry
+                                            let restored = item.and_then(|item| {
+                                                match clipboard_for_thread.lock() {
+                                                    Ok(mut clipboard) => clipboard.set_item(item),
+                                                    Err(_) => Err(ClipboardError::Unavailable(
+                                                        "Clipboard lock poisoned".to_string(),
+                                                    )),
                                                 }
+                                            });
+
+                                            match restored {
+                                                Ok(_) => _send_ok(&mut s),
+                                                Err(err) => _send_err(
+                                                    &mut s,
+                                                    "Could not restore item.",
+                                                    err,
+                                                ),
                                             }
                                         }
-                                        CmdIPC::Delete(pos) => {
-                                            // Get mutex guard
-                                            match history_for_thread.lock() {
-                                                Ok(mut unlocked_history) => {
-                                                    // Delete the item
-                                                    match unlocked_history.delete(pos) {
-                                                        Ok(_) => {
-                                                            // Create snapshot, drop guard, send snapshot
-                                                            let snapshot = unlocked_history.clone();
-                                                            _send_snapshot(&mut s, snapshot);
-                                                        }
-                                                        Err(_) => {
-                                                            _send_msg(
-                                                                &mut s,
-                                                                "Could not delete item. Index out of bounds.",
-                                                            );
-                                                        }
-                                                    };
-                                                }
-                                                Err(_) => {
-                                                    _send_msg(&mut s, "Could not unlock history");
-                                                }
+                                        CmdIPC::Snapshot => {
+                                            if let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            {
+                                                // Create snapshot, drop guard, send snapshot
+                                                let snapshot = unlocked_history.without_secrets();
+                                                drop(unlocked_history);
+                                                _send_snapshot(&mut s, snapshot);
                                             }
                                         }
-                                        CmdIPC::DeleteThis(item) => {
-                                            // Get mutex guard
-                                            match history_for_thread.lock() {
-                                                Ok(mut unlocked_history) => {
-                                                    // Delete the item
-                                                    match unlocked_history.delete_this(item) {
-                                                        Ok(_) => {
-                                                            // Create snapshot, drop guard, send snapshot
-                                                            let snapshot = unlocked_history.clone();
-                                                            _send_snapshot(&mut s, snapshot);
-                                                        }
-                                                        Err(_) => {
-                                                            _send_msg(
-                                                                &mut s,
-                                                                "Could not delete item. Index out of bounds.",
-                                                            );
-                                                        }
-                                                    };
-                                                }
-                                                Err(_) => {
-                                                    _send_msg(&mut s, "Could not unlock history");
-                                                }
+                                        CmdIPC::Summary => {
+                                            if let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            {
+                                                // Summarize, drop guard, send summary
+                                                let summary = unlocked_history.summary();
+                                                drop(unlocked_history);
+                                                _send_summary(&mut s, summary);
                                             }
                                         }
-                                        CmdIPC::Promote(pos) => {
-                                            // Get mutex guard
-                                            match history_for_thread.lock() {
-                                                Ok(mut unlocked_history) => {
-                                                    // Promote the item
-                                                    match unlocked_history.promote(pos) {
-                                                        Ok(_) => {
-                                                            // Create snapshot, drop guard, send snapshot
-                                                            let snapshot = unlocked_history.clone();
-                                                            _send_snapshot(&mut s, snapshot);
-                                                        }
-                                                        Err(_) => {
-                                                            _send_msg(
-                                                                &mut s,
-                                                                "Could not promote item. Index out of bounds.",
-                                                            );
-                                                        }
-                                                    };
-                                                }
-                                                Err(_) => {
-                                                    _send_msg(&mut s, "Could not unlock history");
-                                                }
+                                        CmdIPC::Query(query) => {
+                                            if let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            {
+                                                // Search, drop guard, send the matches
+                                                let summary = unlocked_history.query(&query);
+                                                drop(unlocked_history);
+                                                _send_summary(&mut s, summary);
                                             }
                                         }
-                                        CmdIPC::Snapshot => {
-                                            // Get mutex guard
-                                            match history_for_thread.lock() {
-                                                Ok(unlocked_history) => {
-                                                    // Create snapshot, drop guard, send snapshot
-                                                    let snapshot = unlocked_history.clone();
-                                                    _send_snapshot(&mut s, snapshot);
+                                        CmdIPC::Fetch(id) | CmdIPC::Reveal(id) => {
+                                            let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            else {
+                                                return;
+                                            };
+
+                                            // Only the owner gets the text of a secret, and only when asking for it
+                                            let reveal =
+                                                matches!(ipc_request.cmd, CmdIPC::Reveal(_));
+                                            let entry =
+                                                unlocked_history.get_by_id(id).map(|entry| {
+                                                    match reveal {
+                                                        true => entry.clone(),
+                                                        false => entry.without_secret(),
+                                                    }
+                                                });
+                                            drop(unlocked_history);
+                                            match entry {
+                                                Some(entry) => _send_entry(&mut s, entry),
+                                                None => _send_err(
+                                                    &mut s,
+                                                    "Could not fetch item.",
+                                                    ClipboardError::ItemNotFound(id),
+                                                ),
+                                            }
+                                        }
+                                        CmdIPC::Subscribe => {
+                                            let Some(unlocked_history) =
+                                                _lock_history(&history_for_thread, &mut s)
+                                            else {
+                                                return;
+                                            };
+
+                                            // Changes are published under the history lock,
+                                            // so none fall between the summary and the first event
+                                            let summary = Payload::Response(IPCResponse {
+                                                summary: Some(unlocked_history.summary()),
+                                                ..Default::default()
+                                            });
+                                            let subscribed = s
+                                                .try_clone()
+                                                .map_err(SubscribeError::from)
+                                                .and_then(|subscriber| {
+                                                    subscribers.subscribe(subscriber, summary)
+                                                });
+                                            drop(unlocked_history);
+                                            match subscribed {
+                                                Ok(()) => {}
+                                                Err(err @ SubscribeError::Full(_)) => {
+                                                    _send_failure(
+                                                        &mut s,
+                                                        ErrorCode::Busy,
+                                                        &err.to_string(),
+                                                    );
                                                 }
-                                                Err(_) => {
-                                                    // Send err if could not unlock
-                                                    _send_msg(&mut s, "Could not unlock history");
+                                                Err(err) => {
+                                                    eprintln!("{err}");
+                                                    _send_failure(
+                                                        &mut s,
+                                                        ErrorCode::Unavailable,
+                                                        &err.to_string(),
+                                                    );
                                                 }
                                             }
                                         }
                                         CmdIPC::Stop => {
                                             stop_signal_writer.store(true, Ordering::SeqCst);
-                                            _send_msg(&mut s, "Stop Signal recieved.");
+                                            _send_ok(&mut s);
                                         }
                                     }
                                 }
-                                Payload::Response(_) => {
-                                    _send_msg(
+                                Payload::Response(_)
+                                | Payload::Hello(_)
+                                | Payload::Event(_)
+                                | Payload::Lagged(_) => {
+                                    _send_failure(
                                         &mut s,
-                                        "Wrong Payload type recieved. Expected CmdIpc but got IPCResponse.",
+                                        ErrorCode::BadRequest,
+                                        "Wrong Payload type recieved. Expected CmdIpc.",
                                     );
                                 }
                             }
@@ -416,11 +839,142 @@ impl Manager {
         }));
     }
 
+    /// Start the expiry service in a new background thread.
+    ///
+    /// **Behavior**:
+    /// - Returns early with a log if an expiry thread is already running.
+    /// - Every `EXPIRY_CHECK_INTERVAL`, removes the entries past the expiry policy at the time of `_clock`.
+    /// - If anything expired, persists the history and sends subscribers a `HistoryEvent::Expired` with the ids.
+    /// - Exits when the stop signal is set.
+    ///
+    /// **Notes**:
+    /// - This function stores the JoinHandle in _expiry_handle.
+    pub fn _expiry_service(&mut self) {
+        // Check if expiry thread is already started
+        let None = self._expiry_handle else {
+            eprintln!("Expiry service is already running");
+            return;
+        };
+
+        // Create clones of the Arc items needed.
+        let stop_signal = self._stop_signal.clone();
+        let shared_history = self._shared_history.clone();
+        let store = self._store.clone();
+        let clock = self._clock.clone();
+        let subscribers = self._subscribers.clone();
+
+        self._expiry_handle = Some(thread::spawn(move || {
+            while !stop_signal.load(Ordering::SeqCst) {
+                thread::sleep(Self::EXPIRY_CHECK_INTERVAL);
+
+                if let Ok(mut unlocked_history) = shared_history.lock() {
+                    let expired = unlocked_history.purge_expired(clock.now());
+                    if expired.is_empty() {
+                        continue;
+                    }
+                    persist(&store, &unlocked_history);
+                    subscribers.publish(&[HistoryEvent::Expired(expired)]);
+                }
+            }
+        }));
+    }
+
+    /// Start the config service in a new background thread.
+    ///
+    /// **Behavior**:
+    /// - Returns early if there is no config file, or with a log if a config thread is already running.
+    /// - Every `CONFIG_CHECK_INTERVAL`, reloads the config if the daemon got a SIGHUP or the file changed
+    ///   (it was written, replaced, created or removed). A removed file means the defaults.
+    /// - A reloaded config replaces the history limits, expiry policy, secret rules, source policy,
+    ///   watch options and frame limits. The history is kept, only what no longer fits the limits is evicted.
+    ///   Entries past the new expiry are removed by the expiry service.
+    /// - A config that doesn't load or validate is logged and the current settings stay.
+    /// - Exits when the stop signal is set.
+    ///
+    /// **Notes**:
+    /// - This function stores the JoinHandle in _config_handle.
+    /// - The SIGHUP handler is installed by this thread. Without a config file SIGHUP ends the daemon as usual.
+    /// - The watcher picked at startup stays. A new `poll_interval` applies right away if it polls.
+    pub fn _config_service(&mut self) {
+        let Some(config_file) = self._config.clone() else {
+            return;
+        };
+
+        // Check if config thread is already started
+        let None = self._config_handle else {
+            eprintln!("Config service is already running");
+            return;
+        };
+
+        // Create clones of the Arc items needed.
+        let stop_signal = self._stop_signal.clone();
+        let shared_history = self._shared_history.clone();
+        let store = self._store.clone();
+        let watch_options = self._watch_options.clone();
+        let classifier = self._classifier.clone();
+        let source_policy = self._source_policy.clone();
+        let frame_limits = self._frame_limits.clone();
+        let subscribers = self._subscribers.clone();
+
+        self._config_handle = Some(thread::spawn(move || {
+            let hangup = Arc::new(AtomicBool::new(false));
+            let hangup_handler = flag::register(SIGHUP, hangup.clone())
+                .map_err(|err| eprintln!("Could not listen for SIGHUP: {err}"))
+                .ok();
+            let mut stamp = config_file.stamp();
+
+            while !stop_signal.load(Ordering::SeqCst) {
+                thread::sleep(Self::CONFIG_CHECK_INTERVAL);
+
+                let new_stamp = config_file.stamp();
+                if !hangup.swap(false, Ordering::SeqCst) && new_stamp == stamp {
+                    continue;
+                }
+                stamp = new_stamp;
+
+                let config = match config_file.load() {
+                    Ok(config) => config,
+                    Err(err) => {
+                        eprintln!("{err}. Keeping the current settings.");
+                        continue;
+                    }
+                };
+
+                if let Ok(mut unlocked_history) = shared_history.lock() {
+                    let before = unlocked_history.ids();
+                    unlocked_history.set_limits(config.history_limits());
+                    unlocked_history.set_expiry(config.expiry());
+                    persist(&store, &unlocked_history);
+                    subscribers.publish(&before.events_to(&unlocked_history.ids()));
+                }
+                if let Ok(mut options) = watch_options.lock() {
+                    *options = config.watch_options();
+                }
+                if let Ok(mut classifier) = classifier.lock() {
+                    *classifier = Arc::new(config.classifier());
+                }
+                if let Ok(mut policy) = source_policy.lock() {
+                    *policy = config.source_policy();
+                }
+                if let Ok(mut limits) = frame_limits.lock() {
+                    *limits = config.frame_limits();
+                }
+                eprintln!("Reloaded {}", config_file.path.display());
+            }
+
+            if let Some(handler) = hangup_handler {
+                low_level::unregister(handler);
+            }
+        }));
+    }
+
     /// Start all configured background services.
     ///
     /// **Behavior**:
     /// - Calls _polling_service to start the clipboard poller.
     /// - Calls _command_service to start command handling.
+    /// - Calls _expiry_service to start removing expired entries.
+    /// - Calls _config_service to start reloading the config file, if there is one.
     /// - Each service checks whether it is already running and will not start duplicate
     pub fn start_daemon(&mut self) {
         // Start the polling service
@@ -429,6 +983,12 @@ impl Manager {
         // Start the command service
         self._command_service();
 
+        // Start the expiry service
+        self._expiry_service();
+
+        // Start the config service
+        self._config_service();
+
         // Clone a stop signal
         let daemon_stop_signal = self._stop_signal.clone();
 
@@ -458,6 +1018,8 @@ impl Manager {
         // Take the handles
         let _polling_handle = self._polling_handle.take();
         let _command_handle = self._command_handle.take();
+        let _expiry_handle = self._expiry_handle.take();
+        let _config_handle = self._config_handle.take();
 
         // Spawn a short-lived thread to join them so main thread is not blocked
         // All errors are swallowed
@@ -468,14 +1030,40 @@ impl Manager {
             if let Some(h) = _command_handle {
                 let _ = h.join();
             }
+            if let Some(h) = _expiry_handle {
+                let _ = h.join();
+            }
+            if let Some(h) = _config_handle {
+                let _ = h.join();
+            }
         });
 
+        // Subscribers see the connection close once they have their queued events
+        self._subscribers.close();
+
+        // Final write so nothing is lost on shutdown
+        if let Ok(history) = self._shared_history.lock() {
+            persist(&self._store, &history);
+        }
+
         // Unlock the lock file
         // Swallows the error.
         if let Some(lockfile) = &self._lock_file {
             let _ = lockfile.unlock();
-            let _ = remove_file(SOCKET_PATH);
-            let _ = remove_file(LOCK_PATH);
+            let _ = remove_file(&self._socket_path);
+            let _ = remove_file(lock_path(&self._socket_path));
         }
     }
 }
+
+/// Write the history to the store, if persistence is enabled.
+///
+/// Errors are logged and otherwise ignored so a full disk never takes the daemon down.
+/// Callers hold the history lock while calling this, which keeps writes in order.
+fn persist(store: &Option<Arc<HistoryStore>>, history: &ClipboardHistory) {
+    if let Some(store) = store
+        && let Err(err) = store.save(history)
+    {
+        eprintln!("Could not save history: {err}");
+    }
+}
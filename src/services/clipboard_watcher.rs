@@ -0,0 +1,357 @@
+// System Crates
+use std::{
+    env,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+// My Crates
+use crate::{
+    common::{ClipboardBackend, ClipboardItem, ClipboardSource, Selection},
+    services::{wayland_watcher::WaylandWatcher, x11_watcher::X11Watcher},
+};
+
+// ----------------------- Watcher Items -----------------------------
+/// Events a SelectionWatcher reports to the watch loop.
+///
+/// **Available**:
+/// * **Changed(Selection)** - The selection (may have) changed. The loop re-reads the clipboard.
+/// * **Idle** - Nothing happened before the timeout. The loop only checks the stop signal.
+/// * **Disconnected** - The event source is gone. The loop falls back to polling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchEvent {
+    Changed(Selection),
+    Idle,
+    Disconnected,
+}
+
+/// A source of clipboard change notifications.
+///
+/// Implementations only tell the watch loop *when* to read the clipboard,
+/// reading itself goes through `ClipboardBackend`. That keeps backends small and lets
+/// a scripted watcher drive the loop in tests.
+pub trait SelectionWatcher: Send {
+    /// Waits for the next event, but no longer than `timeout`.
+    ///
+    /// # Returns
+    ///
+    /// * `WatchEvent::Idle` if nothing happened within `timeout`
+    fn wait(&mut self, timeout: Duration) -> WatchEvent;
+
+    /// Short name for logs, e.g. "polling" or "wayland".
+    fn name(&self) -> &'static str;
+
+    /// MIME types offered with the last change of `selection`, for watchers that are told
+    /// them along with the change. They are added to what the `ClipboardBackend` reports.
+    ///
+    /// Watchers that aren't told return `None`.
+    fn offered_types(&self, _selection: Selection) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Changes how often a polling watcher ticks. Watchers that don't poll ignore it.
+    fn set_poll_interval(&mut self, _interval: Duration) {}
+}
+
+/// # PollingWatcher
+/// Fallback watcher that reports a (possible) change on every tick.
+///
+/// The watch loop compares every read with the last item, so this behaves
+/// exactly like the old fixed interval poll. Every clipboard tick is followed
+/// by a PRIMARY tick, which the loop ignores unless it tracks PRIMARY.
+pub struct PollingWatcher {
+    interval: Duration,
+
+    // Report PRIMARY on the next call, without waiting
+    primary_next: bool,
+}
+
+impl PollingWatcher {
+    /// Creates a watcher that ticks every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            primary_next: false,
+        }
+    }
+}
+
+impl Default for PollingWatcher {
+    /// Ticks every 100ms.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+impl SelectionWatcher for PollingWatcher {
+    fn wait(&mut self, timeout: Duration) -> WatchEvent {
+        if self.primary_next {
+            self.primary_next = false;
+            return WatchEvent::Changed(Selection::Primary);
+        }
+
+        if timeout < self.interval {
+            sleep(timeout);
+            return WatchEvent::Idle;
+        }
+
+        sleep(self.interval);
+        self.primary_next = true;
+        WatchEvent::Changed(Selection::Clipboard)
+    }
+
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+
+    fn set_poll_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+}
+// -------------------------------------------------------------------
+
+// ------------------------ Watch Loop -------------------------------
+/// How long the loop waits for an event before checking the stop signal.
+pub const WATCH_TICK: Duration = Duration::from_millis(100);
+
+/// # WatchOptions
+/// What the watch loop records.
+///
+/// Fields:
+/// - track_primary: Also record the PRIMARY selection (off by default).
+/// - primary_debounce: How long PRIMARY has to stay unchanged before it is recorded.
+///   Dragging a selection changes PRIMARY many times, only the final selection is kept.
+/// - poll_interval: Tick of the `PollingWatcher` used when no change events are available.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchOptions {
+    pub track_primary: bool,
+    pub primary_debounce: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchOptions {
+    /// Clipboard only. PRIMARY, if enabled, is debounced by 500ms. Polls every 100ms.
+    fn default() -> Self {
+        Self {
+            track_primary: false,
+            primary_debounce: Duration::from_millis(500),
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Picks the best watcher for the current session.
+///
+/// **Behavior**:
+/// - Wayland session: the data-control watcher, else XFixes through XWayland.
+/// - X11 session: the XFixes watcher.
+/// - The session type comes from `XDG_SESSION_TYPE`, or `WAYLAND_DISPLAY` / `DISPLAY` if that is unset.
+/// - Falls back to a `PollingWatcher` ticking every `poll_interval` when nothing else works, logging why.
+pub fn detect_watcher(poll_interval: Duration) -> Box<dyn SelectionWatcher> {
+    let session = env::var("XDG_SESSION_TYPE").unwrap_or_default();
+    let wayland =
+        session == "wayland" || (session.is_empty() && env::var_os("WAYLAND_DISPLAY").is_some());
+
+    if wayland {
+        match WaylandWatcher::connect() {
+            Ok(watcher) => return Box::new(watcher),
+            Err(err) => eprintln!("Wayland watcher: {}", err),
+        }
+    }
+
+    // Also covers XWayland, which mirrors the Wayland clipboard on compositors without data-control
+    if env::var_os("DISPLAY").is_some() {
+        match X11Watcher::connect(None) {
+            Ok(watcher) => return Box::new(watcher),
+            Err(err) => eprintln!("X11 watcher: {}", err),
+        }
+    }
+
+    eprintln!("No clipboard events available. Falling back to polling.");
+    Box::new(PollingWatcher::new(poll_interval))
+}
+
+/// Reads the clipboard whenever `watcher` reports a change and hands new items to `on_item`.
+///
+/// **Behavior**:
+/// - Remembers the items on the clipboard and PRIMARY at startup so they are not recorded again.
+/// - Skips empty text and items equal to the last recorded one of the same selection.
+/// - With `track_primary`, records PRIMARY once it stayed unchanged for `primary_debounce`.
+/// - New items of either selection come with their `ClipboardSource` (offered MIME types and
+///   owning app), so concealed copies can be told apart. The owner is read before the content.
+/// - `on_item` returns whether the item was stored. If not (e.g. history was locked),
+///   the read is retried on the next tick.
+/// - Switches to `PollingWatcher` when the watcher reports `Disconnected`.
+/// - `options` are read again on every tick, so they can be changed while the loop runs.
+///   PRIMARY tracking that is switched on records the current selection once it settles,
+///   and a new `poll_interval` applies to a polling watcher from its next tick.
+/// - Returns once `stop_signal` is set.
+///
+/// # Arguments
+///
+/// * `watcher` - Source of change notifications
+/// * `clipboard` - Clipboard to read from
+/// * `stop_signal` - Flag checked at least every `WATCH_TICK`
+/// * `options` - What to record
+/// * `on_item` - Called with every new item, the selection it came from and who copied it
+pub fn run_watch_loop<C: ClipboardBackend>(
+    mut watcher: Box<dyn SelectionWatcher>,
+    clipboard: &Mutex<C>,
+    stop_signal: &AtomicBool,
+    options: &Mutex<WatchOptions>,
+    mut on_item: impl FnMut(ClipboardItem, Selection, &ClipboardSource) -> bool,
+) {
+    let current_options = || {
+        *options
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    };
+    let empty_item = ClipboardItem::Text("".to_string());
+
+    // Get the current item in clipboard. This will be compared with and edited
+    let mut last_item = read_item(clipboard, Selection::Clipboard).unwrap_or(empty_item.clone());
+    let mut last_primary = if current_options().track_primary {
+        read_item(clipboard, Selection::Primary).unwrap_or(empty_item)
+    } else {
+        empty_item
+    };
+
+    // Set while a change has been seen but not recorded yet
+    let mut dirty = false;
+
+    // A PRIMARY selection that is still settling, who selected it, and when it last changed
+    let mut pending_primary: Option<(ClipboardItem, ClipboardSource, Instant)> = None;
+
+    while !stop_signal.load(Ordering::SeqCst) {
+        let options = current_options();
+        watcher.set_poll_interval(options.poll_interval);
+        match watcher.wait(WATCH_TICK) {
+            WatchEvent::Changed(Selection::Clipboard) => dirty = true,
+            WatchEvent::Changed(Selection::Primary) if options.track_primary => {
+                if let Some((current, mut source)) =
+                    read_copy(clipboard, Selection::Primary, &last_primary)
+                {
+                    add_offered_types(&mut source, watcher.offered_types(Selection::Primary));
+                    pending_primary =
+                        settle_primary(pending_primary.take(), current, source, &last_primary);
+                }
+            }
+            WatchEvent::Changed(Selection::Primary) | WatchEvent::Idle => {}
+            WatchEvent::Disconnected => {
+                eprintln!(
+                    "Clipboard watcher '{}' disconnected. Falling back to polling.",
+                    watcher.name()
+                );
+                watcher = Box::new(PollingWatcher::new(options.poll_interval));
+                continue;
+            }
+        }
+
+        // PRIMARY tracking was switched off while a selection was settling
+        if !options.track_primary {
+            pending_primary = None;
+        }
+
+        // Record PRIMARY once the selection stopped moving
+        if let Some((item, source, changed_at)) = &pending_primary
+            && changed_at.elapsed() >= options.primary_debounce
+            && on_item(item.clone(), Selection::Primary, source)
+        {
+            last_primary = item.clone();
+            pending_primary = None;
+        }
+
+        if !dirty {
+            continue;
+        }
+
+        // Clipboard busy, or its owner changed while reading. Try again next tick.
+        let Some((current_item, mut source)) =
+            read_copy(clipboard, Selection::Clipboard, &last_item)
+        else {
+            continue;
+        };
+
+        if current_item == last_item || is_empty_text(&current_item) {
+            dirty = false;
+            continue;
+        }
+
+        add_offered_types(&mut source, watcher.offered_types(Selection::Clipboard));
+
+        // So last item wont be written if storing fails
+        if on_item(current_item.clone(), Selection::Clipboard, &source) {
+            last_item = current_item;
+            dirty = false;
+        }
+    }
+}
+
+/// Next pending PRIMARY selection after reading `current`.
+///
+/// The debounce timer only restarts when the content actually changed, so
+/// repeated events for the same selection (e.g. from polling) let it settle.
+fn settle_primary(
+    pending: Option<(ClipboardItem, ClipboardSource, Instant)>,
+    current: ClipboardItem,
+    source: ClipboardSource,
+    last_primary: &ClipboardItem,
+) -> Option<(ClipboardItem, ClipboardSource, Instant)> {
+    if &current == last_primary || is_empty_text(&current) {
+        return None;
+    }
+
+    match pending {
+        Some((item, _, changed_at)) if item == current => Some((item, source, changed_at)),
+        _ => Some((current, source, Instant::now())),
+    }
+}
+
+/// Whether the item is empty or whitespace-only text, which is never worth adding.
+fn is_empty_text(item: &ClipboardItem) -> bool {
+    if let ClipboardItem::Text(text) = item {
+        text.trim().is_empty()
+    } else {
+        false // It's an Image, so it's not empty text
+    }
+}
+
+/// Reads a selection. An empty/unreadable selection reads as empty text.
+///
+/// Returns `None` if the clipboard is locked by someone else.
+fn read_item<C: ClipboardBackend>(
+    clipboard: &Mutex<C>,
+    selection: Selection,
+) -> Option<ClipboardItem> {
+    let mut unlocked_clipboard = clipboard.try_lock().ok()?;
+    let item = match selection {
+        Selection::Clipboard => unlocked_clipboard.get_item(),
+        Selection::Primary => unlocked_clipboard.get_primary(),
+    };
+    Some(item.unwrap_or(ClipboardItem::Text("".to_string())))
+}
+
+/// Adds the MIME types a watcher was told about to those the clipboard reported.
+fn add_offered_types(source: &mut ClipboardSource, offered: Option<Vec<String>>) {
+    for mime_type in offered.into_iter().flatten() {
+        if !source.mime_types.contains(&mime_type) {
+            source.mime_types.push(mime_type);
+        }
+    }
+}
+
+/// Reads a selection together with who copied it, see `ClipboardBackend::get_copy`.
+///
+/// Returns `None` if the clipboard is locked by someone else or the owner changed.
+fn read_copy<C: ClipboardBackend>(
+    clipboard: &Mutex<C>,
+    selection: Selection,
+    last: &ClipboardItem,
+) -> Option<(ClipboardItem, ClipboardSource)> {
+    clipboard.try_lock().ok()?.get_copy(selection, last)
+}
+// -------------------------------------------------------------------
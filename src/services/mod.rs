@@ -1,4 +1,9 @@
 // Daemon Services
 pub mod clipboard_ipc_server;
 pub mod clipboard_manager;
+pub mod clipboard_watcher;
+pub mod subscribers;
+pub mod wayland_watcher;
+pub mod x11_clipboard;
+pub mod x11_watcher;
 pub mod ydotool;
@@ -0,0 +1,197 @@
+// System Crates
+use std::{
+    fmt, io,
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    sync::{
+        Mutex,
+        mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// My Crates
+use crate::{
+    history::HistoryEvent,
+    services::clipboard_ipc_server::{Payload, send_payload},
+};
+
+// --------------------------- Subscribers ---------------------------
+/// How many payloads may wait for a subscriber before its events are dropped.
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 256;
+
+/// How long a write to a subscriber may block before it is disconnected.
+pub const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many subscribers the daemon keeps at once. More are refused with `SubscribeError::Full`.
+pub const MAX_SUBSCRIBERS: usize = 32;
+
+/// Why a connection could not be added as a subscriber.
+#[derive(Debug)]
+pub enum SubscribeError {
+    /// There are already as many subscribers as allowed (the number).
+    Full(usize),
+
+    /// The connection could not be set up, or the subscribers are poisoned.
+    Io(io::Error),
+}
+
+impl fmt::Display for SubscribeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscribeError::Full(max) => write!(f, "There are already {} subscribers", max),
+            SubscribeError::Io(err) => write!(f, "Could not add subscriber: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for SubscribeError {
+    fn from(err: io::Error) -> Self {
+        SubscribeError::Io(err)
+    }
+}
+
+/// # Subscribers
+/// The connections that asked for history events with `CmdIPC::Subscribe`.
+///
+/// Every subscriber has its own queue and writer thread, so publishing never waits for a client.
+///
+/// **Backpressure**:
+/// - While a subscriber's queue is full, its events are dropped and counted.
+/// - Once there is room again, it gets a `Payload::Lagged` with the count before the next event,
+///   so it knows to fetch a new summary.
+/// - A subscriber that takes no frame for `SUBSCRIBER_WRITE_TIMEOUT` or hangs up is disconnected
+///   and forgotten on the next publish or subscribe.
+/// - At most `max_subscribers` are kept; more are refused.
+pub struct Subscribers {
+    queue_size: usize,
+    max_subscribers: usize,
+    list: Mutex<Vec<Subscriber>>,
+}
+
+// The sending end of one subscriber's queue
+struct Subscriber {
+    queue: SyncSender<Payload>,
+    dropped: u64,
+    writer: JoinHandle<()>,
+}
+
+impl Default for Subscribers {
+    fn default() -> Self {
+        Self::new(SUBSCRIBER_QUEUE_SIZE)
+    }
+}
+
+impl Subscribers {
+    /// No subscribers yet. Each one will get a queue of `queue_size` payloads.
+    pub fn new(queue_size: usize) -> Self {
+        Self::with_limits(queue_size, MAX_SUBSCRIBERS)
+    }
+
+    /// Like `new`, but keeps at most `max_subscribers` at once.
+    pub fn with_limits(queue_size: usize, max_subscribers: usize) -> Self {
+        Self {
+            queue_size: queue_size.max(1),
+            max_subscribers,
+            list: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds `stream` as a subscriber and starts its writer thread.
+    ///
+    /// **Behavior**:
+    /// - `first` is sent before any event, e.g. the summary the events apply to.
+    /// - Callers hold the history lock, so no change falls between `first` and the first event.
+    /// - Subscribers whose writer has stopped are forgotten before the limit is checked.
+    ///
+    /// **Errors**:
+    /// - `SubscribeError::Full` if there are already `max_subscribers`. `stream` is left untouched.
+    /// - `SubscribeError::Io` if the write timeout can't be set or the subscribers are poisoned.
+    pub fn subscribe(&self, stream: UnixStream, first: Payload) -> Result<(), SubscribeError> {
+        let mut list = self
+            .list
+            .lock()
+            .map_err(|_| io::Error::other("Subscribers lock poisoned"))?;
+        list.retain(|subscriber| !subscriber.writer.is_finished());
+        if list.len() >= self.max_subscribers {
+            return Err(SubscribeError::Full(self.max_subscribers));
+        }
+
+        stream.set_write_timeout(Some(SUBSCRIBER_WRITE_TIMEOUT))?;
+        let (queue, receiver) = sync_channel(self.queue_size);
+        // The queue is empty, so this can't fail
+        let _ = queue.try_send(first);
+        let writer = thread::spawn(move || write_queue(stream, receiver));
+        list.push(Subscriber {
+            queue,
+            dropped: 0,
+            writer,
+        });
+        Ok(())
+    }
+
+    /// Queues `events` for every subscriber, without waiting for any of them.
+    ///
+    /// Subscribers that were disconnected are removed.
+    pub fn publish(&self, events: &[HistoryEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        let Ok(mut list) = self.list.lock() else {
+            return;
+        };
+
+        list.retain_mut(|subscriber| {
+            for event in events {
+                if subscriber.dropped > 0 {
+                    match subscriber
+                        .queue
+                        .try_send(Payload::Lagged(subscriber.dropped))
+                    {
+                        Ok(()) => subscriber.dropped = 0,
+                        Err(TrySendError::Full(_)) => {
+                            subscriber.dropped += 1;
+                            continue;
+                        }
+                        Err(TrySendError::Disconnected(_)) => return false,
+                    }
+                }
+                match subscriber.queue.try_send(Payload::Event(event.clone())) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => subscriber.dropped += 1,
+                    Err(TrySendError::Disconnected(_)) => return false,
+                }
+            }
+            true
+        });
+    }
+
+    /// How many subscribers there are, including disconnected ones not yet removed by `publish`.
+    pub fn len(&self) -> usize {
+        self.list.lock().map(|list| list.len()).unwrap_or(0)
+    }
+
+    /// Whether there are no subscribers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Disconnects every subscriber once its writer has sent what is queued.
+    pub fn close(&self) {
+        if let Ok(mut list) = self.list.lock() {
+            list.clear();
+        }
+    }
+}
+
+// Sends the queue to the subscriber until either side goes away
+fn write_queue(mut stream: UnixStream, receiver: Receiver<Payload>) {
+    for payload in receiver {
+        if send_payload(&mut stream, payload).is_err() {
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+// -------------------------------------------------------------------
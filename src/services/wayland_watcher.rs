@@ -0,0 +1,293 @@
+// System Crates
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+// External Crates
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle,
+    backend::ObjectId,
+    delegate_noop, event_created_child,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::{wl_registry::WlRegistry, wl_seat::WlSeat},
+};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+};
+
+// My Crates
+use crate::{
+    common::{Selection, WatcherError},
+    services::clipboard_watcher::{SelectionWatcher, WatchEvent},
+};
+
+// --------------------- Wayland Watcher -----------------------------
+/// # WaylandWatcher
+/// Selection watcher for Wayland compositors with a data-control protocol.
+///
+/// Prefers `ext-data-control-v1` and falls back to `wlr-data-control-unstable-v1`.
+/// The compositor sends a `selection` event whenever the clipboard changes, so
+/// the daemon only reads the clipboard when there is something new.
+///
+/// Each change comes with the MIME types of the new offer, e.g. the
+/// `x-kde-passwordManagerHint` of password managers, see `offered_types`.
+///
+/// Events are dispatched on a background thread and forwarded over a channel.
+/// The thread exits once the watcher is dropped and the next event arrives.
+pub struct WaylandWatcher {
+    events: Receiver<(WatchEvent, Vec<String>)>,
+
+    // MIME types offered with the last change of the clipboard and PRIMARY
+    clipboard_types: Vec<String>,
+    primary_types: Vec<String>,
+}
+
+impl WaylandWatcher {
+    /// Connects to the compositor from `WAYLAND_DISPLAY` and subscribes to selection changes.
+    ///
+    /// # Errors
+    ///
+    /// * `WatcherError::Unavailable` - No compositor could be reached
+    /// * `WatcherError::Unsupported` - The compositor has no seat or no data-control manager
+    pub fn connect() -> Result<Self, WatcherError> {
+        let conn = Connection::connect_to_env()
+            .map_err(|err| WatcherError::Unavailable(err.to_string()))?;
+        let (globals, mut queue) = registry_queue_init::<DataControlState>(&conn)
+            .map_err(|err| WatcherError::Unavailable(err.to_string()))?;
+        let qh = queue.handle();
+
+        let seat: WlSeat = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| WatcherError::Unsupported("compositor has no seat".to_string()))?;
+
+        // The device keeps sending events as long as it is alive, which is as long as the queue
+        if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(&qh, 1..=1, ()) {
+            manager.get_data_device(&seat, &qh, ());
+        } else if let Ok(manager) = globals.bind::<ZwlrDataControlManagerV1, _, _>(&qh, 1..=2, ()) {
+            manager.get_data_device(&seat, &qh, ());
+        } else {
+            return Err(WatcherError::Unsupported(
+                "compositor has no data-control manager".to_string(),
+            ));
+        }
+
+        // The first selection event only reports what is already on the clipboard
+        let mut state = DataControlState::default();
+        queue
+            .roundtrip(&mut state)
+            .map_err(|err| WatcherError::Unavailable(err.to_string()))?;
+        if state.finished {
+            return Err(WatcherError::Unsupported(
+                "data-control device was rejected".to_string(),
+            ));
+        }
+        state.changed.clear();
+        state.offers.clear();
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // Keep the connection alive for the lifetime of the thread
+            let _conn = conn;
+
+            loop {
+                if queue.blocking_dispatch(&mut state).is_err() || state.finished {
+                    let _ = tx.send((WatchEvent::Disconnected, Vec::new()));
+                    return;
+                }
+
+                // Stop once the watcher has been dropped
+                for (selection, mime_types) in state.changed.drain(..) {
+                    if tx
+                        .send((WatchEvent::Changed(selection), mime_types))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            events: rx,
+            clipboard_types: Vec::new(),
+            primary_types: Vec::new(),
+        })
+    }
+}
+
+impl SelectionWatcher for WaylandWatcher {
+    fn wait(&mut self, timeout: Duration) -> WatchEvent {
+        match self.events.recv_timeout(timeout) {
+            Ok((event, mime_types)) => {
+                match event {
+                    WatchEvent::Changed(Selection::Clipboard) => self.clipboard_types = mime_types,
+                    WatchEvent::Changed(Selection::Primary) => self.primary_types = mime_types,
+                    WatchEvent::Idle | WatchEvent::Disconnected => {}
+                }
+                event
+            }
+            Err(RecvTimeoutError::Timeout) => WatchEvent::Idle,
+            Err(RecvTimeoutError::Disconnected) => WatchEvent::Disconnected,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn offered_types(&self, selection: Selection) -> Option<Vec<String>> {
+        match selection {
+            Selection::Clipboard => Some(self.clipboard_types.clone()),
+            Selection::Primary => Some(self.primary_types.clone()),
+        }
+    }
+}
+// -------------------------------------------------------------------
+
+// ---------------------- Event Dispatch -----------------------------
+// State shared by the ext and wlr variants of the protocol
+#[derive(Default)]
+struct DataControlState {
+    // Selections that changed since the last forward, with the MIME types of their offer
+    changed: Vec<(Selection, Vec<String>)>,
+
+    // MIME types of the offers the compositor introduced, until they become a selection
+    offers: HashMap<ObjectId, Vec<String>>,
+
+    // The compositor invalidated the device
+    finished: bool,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for DataControlState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(DataControlState: ignore WlSeat);
+delegate_noop!(DataControlState: ExtDataControlManagerV1);
+delegate_noop!(DataControlState: ZwlrDataControlManagerV1);
+
+// Offers advertise their MIME types right after they are introduced, before the selection
+// event that uses them. The clipboard itself is read through arboard.
+impl Dispatch<ExtDataControlOfferV1, ()> for DataControlState {
+    fn event(
+        state: &mut Self,
+        offer: &ExtDataControlOfferV1,
+        event: ext_data_control_offer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for DataControlState {
+    fn event(
+        state: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl DataControlState {
+    // Records a change of `selection` to the offer `id`. Offers are only valid until the
+    // next selection, so the caller destroys it right after.
+    fn selection_changed(&mut self, selection: Selection, id: Option<ObjectId>) {
+        let mime_types = id
+            .and_then(|id| self.offers.remove(&id))
+            .unwrap_or_default();
+        self.changed.push((selection, mime_types));
+    }
+}
+
+impl Dispatch<ExtDataControlDeviceV1, ()> for DataControlState {
+    fn event(
+        state: &mut Self,
+        _: &ExtDataControlDeviceV1,
+        event: ext_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_data_control_device_v1::Event::Selection { id } => {
+                state.selection_changed(Selection::Clipboard, id.as_ref().map(Proxy::id));
+                if let Some(offer) = id {
+                    offer.destroy();
+                }
+            }
+            ext_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.selection_changed(Selection::Primary, id.as_ref().map(Proxy::id));
+                if let Some(offer) = id {
+                    offer.destroy();
+                }
+            }
+            ext_data_control_device_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(DataControlState, ExtDataControlDeviceV1, [
+        ext_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ExtDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for DataControlState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                state.selection_changed(Selection::Clipboard, id.as_ref().map(Proxy::id));
+                if let Some(offer) = id {
+                    offer.destroy();
+                }
+            }
+            zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.selection_changed(Selection::Primary, id.as_ref().map(Proxy::id));
+                if let Some(offer) = id {
+                    offer.destroy();
+                }
+            }
+            zwlr_data_control_device_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(DataControlState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+// -------------------------------------------------------------------
@@ -0,0 +1,610 @@
+// System Crates
+use std::{
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+// External Crates
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode, percent_encode};
+use x11rb::{
+    COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE,
+    connection::{Connection, RequestConnection},
+    protocol::{
+        Event,
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux,
+            EventMask, PropMode, Property, PropertyNotifyEvent, SELECTION_NOTIFY_EVENT,
+            SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass,
+        },
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+// My Crates
+use crate::common::{ClipboardError, Selection};
+
+// ----------------------- X11 Flavours ------------------------------
+// Targets (mime types) used for formatted text
+pub const RTF: &str = "text/rtf";
+pub const UTF8_TEXT: &str = "UTF8_STRING";
+pub const PLAIN_TEXT: &str = "text/plain;charset=utf-8";
+
+// Targets used for copied files. Most file managers read the URI list,
+// Nautilus and other GNOME apps want their own format.
+pub const URI_LIST: &str = "text/uri-list";
+pub const GNOME_COPIED_FILES: &str = "x-special/gnome-copied-files";
+
+/// How long the selection owner gets to answer a read, or to send the next chunk of a large one.
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Largest flavour read incrementally (INCR), in bytes.
+const MAX_INCR_BYTES: usize = 64 << 20;
+
+// Property the owner writes the data to
+const TRANSFER_PROPERTY: &str = "SUPER_V_TRANSFER";
+
+/// # SelectionReader
+/// One connection to the X server for reading the CLIPBOARD or PRIMARY selection.
+///
+/// arboard only knows text, HTML and images, this covers the rest. The owner's `TARGETS`
+/// are asked for once when the reader opens, and only the flavours listed there are
+/// converted, so a read doesn't wait for flavours the owner doesn't have.
+///
+/// Open a new reader for every read. The targets belong to whoever owned the selection then,
+/// see `owner_changed`.
+pub struct SelectionReader {
+    conn: RustConnection,
+    window: Window,
+    selection: Atom,
+    property: Atom,
+    incr: Atom,
+
+    // Owner window when the reader opened, `NONE` if there was none
+    owner: Window,
+
+    // `None` if the owner didn't answer for its targets
+    targets: Option<Vec<String>>,
+}
+
+impl SelectionReader {
+    /// Connects to the display, notes the owner of `selection` and asks it for its `TARGETS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - Display name like ":0". `None` uses `DISPLAY`.
+    /// * `selection` - Which selection to read
+    ///
+    /// # Errors
+    ///
+    /// * `ClipboardError::Unavailable` - The X server could not be reached
+    pub fn open(display: Option<&str>, selection: Selection) -> Result<Self, ClipboardError> {
+        let (conn, window) = connect(display)?;
+        let selection = match selection {
+            Selection::Clipboard => intern(&conn, "CLIPBOARD")?,
+            Selection::Primary => intern(&conn, "PRIMARY")?,
+        };
+        let mut reader = Self {
+            selection,
+            property: intern(&conn, TRANSFER_PROPERTY)?,
+            incr: intern(&conn, "INCR")?,
+            owner: selection_owner(&conn, selection)?,
+            conn,
+            window,
+            targets: None,
+        };
+
+        reader.targets = match reader.convert("TARGETS") {
+            Ok(data) => Some(reader.atom_names(&data)?),
+            // Nobody owns the selection, so there is nothing to read
+            Err(ClipboardError::ClipboardEmpty) => Some(Vec::new()),
+            Err(_) => None,
+        };
+        Ok(reader)
+    }
+
+    /// Names of the flavours the owner offers. Empty if there is no owner or it didn't answer.
+    ///
+    /// Password managers add hints like `x-kde-passwordManagerHint` here, see `sensitive::SourcePolicy`.
+    pub fn targets(&self) -> &[String] {
+        self.targets.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the owner offers a flavour `matches` accepts.
+    ///
+    /// An owner that didn't answer for its targets may offer anything.
+    pub fn offers(&self, matches: impl Fn(&str) -> bool) -> bool {
+        self.targets
+            .as_ref()
+            .is_none_or(|targets| targets.iter().any(|target| matches(target)))
+    }
+
+    /// Reads one flavour, e.g. `text/rtf`.
+    ///
+    /// **Behavior**:
+    /// - Flavours the owner doesn't offer are empty without asking it.
+    /// - Waits up to `READ_TIMEOUT` for the owner, and as long for every chunk of large data
+    ///   sent incrementally (INCR), up to `MAX_INCR_BYTES`.
+    ///
+    /// # Errors
+    ///
+    /// * `ClipboardError::ClipboardEmpty` - Nobody owns the selection or it has no such flavour
+    /// * `ClipboardError::Unavailable` - The owner didn't answer, or sent more than `MAX_INCR_BYTES`
+    pub fn read(&self, target: &str) -> Result<Vec<u8>, ClipboardError> {
+        if !self.offers(|offered| offered == target) {
+            return Err(ClipboardError::ClipboardEmpty);
+        }
+        self.convert(target)
+    }
+
+    /// Copied files from either file manager format. `None` if there are none.
+    pub fn copied_files(&self) -> Option<Vec<PathBuf>> {
+        [URI_LIST, GNOME_COPIED_FILES]
+            .into_iter()
+            .filter_map(|target| self.read(target).ok())
+            .map(|data| paths_from_uri_list(&data))
+            .find(|paths| !paths.is_empty())
+    }
+
+    /// Whether the selection changed hands since the reader opened, so what it read may
+    /// belong to another copy. Also true if the owner can't be asked.
+    pub fn owner_changed(&self) -> bool {
+        !matches!(selection_owner(&self.conn, self.selection), Ok(owner) if owner == self.owner)
+    }
+
+    /// The application that owned the selection when the reader opened.
+    ///
+    /// **Behavior**:
+    /// - Uses the class of the owner window (`WM_CLASS`), e.g. "KeePassXC".
+    /// - Falls back to the process name of its `_NET_WM_PID`, e.g. "keepassxc".
+    /// - Returns `None` if there is no owner or it doesn't tell. Windows of other
+    ///   machines (X forwarding) may name a process that doesn't exist here.
+    pub fn owner_app(&self) -> Option<String> {
+        let (conn, owner) = (&self.conn, self.owner);
+        if owner == NONE {
+            return None;
+        }
+
+        let class = conn
+            .get_property(false, owner, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.value)
+            .unwrap_or_default();
+
+        // "instance\0class\0", the class is the nicer name
+        let mut names = class
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned());
+        let instance = names.next();
+        if let Some(name) = names.next().or(instance) {
+            return Some(name);
+        }
+
+        let pid_atom = intern(conn, "_NET_WM_PID").ok()?;
+        let pid = conn
+            .get_property(false, owner, pid_atom, AtomEnum::CARDINAL, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?
+            .value32()?
+            .next()?;
+        let name = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+        Some(name.trim_end().to_string())
+    }
+
+    // Asks the owner to convert the selection to `target` and waits for the data
+    fn convert(&self, target: &str) -> Result<Vec<u8>, ClipboardError> {
+        let target = intern(&self.conn, target)?;
+        self.conn
+            .convert_selection(
+                self.window,
+                self.selection,
+                target,
+                self.property,
+                CURRENT_TIME,
+            )
+            .map_err(unavailable)?;
+        self.conn.flush().map_err(unavailable)?;
+
+        let Event::SelectionNotify(notify) = self.wait_for(|event| {
+            matches!(event, Event::SelectionNotify(notify)
+                if notify.requestor == self.window && notify.target == target)
+        })?
+        else {
+            unreachable!("wait_for only returns matching events");
+        };
+
+        // The owner has no such flavour (or there is no owner)
+        if notify.property == NONE {
+            return Err(ClipboardError::ClipboardEmpty);
+        }
+
+        let (kind, data) = self.take_property()?;
+        if kind != self.incr {
+            return Ok(data);
+        }
+
+        // Deleting the INCR property asked for the first chunk, an empty chunk ends the transfer
+        let mut data = Vec::new();
+        loop {
+            self.wait_for(|event| {
+                matches!(event, Event::PropertyNotify(notify)
+                    if notify.window == self.window
+                        && notify.atom == self.property
+                        && notify.state == Property::NEW_VALUE)
+            })?;
+            let (_, chunk) = self.take_property()?;
+            if chunk.is_empty() {
+                return Ok(data);
+            }
+            if data.len() + chunk.len() > MAX_INCR_BYTES {
+                return Err(ClipboardError::Unavailable(
+                    "Clipboard content is too large".to_string(),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+    }
+
+    // Reads and deletes the transfer property. Returns its type and value.
+    fn take_property(&self) -> Result<(Atom, Vec<u8>), ClipboardError> {
+        let reply = self
+            .conn
+            .get_property(
+                true,
+                self.window,
+                self.property,
+                AtomEnum::ANY,
+                0,
+                u32::MAX / 4,
+            )
+            .map_err(unavailable)?
+            .reply()
+            .map_err(unavailable)?;
+        Ok((reply.type_, reply.value))
+    }
+
+    // Waits up to `READ_TIMEOUT` for an event `matches` accepts, dropping the others
+    fn wait_for(&self, matches: impl Fn(&Event) -> bool) -> Result<Event, ClipboardError> {
+        let deadline = Instant::now() + READ_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.conn.poll_for_event().map_err(unavailable)? {
+                Some(event) if matches(&event) => return Ok(event),
+                Some(_) => continue,
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        }
+
+        Err(ClipboardError::Unavailable(
+            "Clipboard owner did not answer".to_string(),
+        ))
+    }
+
+    // Names of a list of 32 bit atoms in the X server's byte order, which is ours
+    fn atom_names(&self, data: &[u8]) -> Result<Vec<String>, ClipboardError> {
+        // Send every lookup before waiting for the first answer
+        let cookies = data
+            .chunks_exact(4)
+            .map(|chunk| Atom::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .map(|atom| self.conn.get_atom_name(atom))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(unavailable)?;
+        Ok(cookies
+            .into_iter()
+            .filter_map(|cookie| cookie.reply().ok())
+            .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+            .collect())
+    }
+}
+
+/// Takes ownership of the CLIPBOARD selection and offers every flavour in `flavours`.
+///
+/// **Behavior**:
+/// - Requests are served on a background thread that keeps its own connection.
+/// - The thread exits once another client takes the clipboard, cutting off transfers still running.
+/// - `TARGETS` lists all flavours, so pasting apps can pick the richest they support.
+/// - Flavours too big for one request are sent incrementally (INCR), one chunk each time
+///   the requestor has taken the previous one.
+///
+/// # Arguments
+///
+/// * `display` - Display name like ":0". `None` uses `DISPLAY`.
+/// * `flavours` - Pairs of target name (e.g. `RTF`) and the data served for it
+///
+/// # Errors
+///
+/// * `ClipboardError::Unavailable` - The X server could not be reached or ownership was refused
+pub fn offer_flavours(
+    display: Option<&str>,
+    flavours: Vec<(&str, Vec<u8>)>,
+) -> Result<(), ClipboardError> {
+    let (conn, window) = connect(display)?;
+    let clipboard = intern(&conn, "CLIPBOARD")?;
+    let targets = intern(&conn, "TARGETS")?;
+    let incr = intern(&conn, "INCR")?;
+    let flavours = flavours
+        .into_iter()
+        .map(|(name, data)| Ok((intern(&conn, name)?, data)))
+        .collect::<Result<Vec<(Atom, Vec<u8>)>, ClipboardError>>()?;
+
+    conn.set_selection_owner(window, clipboard, CURRENT_TIME)
+        .map_err(unavailable)?;
+    if selection_owner(&conn, clipboard)? != window {
+        return Err(ClipboardError::Unavailable(
+            "Could not take over the clipboard".to_string(),
+        ));
+    }
+
+    thread::spawn(move || {
+        let mut transfers = Vec::new();
+        while let Ok(event) = conn.wait_for_event() {
+            match event {
+                Event::SelectionRequest(request) => {
+                    let served = serve(&conn, &request, targets, incr, &flavours, &mut transfers);
+                    answer(&conn, &request, served);
+                }
+
+                // The requestor took a chunk, send the next
+                Event::PropertyNotify(event) if event.state == Property::DELETE => {
+                    send_chunk(&conn, &event, &flavours, &mut transfers);
+                }
+
+                // Someone else copied something, we are done
+                Event::SelectionClear(_) => return,
+                _ => {}
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// A flavour sent in chunks, see `send_chunk`
+struct Transfer {
+    requestor: Window,
+    property: Atom,
+    flavour: usize,
+    sent: usize,
+}
+
+// Writes the requested flavour to the requestor's window. Returns the property written to.
+// Flavours too big for one request only get an INCR property, and a transfer is started.
+fn serve(
+    conn: &RustConnection,
+    request: &SelectionRequestEvent,
+    targets: Atom,
+    incr: Atom,
+    flavours: &[(Atom, Vec<u8>)],
+    transfers: &mut Vec<Transfer>,
+) -> Option<Atom> {
+    // Obsolete clients don't name a property, the target is used instead
+    let property = if request.property == NONE {
+        request.target
+    } else {
+        request.property
+    };
+
+    if request.target == targets {
+        let mut offered = vec![targets];
+        offered.extend(flavours.iter().map(|(atom, _)| *atom));
+        conn.change_property32(
+            PropMode::REPLACE,
+            request.requestor,
+            property,
+            AtomEnum::ATOM,
+            &offered,
+        )
+        .ok()?;
+        return Some(property);
+    }
+
+    let flavour = flavours
+        .iter()
+        .position(|(atom, _)| *atom == request.target)?;
+    let (atom, data) = &flavours[flavour];
+
+    if data.len() + 32 > conn.maximum_request_bytes() {
+        // The requestor deletes the property for every chunk it took, we need to hear that
+        conn.change_window_attributes(
+            request.requestor,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .ok()?;
+        let size = u32::try_from(data.len()).unwrap_or(u32::MAX);
+        conn.change_property32(
+            PropMode::REPLACE,
+            request.requestor,
+            property,
+            incr,
+            &[size],
+        )
+        .ok()?;
+        transfers.push(Transfer {
+            requestor: request.requestor,
+            property,
+            flavour,
+            sent: 0,
+        });
+        return Some(property);
+    }
+    conn.change_property8(PropMode::REPLACE, request.requestor, property, *atom, data)
+        .ok()?;
+    Some(property)
+}
+
+// Writes the next chunk of the transfer whose property was deleted.
+// The empty chunk after the last one ends the transfer.
+fn send_chunk(
+    conn: &RustConnection,
+    event: &PropertyNotifyEvent,
+    flavours: &[(Atom, Vec<u8>)],
+    transfers: &mut Vec<Transfer>,
+) {
+    let Some(pos) = transfers
+        .iter()
+        .position(|transfer| transfer.requestor == event.window && transfer.property == event.atom)
+    else {
+        return;
+    };
+    let transfer = &mut transfers[pos];
+    let (atom, data) = &flavours[transfer.flavour];
+    let end = data
+        .len()
+        .min(transfer.sent + conn.maximum_request_bytes() / 2);
+
+    let written = conn.change_property8(
+        PropMode::REPLACE,
+        transfer.requestor,
+        transfer.property,
+        *atom,
+        &data[transfer.sent..end],
+    );
+    if written.is_err() || transfer.sent == end {
+        let done = transfers.remove(pos);
+        // Stop listening to the requestor's window, unless it is still being sent something
+        if !transfers
+            .iter()
+            .any(|transfer| transfer.requestor == done.requestor)
+        {
+            let _ = conn.change_window_attributes(
+                done.requestor,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+            );
+        }
+    } else {
+        transfer.sent = end;
+    }
+    let _ = conn.flush();
+}
+
+// Tells the requestor whether its request was served
+fn answer(conn: &RustConnection, request: &SelectionRequestEvent, served: Option<Atom>) {
+    let notify = SelectionNotifyEvent {
+        response_type: SELECTION_NOTIFY_EVENT,
+        sequence: 0,
+        time: request.time,
+        requestor: request.requestor,
+        selection: request.selection,
+        target: request.target,
+        property: served.unwrap_or(NONE),
+    };
+    let _ = conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify);
+    let _ = conn.flush();
+}
+
+// Connects to the display and creates an invisible window to transfer data through.
+// The window hears about its property changes, which incremental transfers need.
+fn connect(display: Option<&str>) -> Result<(RustConnection, Window), ClipboardError> {
+    let (conn, screen_num) = x11rb::connect(display).map_err(unavailable)?;
+    let screen = &conn.setup().roots[screen_num];
+    let window = conn.generate_id().map_err(unavailable)?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        screen.root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )
+    .map_err(unavailable)?;
+    Ok((conn, window))
+}
+
+fn intern(conn: &RustConnection, name: &str) -> Result<Atom, ClipboardError> {
+    Ok(conn
+        .intern_atom(false, name.as_bytes())
+        .map_err(unavailable)?
+        .reply()
+        .map_err(unavailable)?
+        .atom)
+}
+
+// Window owning `selection`, `NONE` if nobody does
+fn selection_owner(conn: &RustConnection, selection: Atom) -> Result<Window, ClipboardError> {
+    Ok(conn
+        .get_selection_owner(selection)
+        .map_err(unavailable)?
+        .reply()
+        .map_err(unavailable)?
+        .owner)
+}
+
+fn unavailable(err: impl ToString) -> ClipboardError {
+    ClipboardError::Unavailable(err.to_string())
+}
+// -------------------------------------------------------------------
+
+// ------------------------ File Lists -------------------------------
+// Characters escaped in file URIs, on top of control characters
+const URI_ESCAPED: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// Paths of the local files in a `text/uri-list` or `x-special/gnome-copied-files` flavour.
+///
+/// **Behavior**:
+/// - Lines may end in `\r\n` or `\n`. Comments and the GNOME `copy`/`cut` line are skipped.
+/// - Only `file://` URIs are kept, remote ones can't be pasted as files.
+/// - Paths that aren't valid UTF-8 are skipped, so the history can always be saved.
+pub fn paths_from_uri_list(data: &[u8]) -> Vec<PathBuf> {
+    data.split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter_map(|line| line.strip_prefix(b"file://"))
+        // file://host/path, only the local host makes sense
+        .filter_map(|uri| {
+            let start = uri.iter().position(|byte| *byte == b'/')?;
+            match &uri[..start] {
+                b"" | b"localhost" => Some(&uri[start..]),
+                _ => None,
+            }
+        })
+        .filter_map(|path| percent_decode(path).decode_utf8().ok())
+        .map(|path| PathBuf::from(path.as_ref()))
+        .collect()
+}
+
+/// `text/uri-list` for `paths`, one `file://` URI per line.
+pub fn paths_to_uri_list(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| file_uri(path))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// `x-special/gnome-copied-files` for `paths`, which marks them as copied (not cut).
+pub fn paths_to_gnome_copied_files(paths: &[PathBuf]) -> String {
+    let uris: Vec<String> = paths.iter().map(|path| file_uri(path)).collect();
+    format!("copy\n{}", uris.join("\n"))
+}
+
+fn file_uri(path: &Path) -> String {
+    format!(
+        "file://{}",
+        percent_encode(path.as_os_str().as_bytes(), URI_ESCAPED)
+    )
+}
+// -------------------------------------------------------------------
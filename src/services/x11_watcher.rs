@@ -0,0 +1,120 @@
+// System Crates
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+// External Crates
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        Event,
+        xfixes::{ConnectionExt as _, SelectionEventMask},
+        xproto::{AtomEnum, ConnectionExt as _},
+    },
+};
+
+// My Crates
+use crate::{
+    common::{Selection, WatcherError},
+    services::clipboard_watcher::{SelectionWatcher, WatchEvent},
+};
+
+// ----------------------- X11 Watcher -------------------------------
+/// # X11Watcher
+/// Selection watcher for X11 servers with the XFixes extension.
+///
+/// XFixes sends a `SelectionNotify` event whenever a client takes ownership of
+/// CLIPBOARD or PRIMARY, or the owner goes away.
+///
+/// Events are read on a background thread and forwarded over a channel.
+/// The thread exits once the watcher is dropped and the next event arrives.
+pub struct X11Watcher {
+    events: Receiver<WatchEvent>,
+}
+
+impl X11Watcher {
+    /// Connects to an X server and subscribes to CLIPBOARD and PRIMARY owner changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - Display name like ":0". `None` uses `DISPLAY`.
+    ///
+    /// # Errors
+    ///
+    /// * `WatcherError::Unavailable` - The X server could not be reached
+    /// * `WatcherError::Unsupported` - The X server has no XFixes extension
+    pub fn connect(display: Option<&str>) -> Result<Self, WatcherError> {
+        let (conn, screen_num) = x11rb::connect(display).map_err(unavailable)?;
+
+        // Selection events need XFixes 2.0 or newer
+        conn.xfixes_query_version(5, 0)
+            .map_err(unavailable)?
+            .reply()
+            .map_err(|_| WatcherError::Unsupported("X server has no XFixes".to_string()))?;
+
+        let clipboard = conn
+            .intern_atom(false, b"CLIPBOARD")
+            .map_err(unavailable)?
+            .reply()
+            .map_err(unavailable)?
+            .atom;
+        let primary = AtomEnum::PRIMARY.into();
+
+        // Events are delivered to us for the root window, no window of our own needed
+        let root = conn.setup().roots[screen_num].root;
+        let mask = SelectionEventMask::SET_SELECTION_OWNER
+            | SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+        for selection in [clipboard, primary] {
+            conn.xfixes_select_selection_input(root, selection, mask)
+                .map_err(unavailable)?
+                .check()
+                .map_err(unavailable)?;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                let selection = match conn.wait_for_event() {
+                    Ok(Event::XfixesSelectionNotify(event)) if event.selection == clipboard => {
+                        Selection::Clipboard
+                    }
+                    Ok(Event::XfixesSelectionNotify(_)) => Selection::Primary,
+                    Ok(_) => continue,
+                    Err(_) => {
+                        let _ = tx.send(WatchEvent::Disconnected);
+                        return;
+                    }
+                };
+
+                // Stop once the watcher has been dropped
+                if tx.send(WatchEvent::Changed(selection)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self { events: rx })
+    }
+}
+
+impl SelectionWatcher for X11Watcher {
+    fn wait(&mut self, timeout: Duration) -> WatchEvent {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => WatchEvent::Idle,
+            Err(RecvTimeoutError::Disconnected) => WatchEvent::Disconnected,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+}
+
+fn unavailable(err: impl ToString) -> WatcherError {
+    WatcherError::Unavailable(err.to_string())
+}
+// -------------------------------------------------------------------
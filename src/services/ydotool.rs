@@ -2,23 +2,63 @@
 use std::path::Path;
 use std::process::Command;
 
-pub fn send_shift_insert() {
+use serde::Deserialize;
+
+/// Where ydotoold listens unless the config says otherwise.
+pub const DEFAULT_SOCKET: &str = "/tmp/.ydotool_socket";
+
+/// The key combination sent to paste into the focused window.
+///
+/// **Available**:
+/// * **ShiftInsert** - Works in terminals and most GUI apps (default)
+/// * **CtrlV** - The usual GUI shortcut
+/// * **CtrlShiftV** - Pastes in most terminals
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PasteKey {
+    #[default]
+    ShiftInsert,
+    CtrlV,
+    CtrlShiftV,
+}
+
+impl PasteKey {
+    /// `ydotool key` arguments: modifiers down, key down and up, modifiers up.
+    fn key_events(self) -> Vec<String> {
+        // Linux input event codes
+        const LEFT_CTRL: &str = "29";
+        const LEFT_SHIFT: &str = "42";
+        const INSERT: &str = "110";
+        const V: &str = "47";
+
+        let (modifiers, key): (&[&str], &str) = match self {
+            PasteKey::ShiftInsert => (&[LEFT_SHIFT], INSERT),
+            PasteKey::CtrlV => (&[LEFT_CTRL], V),
+            PasteKey::CtrlShiftV => (&[LEFT_CTRL, LEFT_SHIFT], V),
+        };
+
+        let down = |code: &str| format!("{code}:1");
+        let up = |code: &str| format!("{code}:0");
+        let mut events: Vec<String> = modifiers.iter().map(|code| down(code)).collect();
+        events.push(down(key));
+        events.push(up(key));
+        events.extend(modifiers.iter().rev().map(|code| up(code)));
+        events
+    }
+}
+
+pub fn send_paste(socket_path: &Path, key: PasteKey) {
     // Check if socket exists
-    let socket_path = "/tmp/.ydotool_socket";
-    if !Path::new(socket_path).exists() {
-        eprintln!("ydotool socket not found at {}", socket_path);
+    if !socket_path.exists() {
+        eprintln!("ydotool socket not found at {}", socket_path.display());
         return;
     }
 
-    // Simulate Shift+Insert (paste)
+    // Simulate the paste shortcut
     let result = Command::new("ydotool")
         .env("YDOTOOL_SOCKET", socket_path)
-        .args([
-            "key", "42:1",  // Shift down
-            "110:1", // Insert down
-            "110:0", // Insert up
-            "42:0",  // Shift up
-        ])
+        .arg("key")
+        .args(key.key_events())
         .output();
 
     match result {
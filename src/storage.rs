@@ -0,0 +1,487 @@
+// Standard Crates
+use std::{
+    collections::VecDeque,
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+// External Crates
+use argon2::Argon2;
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload, rand_core::RngCore},
+};
+use rmp_serde::Serializer;
+use serde::{Deserialize, Serialize};
+
+// My Crates
+use crate::{
+    common::{ClipboardItem, StorageError, fnv1a},
+    history::ClipboardHistory,
+};
+
+// ------------------------- Constants --------------------------------
+/// Magic bytes at the start of every history file.
+const MAGIC: &[u8; 4] = b"SUPV";
+
+/// Plain MessagePack payload, protected by a checksum.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Payload is `salt | nonce | ciphertext`, sealed with XChaCha20-Poly1305.
+pub const ENCRYPTED_FORMAT_VERSION: u16 = 2;
+
+/// magic (4) + version (2) + payload length (4) + checksum (8)
+const HEADER_LEN: usize = 18;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+const APP_DIR: &str = "super_v";
+const HISTORY_FILE: &str = "history.bin";
+// --------------------------------------------------------------------
+
+// ------------------------- Store Key --------------------------------
+/// Secret material used to encrypt the history file.
+///
+/// The actual cipher key is derived from this with Argon2id and a per-file salt,
+/// so a keyfile and a passphrase are handled the same way.
+#[derive(Clone)]
+pub struct StoreKey {
+    secret: Vec<u8>,
+}
+
+impl StoreKey {
+    /// Uses a passphrase as the secret. Surrounding whitespace is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError::InvalidKey` if the passphrase is empty.
+    pub fn from_passphrase(passphrase: &str) -> Result<Self, StorageError> {
+        let passphrase = passphrase.trim();
+        if passphrase.is_empty() {
+            return Err(StorageError::InvalidKey("Passphrase is empty".into()));
+        }
+
+        Ok(Self {
+            secret: passphrase.as_bytes().to_vec(),
+        })
+    }
+
+    /// Uses the raw contents of a keyfile as the secret.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StorageError::InvalidKey` if the keyfile can't be read or is empty.
+    pub fn from_keyfile(path: &Path) -> Result<Self, StorageError> {
+        let secret = fs::read(path).map_err(|err| {
+            StorageError::InvalidKey(format!("Could not read {}: {}", path.display(), err))
+        })?;
+        if secret.is_empty() {
+            return Err(StorageError::InvalidKey(format!(
+                "{} is empty",
+                path.display()
+            )));
+        }
+
+        Ok(Self { secret })
+    }
+
+    /// Derives the 32 byte cipher key for the given salt.
+    fn derive(&self, salt: &[u8; SALT_LEN]) -> Result<[u8; 32], StorageError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(&self.secret, salt, &mut key)
+            .map_err(|err| StorageError::InvalidKey(err.to_string()))?;
+        Ok(key)
+    }
+}
+
+// Never print the secret
+impl fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StoreKey(..)")
+    }
+}
+
+/// A StoreKey with its derived key cached for one salt.
+///
+/// Argon2 is deliberately slow, so the key is derived once at startup
+/// instead of on every save.
+#[derive(Clone)]
+struct StoreCipher {
+    secret: StoreKey,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+impl StoreCipher {
+    fn new(secret: StoreKey, salt: [u8; SALT_LEN]) -> Result<Self, StorageError> {
+        let key = secret.derive(&salt)?;
+        Ok(Self { secret, salt, key })
+    }
+
+    /// Key for a file written with `salt`. Only re-derives if the file was
+    /// written with a different salt than the cached one.
+    fn key_for(&self, salt: &[u8; SALT_LEN]) -> Result<[u8; 32], StorageError> {
+        if salt == &self.salt {
+            Ok(self.key)
+        } else {
+            self.secret.derive(salt)
+        }
+    }
+}
+// --------------------------------------------------------------------
+
+// ----------------------- History Store -----------------------------
+/// Persists a `ClipboardHistory` to a single state file.
+///
+/// The file starts with a small header (magic, format version, payload length
+/// and checksum) followed by the MessagePack encoded history, the same encoding
+/// the IPC layer uses. Writes go to a temporary file that is synced and then
+/// renamed over the old one, so a crash mid-write leaves the previous file intact.
+///
+/// A store created with `HistoryStore::encrypted` seals the payload with
+/// XChaCha20-Poly1305 instead, which also authenticates it.
+#[derive(Clone)]
+pub struct HistoryStore {
+    path: PathBuf,
+    cipher: Option<StoreCipher>,
+}
+
+impl HistoryStore {
+    /// Creates a plaintext store backed by the given file path.
+    ///
+    /// Nothing is touched on disk until `load` or `save` is called.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cipher: None,
+        }
+    }
+
+    /// Creates a store that encrypts the history with a key derived from `key`.
+    ///
+    /// If the file already exists and is encrypted, its salt is reused so the
+    /// key only has to be derived once. Otherwise a fresh salt is generated.
+    ///
+    /// # Errors
+    ///
+    /// * `StorageError::InvalidKey` - The key could not be derived.
+    /// * `StorageError::Io` - The existing file could not be read.
+    pub fn encrypted(path: impl Into<PathBuf>, key: StoreKey) -> Result<Self, StorageError> {
+        let path = path.into();
+
+        let salt = match fs::read(&path) {
+            Ok(bytes) => match split_file(&bytes) {
+                Ok((ENCRYPTED_FORMAT_VERSION, _, payload)) => {
+                    split_sealed(payload).ok().map(|sealed| sealed.salt)
+                }
+                _ => None,
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(StorageError::Io(err.to_string())),
+        };
+        let salt = salt.unwrap_or_else(|| {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            salt
+        });
+
+        Ok(Self {
+            path,
+            cipher: Some(StoreCipher::new(key, salt)?),
+        })
+    }
+
+    /// Returns the default state file location.
+    ///
+    /// Resolves to `$XDG_DATA_HOME/super_v/history.bin`, falling back to
+    /// `$HOME/.local/share/super_v/history.bin`. Returns `None` if neither is set.
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+
+        Some(data_home.join(APP_DIR).join(HISTORY_FILE))
+    }
+
+    /// Path of the state file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether this store writes encrypted files.
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Loads the history from disk.
+    ///
+    /// A plaintext file is still readable by an encrypted store, and is
+    /// encrypted on the next save.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(history))` - The file existed and was decoded.
+    /// * `Ok(None)` - There is no state file yet.
+    ///
+    /// # Errors
+    ///
+    /// * `StorageError::Corrupt` - The file is truncated, fails its checksum or can't be decoded.
+    /// * `StorageError::UnsupportedVersion` - The file was written with an unknown format version.
+    /// * `StorageError::KeyRequired` - The file is encrypted but this store has no key.
+    /// * `StorageError::DecryptionFailed` - The key is wrong or the file was modified.
+    /// * `StorageError::Io` - The file exists but could not be read.
+    pub fn load(&self) -> Result<Option<ClipboardHistory>, StorageError> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(StorageError::Io(err.to_string())),
+        };
+
+        self.decode(&bytes).map(Some)
+    }
+
+    /// Atomically writes the history to disk.
+    ///
+    /// The parent directory is created if needed and the file is only readable
+    /// by the current user.
+    ///
+    /// # Errors
+    ///
+    /// * `StorageError::Encode` - The history could not be serialized.
+    /// * `StorageError::Io` - Writing, syncing or renaming the file failed.
+    pub fn save(&self, history: &ClipboardHistory) -> Result<(), StorageError> {
+        let bytes = self.encode(history)?;
+        let io_err = |err: std::io::Error| StorageError::Io(err.to_string());
+
+        let parent = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent).map_err(io_err)?;
+
+        // Write next to the target so the rename stays on the same filesystem
+        let tmp_path = self.tmp_path();
+        let write_result = (|| {
+            let mut tmp_file = OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .mode(0o600)
+                .open(&tmp_path)?;
+            tmp_file.write_all(&bytes)?;
+            tmp_file.sync_all()?;
+            fs::rename(&tmp_path, &self.path)?;
+
+            // Make the rename itself durable
+            File::open(parent)?.sync_all()
+        })();
+
+        if write_result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        write_result.map_err(io_err)
+    }
+
+    /// Moves an unreadable state file out of the way so a fresh one can be written.
+    ///
+    /// The file is kept next to the original with a `.corrupt` suffix for inspection.
+    /// Returns the path it was moved to.
+    pub fn quarantine(&self) -> Result<PathBuf, StorageError> {
+        let mut corrupt_path = self.path.clone().into_os_string();
+        corrupt_path.push(".corrupt");
+        let corrupt_path = PathBuf::from(corrupt_path);
+
+        fs::rename(&self.path, &corrupt_path).map_err(|err| StorageError::Io(err.to_string()))?;
+        Ok(corrupt_path)
+    }
+
+    /// Serializes a history into the versioned state file layout.
+    pub fn encode(&self, history: &ClipboardHistory) -> Result<Vec<u8>, StorageError> {
+        let mut plain: Vec<u8> = Vec::new();
+        history
+            .serialize(&mut Serializer::new(&mut plain))
+            .map_err(|err| StorageError::Encode(err.to_string()))?;
+
+        match &self.cipher {
+            None => join_file(FORMAT_VERSION, &plain, fnv1a(&plain)),
+            Some(cipher) => {
+                let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let ciphertext = XChaCha20Poly1305::new(&cipher.key.into())
+                    .encrypt(
+                        &nonce,
+                        Payload {
+                            msg: &plain,
+                            aad: &associated_data(&cipher.salt),
+                        },
+                    )
+                    .map_err(|_| StorageError::Encode("Encryption failed".into()))?;
+
+                let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+                sealed.extend_from_slice(&cipher.salt);
+                sealed.extend_from_slice(&nonce);
+                sealed.extend_from_slice(&ciphertext);
+
+                // The Poly1305 tag already covers the payload
+                join_file(ENCRYPTED_FORMAT_VERSION, &sealed, 0)
+            }
+        }
+    }
+
+    /// Validates and deserializes a state file produced by `encode`.
+    pub fn decode(&self, bytes: &[u8]) -> Result<ClipboardHistory, StorageError> {
+        let plain = match split_file(bytes)? {
+            (FORMAT_VERSION, stored_checksum, payload) => {
+                if stored_checksum != fnv1a(payload) {
+                    return Err(StorageError::Corrupt("Checksum mismatch".into()));
+                }
+                payload.to_vec()
+            }
+            (ENCRYPTED_FORMAT_VERSION, _, payload) => {
+                let Some(cipher) = &self.cipher else {
+                    return Err(StorageError::KeyRequired);
+                };
+
+                let sealed = split_sealed(payload)?;
+                let key = cipher.key_for(&sealed.salt)?;
+                XChaCha20Poly1305::new(&key.into())
+                    .decrypt(
+                        XNonce::from_slice(sealed.nonce),
+                        Payload {
+                            msg: sealed.ciphertext,
+                            aad: &associated_data(&sealed.salt),
+                        },
+                    )
+                    .map_err(|_| StorageError::DecryptionFailed)?
+            }
+            (version, _, _) => return Err(StorageError::UnsupportedVersion(version)),
+        };
+
+        match rmp_serde::from_slice(&plain) {
+            Ok(history) => Ok(history),
+            // Files written before entries carried metadata hold bare items
+            Err(err) => rmp_serde::from_slice::<LegacyHistory>(&plain)
+                .map(LegacyHistory::into_history)
+                .map_err(|_| StorageError::Corrupt(err.to_string())),
+        }
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(".tmp.{}", std::process::id()));
+        PathBuf::from(tmp_path)
+    }
+}
+
+// Never print the key
+impl fmt::Debug for HistoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HistoryStore")
+            .field("path", &self.path)
+            .field("encrypted", &self.is_encrypted())
+            .finish()
+    }
+}
+// -------------------------------------------------------------------
+
+// ------------------------ Legacy Layout ----------------------------
+/// History layout from before entries carried metadata and ids.
+#[derive(Deserialize)]
+struct LegacyHistory {
+    history: VecDeque<ClipboardItem>,
+    max_size: usize,
+    #[serde(default)]
+    pinned: VecDeque<ClipboardItem>,
+}
+
+impl LegacyHistory {
+    fn into_history(self) -> ClipboardHistory {
+        ClipboardHistory::from_items(self.history, self.pinned, self.max_size)
+    }
+}
+// -------------------------------------------------------------------
+
+// ------------------------ File Format ------------------------------
+/// Prepends the header to a payload.
+fn join_file(version: u16, payload: &[u8], checksum: u64) -> Result<Vec<u8>, StorageError> {
+    let payload_len = u32::try_from(payload.len())
+        .map_err(|_| StorageError::Encode("History too large".into()))?;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&version.to_be_bytes());
+    bytes.extend_from_slice(&payload_len.to_be_bytes());
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    bytes.extend_from_slice(payload);
+
+    Ok(bytes)
+}
+
+/// Checks the header and returns the format version, checksum and payload.
+fn split_file(bytes: &[u8]) -> Result<(u16, u64, &[u8]), StorageError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(StorageError::Corrupt(
+            "File is shorter than its header".into(),
+        ));
+    }
+
+    let (header, payload) = bytes.split_at(HEADER_LEN);
+    if &header[0..4] != MAGIC {
+        return Err(StorageError::Corrupt("Missing magic bytes".into()));
+    }
+
+    let version = u16::from_be_bytes([header[4], header[5]]);
+    let payload_len = u32::from_be_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    if payload.len() != payload_len {
+        return Err(StorageError::Corrupt(format!(
+            "Expected {} bytes of history but found {}",
+            payload_len,
+            payload.len()
+        )));
+    }
+
+    let mut stored_checksum = [0u8; 8];
+    stored_checksum.copy_from_slice(&header[10..18]);
+
+    Ok((version, u64::from_be_bytes(stored_checksum), payload))
+}
+
+/// An encrypted payload split into its parts.
+struct Sealed<'a> {
+    salt: [u8; SALT_LEN],
+    nonce: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+/// Splits an encrypted payload into salt, nonce and ciphertext.
+fn split_sealed(payload: &[u8]) -> Result<Sealed<'_>, StorageError> {
+    if payload.len() < SALT_LEN + NONCE_LEN {
+        return Err(StorageError::Corrupt(
+            "Encrypted history is missing its salt or nonce".into(),
+        ));
+    }
+
+    let (salt_bytes, rest) = payload.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(salt_bytes);
+    Ok(Sealed {
+        salt,
+        nonce,
+        ciphertext,
+    })
+}
+
+/// Binds the ciphertext to the file format and salt it was written with.
+fn associated_data(salt: &[u8; SALT_LEN]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(MAGIC.len() + 2 + SALT_LEN);
+    aad.extend_from_slice(MAGIC);
+    aad.extend_from_slice(&ENCRYPTED_FORMAT_VERSION.to_be_bytes());
+    aad.extend_from_slice(salt);
+    aad
+}
+
+// -------------------------------------------------------------------
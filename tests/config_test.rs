@@ -0,0 +1,206 @@
+#[cfg(test)]
+mod config_tests {
+    use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+    use super_v::{
+        common::{ClipboardItem, ConfigError},
+        config::{Config, ConfigFile, parse_duration, parse_size},
+        history::KindFilter,
+        sensitive::{Classifier, SecretAction},
+        services::{
+            clipboard_ipc_server::FrameLimits, clipboard_manager::Manager, ydotool::PasteKey,
+        },
+    };
+
+    // ------------------ Helper Functions ----------------------
+    fn temp_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("super_v_config_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("config.toml")
+    }
+
+    // The message of a config that fails to load
+    fn error_of(text: &str) -> ConfigError {
+        Config::parse(text).unwrap_err()
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_empty_config_has_defaults() {
+        let config = Config::parse("").unwrap();
+
+        assert_eq!(config.history.max_items, Manager::DEFAULT_HISTORY_SIZE);
+        assert_eq!(config.history.max_bytes, None);
+        assert!(!config.watch.primary);
+        assert_eq!(config.watch.poll_interval, Duration::from_millis(100));
+        assert_eq!(config.secrets.concealed, SecretAction::Skip);
+        assert_eq!((config.gui.width, config.gui.height), (360, 400));
+        assert_eq!(config.gui.shown_items, 100);
+        assert_eq!(config.paste.key, PasteKey::ShiftInsert);
+        assert_eq!(config.frame_limits(), FrameLimits::default());
+    }
+
+    #[test]
+    fn test_full_config() {
+        let config = Config::parse(
+            r#"
+            [history]
+            max_items = 500
+            max_bytes = "200M"
+            expire_after = "7d"
+            kinds.image = { max_items = 20, expire_after = "1h" }
+            kinds.text = { max_bytes = 4096 }
+
+            [watch]
+            primary = true
+            primary_debounce = "250ms"
+            poll_interval = 1
+
+            [secrets]
+            concealed = "30s"
+            deny_apps = ["keepassxc"]
+            rules = [
+                { action = "mask", pattern = "corp-[0-9]{8}" },
+                { name = "pin", action = "30s", pattern = "^PIN [0-9]+$" },
+            ]
+
+            [gui]
+            width = 500
+            shown_items = 20
+
+            [paste]
+            ydotool_socket = "/run/user/1000/.ydotool_socket"
+            key = "ctrl-shift-v"
+
+            [ipc]
+            max_frame_size = "1M"
+            timeout = "500ms"
+            "#,
+        )
+        .unwrap();
+
+        let limits = config.history_limits();
+        assert_eq!(limits.max_items, 500);
+        assert_eq!(limits.max_bytes, Some(200 << 20));
+        assert_eq!(limits.kinds[&KindFilter::Image].max_items, Some(20));
+        assert_eq!(limits.kinds[&KindFilter::Text].max_bytes, Some(4096));
+
+        let expiry = config.expiry();
+        assert_eq!(expiry.max_age, Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(
+            expiry.kinds,
+            [(KindFilter::Image, Duration::from_secs(60 * 60))].into()
+        );
+
+        let watch = config.watch_options();
+        assert!(watch.track_primary);
+        assert_eq!(watch.primary_debounce, Duration::from_millis(250));
+        assert_eq!(watch.poll_interval, Duration::from_secs(1));
+
+        // Own rules come before the built-in ones
+        let classifier = config.classifier();
+        // Unnamed rules don't show their pattern
+        assert_eq!(classifier.rules()[0].name, "custom-1");
+        assert_eq!(classifier.rules()[1].name, "pin");
+        assert_eq!(
+            classifier.classify(&ClipboardItem::Text("id corp-12345678".into())),
+            Some(SecretAction::Mask)
+        );
+        assert_eq!(
+            config.source_policy().concealed,
+            SecretAction::Expire(Duration::from_secs(30))
+        );
+
+        assert_eq!((config.gui.width, config.gui.height), (500, 400));
+        assert_eq!(config.paste.key, PasteKey::CtrlShiftV);
+
+        let frame_limits = config.frame_limits();
+        assert_eq!(frame_limits.max_request_size, 1 << 20);
+        assert_eq!(frame_limits.timeout, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_errors_name_the_problem() {
+        let cases = [
+            ("[history]\nmax_itmes = 5", "max_itmes"),
+            ("[history]\nmax_bytes = \"5X\"", "unknown size unit"),
+            (
+                "[history.kinds.video]\nmax_items = 5",
+                "unknown kind 'video'",
+            ),
+            ("[watch]\nprimary = \"yes\"", "line 2"),
+            (
+                "[secrets]\nrules = [{ action = \"mask\", pattern = \"(\" }]",
+                "regex",
+            ),
+            ("[secrets]\nconcealed = \"hide\"", "unknown action 'hide'"),
+            ("[paste]\nkey = \"alt-v\"", "alt-v"),
+        ];
+        for (text, expected) in cases {
+            let ConfigError::Parse(message) = error_of(text) else {
+                panic!("{text} should not parse");
+            };
+            assert!(message.contains(expected), "{text}: {message}");
+        }
+
+        assert_eq!(
+            error_of("[gui]\nwidth = 0"),
+            ConfigError::Invalid("gui.width and gui.height must be greater than 0".to_string())
+        );
+        assert_eq!(
+            error_of("[watch]\npoll_interval = \"1ms\""),
+            ConfigError::Invalid("watch.poll_interval must be at least 10ms".to_string())
+        );
+        assert_eq!(
+            error_of("[history.kinds.files]\nexpire_after = 0"),
+            ConfigError::Invalid("history.kinds.files.expire_after must be longer than 0s".into())
+        );
+        assert_eq!(
+            error_of("[secrets]\nrules = [{ name = \"pin\", action = \"0s\", pattern = \"x\" }]"),
+            ConfigError::Invalid(
+                "secrets.rules 'pin' must not expire after 0s, use \"skip\"".to_string()
+            )
+        );
+        assert_eq!(
+            error_of("[ipc]\nmax_frame_size = 100"),
+            ConfigError::Invalid("ipc.max_frame_size must be at least 4K".to_string())
+        );
+    }
+
+    #[test]
+    fn test_config_file() {
+        let path = temp_path("file");
+
+        // A missing file is the defaults
+        let file = ConfigFile::new(&path);
+        assert_eq!(file.stamp(), None);
+        assert_eq!(file.load().unwrap().history.max_items, 1000);
+
+        fs::write(&path, "[history]\nmax_items = 5\n").unwrap();
+        assert!(file.stamp().is_some());
+        assert_eq!(file.load().unwrap().history.max_items, 5);
+
+        // Overrides win over the file
+        let file = ConfigFile {
+            path: path.clone(),
+            overrides: Arc::new(|config: &mut Config| config.history.max_items = 7),
+        };
+        assert_eq!(file.load().unwrap().history.max_items, 7);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("2K"), Ok(2048));
+        assert_eq!(parse_size("1 MiB"), Ok(1 << 20));
+        assert!(parse_size("1T").is_err());
+
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert!(parse_duration("2w").is_err());
+    }
+}
@@ -1,12 +1,34 @@
 #[cfg(test)]
 mod history_tests {
-    use std::collections::VecDeque;
+    use serde::Serialize;
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
 
     use super_v::{
-        common::{ClipboardError, ClipboardItem},
-        history::ClipboardHistory,
+        common::{ClipboardError, ClipboardImage, ClipboardItem, Selection},
+        history::{
+            ClipboardHistory, EntrySummary, ExpiryPolicy, FALLBACK_ENTRIES, HistoryEntry,
+            HistoryEvent, HistoryLimits, HistoryQuery, ItemKind, KindFilter, KindLimit,
+            MASKED_PREVIEW, PREVIEW_CHARS, PREVIEW_FILES,
+        },
+        sensitive::SecretAction,
     };
 
+    // ------------------ Helper Functions ----------------------
+    // Image of `width` x `height` pixels with every byte set to `fill`
+    fn image(width: usize, height: usize, fill: u8) -> ClipboardItem {
+        let rgba = vec![fill; width * height * 4];
+        ClipboardItem::Image(ClipboardImage::from_rgba(width, height, &rgba).unwrap())
+    }
+
+    // Ten bytes of text
+    fn text(n: usize) -> ClipboardItem {
+        ClipboardItem::Text(format!("text {n:05}"))
+    }
+    // ----------------------------------------------------------
+
     #[test]
     fn test_history_add_item() {
         // Create history
@@ -19,7 +41,7 @@ mod history_tests {
         history.add(item.clone());
 
         // Check if the history matches
-        assert_eq!(history.get_items(), &VecDeque::from([item]));
+        assert_eq!(history.get_items(), &[item]);
     }
 
     #[test]
@@ -49,10 +71,7 @@ mod history_tests {
             "Length must be 5, but got {}",
             history.get_items().len()
         );
-        assert_eq!(
-            history.get_items(),
-            &VecDeque::from([item6, item5, item4, item3, item2])
-        );
+        assert_eq!(history.get_items(), &[item6, item5, item4, item3, item2]);
     }
 
     #[test]
@@ -75,7 +94,7 @@ mod history_tests {
         history.promote(1).unwrap();
 
         // Compare
-        assert_eq!(history.get_items(), &VecDeque::from([item2, item3, item1]));
+        assert_eq!(history.get_items(), &[item2, item3, item1]);
     }
 
     #[test]
@@ -135,18 +154,16 @@ mod history_tests {
         // Test with large image data to ensure no memory issues
         let mut history = ClipboardHistory::new(3);
 
-        // Create a large image (1MB of data)
-        let large_bytes = vec![0u8; 1_000_000];
-        let large_image = ClipboardItem::Image {
-            width: 1000,
-            height: 1000,
-            bytes: large_bytes,
-        };
+        // Create a large image (4MB of pixels)
+        let large_image = image(1000, 1000, 0);
 
         history.add(large_image.clone());
 
         assert_eq!(history.get_items().len(), 1);
-        assert_eq!(history.get_items().front(), Some(&large_image));
+        assert_eq!(
+            history.get_items().front().map(|entry| &entry.item),
+            Some(&large_image)
+        );
     }
 
     #[test]
@@ -158,7 +175,7 @@ mod history_tests {
         history.add(empty_text.clone());
 
         assert_eq!(history.get_items().len(), 1);
-        assert_eq!(history.get_items(), &VecDeque::from([empty_text]));
+        assert_eq!(history.get_items(), &[empty_text]);
     }
 
     #[test]
@@ -171,7 +188,7 @@ mod history_tests {
         history.add(long_item.clone());
 
         assert_eq!(history.get_items().len(), 1);
-        if let ClipboardItem::Text(ref text) = history.get_items()[0] {
+        if let ClipboardItem::Text(ref text) = history.get_items()[0].item {
             assert_eq!(text.len(), 100_000);
         } else {
             panic!("Expected Text item");
@@ -187,7 +204,7 @@ mod history_tests {
         history.add(special_text.clone());
 
         assert_eq!(history.get_items().len(), 1);
-        assert_eq!(history.get_items(), &VecDeque::from([special_text]));
+        assert_eq!(history.get_items(), &[special_text]);
     }
 
     #[test]
@@ -195,16 +212,12 @@ mod history_tests {
         // Test edge case: image with zero dimensions
         let mut history = ClipboardHistory::new(5);
 
-        let zero_img = ClipboardItem::Image {
-            width: 0,
-            height: 0,
-            bytes: vec![],
-        };
+        let zero_img = image(0, 0, 0);
 
         history.add(zero_img.clone());
 
         assert_eq!(history.get_items().len(), 1);
-        assert_eq!(history.get_items(), &VecDeque::from([zero_img]));
+        assert_eq!(history.get_items(), &[zero_img]);
     }
 
     #[test]
@@ -224,13 +237,13 @@ mod history_tests {
         history.promote(2).unwrap(); // 3,2,1 -> 1,3,2
         assert_eq!(
             history.get_items(),
-            &VecDeque::from([item1.clone(), item3.clone(), item2.clone()])
+            &[item1.clone(), item3.clone(), item2.clone()]
         );
 
         history.promote(2).unwrap(); // 1,3,2 -> 2,1,2
         assert_eq!(
             history.get_items(),
-            &VecDeque::from([item2.clone(), item1.clone(), item3.clone()])
+            &[item2.clone(), item1.clone(), item3.clone()]
         );
     }
 
@@ -250,7 +263,7 @@ mod history_tests {
 
         // Should have only 2 items with item1 promoted to front
         assert_eq!(history.get_items().len(), 2);
-        assert_eq!(history.get_items(), &VecDeque::from([item1, item2]));
+        assert_eq!(history.get_items(), &[item1, item2]);
     }
 
     #[test]
@@ -281,24 +294,16 @@ mod history_tests {
         let mut history = ClipboardHistory::new(3);
 
         // Create Image items (Random data)
-        let image1 = ClipboardItem::Image {
-            width: 100,
-            height: 100,
-            bytes: vec![0u8; 100],
-        };
+        let image1 = image(100, 100, 0);
 
-        let image2 = ClipboardItem::Image {
-            width: 200,
-            height: 150,
-            bytes: vec![255u8; 200],
-        };
+        let image2 = image(200, 150, 255);
 
         // Add images
         history.add(image1.clone());
         history.add(image2.clone());
 
         // Check if images were added
-        assert_eq!(history.get_items(), &VecDeque::from([image2, image1]));
+        assert_eq!(history.get_items(), &[image2, image1]);
     }
 
     #[test]
@@ -308,18 +313,14 @@ mod history_tests {
 
         // Create items, one of each type
         let text = ClipboardItem::Text("Hello".to_string());
-        let image = ClipboardItem::Image {
-            width: 50,
-            height: 50,
-            bytes: vec![0u8; 50],
-        };
+        let image = image(50, 50, 0);
 
         // Add items to history
         history.add(text.clone());
         history.add(image.clone());
 
         // Check history
-        assert_eq!(history.get_items(), &VecDeque::from([image, text]));
+        assert_eq!(history.get_items(), &[image, text]);
     }
 
     #[test]
@@ -338,7 +339,7 @@ mod history_tests {
         // Promote first item (index 0) - should remain at top
         history.promote(0).unwrap();
 
-        assert_eq!(history.get_items(), &VecDeque::from([item2, item1]));
+        assert_eq!(history.get_items(), &[item2, item1]);
     }
 
     #[test]
@@ -357,7 +358,7 @@ mod history_tests {
         // Delete the middle item (current order: 3,2,1)
         let result = history.delete(1);
         assert_eq!(result, Ok(()));
-        assert_eq!(history.get_items(), &VecDeque::from([item3, item1]));
+        assert_eq!(history.get_items(), &[item3, item1]);
     }
 
     #[test]
@@ -385,7 +386,7 @@ mod history_tests {
         // Delete a specific item by value
         let result = history.delete_this(item1.clone());
         assert_eq!(result, Ok(()));
-        assert_eq!(history.get_items(), &VecDeque::from([item2]));
+        assert_eq!(history.get_items(), &[item2]);
     }
 
     #[test]
@@ -397,4 +398,1081 @@ mod history_tests {
         let result = history.delete_this(ClipboardItem::Text("Missing".to_string()));
         assert_eq!(result, Err(ClipboardError::IndexOutOfBound));
     }
+
+    #[test]
+    fn test_pin_item() {
+        // Create history
+        let mut history = ClipboardHistory::new(5);
+
+        let item1 = ClipboardItem::Text("Item 1".to_string());
+        let item2 = ClipboardItem::Text("Item 2".to_string());
+
+        history.add(item1.clone());
+        history.add(item2.clone());
+
+        // Pin item 1 (current order: 2,1)
+        assert_eq!(history.pin(1), Ok(()));
+
+        // It moves out of the rolling history into the pinned items
+        assert_eq!(history.get_items(), &[item2]);
+        assert_eq!(history.get_pinned(), &[item1]);
+    }
+
+    #[test]
+    fn test_pinned_items_survive_clear() {
+        let mut history = ClipboardHistory::new(5);
+
+        let pinned = ClipboardItem::Text("SELECT * FROM users;".to_string());
+        history.add(pinned.clone());
+        history.pin(0).unwrap();
+        history.add(ClipboardItem::Text("Item".to_string()));
+
+        history.clear();
+
+        assert_eq!(history.get_items().len(), 0);
+        assert_eq!(history.get_pinned(), &[pinned]);
+    }
+
+    #[test]
+    fn test_pinned_items_skip_eviction_and_capacity() {
+        // Capacity of 2 for unpinned items
+        let mut history = ClipboardHistory::new(2);
+
+        let pinned = ClipboardItem::Text("Pinned".to_string());
+        let item1 = ClipboardItem::Text("Item 1".to_string());
+        let item2 = ClipboardItem::Text("Item 2".to_string());
+        let item3 = ClipboardItem::Text("Item 3".to_string());
+
+        history.add(pinned.clone());
+        history.pin(0).unwrap();
+
+        // Fill past capacity, the pinned item does not take a slot
+        history.add(item1);
+        history.add(item2.clone());
+        history.add(item3.clone());
+
+        assert_eq!(history.get_items(), &[item3, item2]);
+        assert_eq!(history.get_pinned(), &[pinned]);
+    }
+
+    #[test]
+    fn test_copying_pinned_item_again_keeps_it_pinned() {
+        let mut history = ClipboardHistory::new(5);
+
+        let pinned = ClipboardItem::Text("Pinned".to_string());
+        history.add(pinned.clone());
+        history.pin(0).unwrap();
+
+        // Copying it again should not create an unpinned duplicate
+        history.add(pinned.clone());
+
+        assert_eq!(history.get_items().len(), 0);
+        assert_eq!(history.get_pinned().len(), 1);
+
+        // And removing by value only looks at unpinned items
+        assert_eq!(
+            history.delete_this(pinned.clone()),
+            Err(ClipboardError::IndexOutOfBound)
+        );
+        assert_eq!(history.get_pinned(), &[pinned]);
+    }
+
+    #[test]
+    fn test_unpin_item() {
+        // Capacity of 2 for unpinned items
+        let mut history = ClipboardHistory::new(2);
+
+        let item1 = ClipboardItem::Text("Item 1".to_string());
+        let item2 = ClipboardItem::Text("Item 2".to_string());
+        let item3 = ClipboardItem::Text("Item 3".to_string());
+
+        history.add(item1.clone());
+        history.pin(0).unwrap();
+        history.add(item2.clone());
+        history.add(item3.clone());
+
+        // Unpinning puts it back on top, which pushes the oldest item out
+        assert_eq!(history.unpin(0), Ok(()));
+        assert_eq!(history.get_items(), &[item1, item3]);
+        assert_eq!(history.get_pinned().len(), 0);
+    }
+
+    #[test]
+    fn test_pin_unpin_out_of_bounds_error() {
+        let mut history = ClipboardHistory::new(2);
+        assert_eq!(history.pin(0), Err(ClipboardError::IndexOutOfBound));
+        assert_eq!(history.unpin(0), Err(ClipboardError::IndexOutOfBound));
+
+        history.add(ClipboardItem::Text("Item".to_string()));
+        assert_eq!(history.pin(1), Err(ClipboardError::IndexOutOfBound));
+        assert_eq!(history.unpin(0), Err(ClipboardError::IndexOutOfBound));
+    }
+
+    #[test]
+    fn test_entry_metadata_on_add() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add(ClipboardItem::Text("Hello 世界".to_string()));
+        history.add(image(2, 2, 0));
+
+        let image_entry = &history.get_items()[0];
+        let text_entry = &history.get_items()[1];
+
+        // Byte size is the size of the content
+        assert_eq!(text_entry.byte_size, "Hello 世界".len());
+        // Images count what is stored, the compressed image and its thumbnail
+        let ClipboardItem::Image(stored) = &image_entry.item else {
+            panic!("Expected Image item");
+        };
+        assert_eq!(
+            image_entry.byte_size,
+            stored.png.len() + stored.thumbnail.len()
+        );
+
+        // Fresh entries are unused
+        assert_eq!(text_entry.promote_count, 0);
+        assert_eq!(text_entry.first_copied, text_entry.last_used);
+        assert_eq!(text_entry.source, None);
+    }
+
+    #[test]
+    fn test_entries_get_increasing_ids() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add(ClipboardItem::Text("Item 1".to_string()));
+        history.add(ClipboardItem::Text("Item 2".to_string()));
+        history.add(ClipboardItem::Text("Item 3".to_string()));
+
+        // Order is 3,2,1 so ids are descending
+        let ids: Vec<u64> = history.get_items().iter().map(|entry| entry.id).collect();
+        assert!(
+            ids[0] > ids[1] && ids[1] > ids[2],
+            "Ids not increasing: {ids:?}"
+        );
+
+        // Deleting the newest item must not free its id for reuse
+        let newest = ids[0];
+        history.delete(0).unwrap();
+        history.add(ClipboardItem::Text("Item 4".to_string()));
+        assert!(history.get_items()[0].id > newest);
+    }
+
+    #[test]
+    fn test_commands_by_id_survive_new_items() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add(ClipboardItem::Text("Item 1".to_string()));
+        history.add(ClipboardItem::Text("Item 2".to_string()));
+        let target = history.get_items()[1].id; // Item 1
+
+        // A new copy shifts every position, but not the ids
+        history.add(ClipboardItem::Text("Item 3".to_string()));
+
+        history.promote_by_id(target).unwrap();
+        assert_eq!(
+            history.get_items()[0],
+            ClipboardItem::Text("Item 1".to_string())
+        );
+
+        history.pin_by_id(target).unwrap();
+        assert_eq!(history.get_pinned()[0].id, target);
+        assert_eq!(history.get_by_id(target).unwrap().id, target);
+
+        history.unpin_by_id(target).unwrap();
+        history.delete_by_id(target).unwrap();
+        assert!(history.get_by_id(target).is_none());
+        assert_eq!(history.get_items().len(), 2);
+    }
+
+    #[test]
+    fn test_commands_by_missing_id_error() {
+        let mut history = ClipboardHistory::new(5);
+        history.add(ClipboardItem::Text("Item 1".to_string()));
+        let id = history.get_items()[0].id;
+        history.delete_by_id(id).unwrap();
+
+        // A stale id must not hit whatever now sits at its old position
+        history.add(ClipboardItem::Text("Item 2".to_string()));
+        assert_eq!(
+            history.promote_by_id(id),
+            Err(ClipboardError::ItemNotFound(id))
+        );
+        assert_eq!(
+            history.delete_by_id(id),
+            Err(ClipboardError::ItemNotFound(id))
+        );
+        assert_eq!(history.pin_by_id(id), Err(ClipboardError::ItemNotFound(id)));
+
+        // Unpinned entries aren't found by unpin
+        let other = history.get_items()[0].id;
+        assert_eq!(
+            history.unpin_by_id(other),
+            Err(ClipboardError::ItemNotFound(other))
+        );
+        assert_eq!(history.get_items().len(), 1);
+    }
+
+    #[test]
+    fn test_promote_updates_metadata() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add(ClipboardItem::Text("Item 1".to_string()));
+        history.add(ClipboardItem::Text("Item 2".to_string()));
+        let before = history.get_items()[1].clone();
+
+        // Promote item 1 (current order: 2,1)
+        history.promote(1).unwrap();
+
+        let after = &history.get_items()[0];
+        assert_eq!(after.id, before.id);
+        assert_eq!(after.first_copied, before.first_copied);
+        assert_eq!(after.promote_count, 1);
+        assert!(after.last_used >= before.last_used);
+    }
+
+    #[test]
+    fn test_copying_again_counts_as_use() {
+        let mut history = ClipboardHistory::new(5);
+
+        let item = ClipboardItem::Text("Item".to_string());
+        history.add(item.clone());
+        history.add(item.clone());
+        history.add(item);
+
+        assert_eq!(history.get_items().len(), 1);
+        assert_eq!(history.get_items()[0].promote_count, 2);
+    }
+
+    #[test]
+    fn test_age_label() {
+        let mut history = ClipboardHistory::new(5);
+        history.add(ClipboardItem::Text("Item".to_string()));
+
+        let entry = &history.get_items()[0];
+        let used = entry.last_used;
+
+        assert_eq!(entry.age_label(used), "Just now");
+        assert_eq!(entry.age_label(used + Duration::from_secs(59)), "Just now");
+        assert_eq!(
+            entry.age_label(used + Duration::from_secs(120)),
+            "2 min ago"
+        );
+        assert_eq!(
+            entry.age_label(used + Duration::from_secs(3 * 3_600)),
+            "3 h ago"
+        );
+        assert_eq!(
+            entry.age_label(used + Duration::from_secs(2 * 86_400)),
+            "2 d ago"
+        );
+
+        // A clock that went backwards should not panic
+        assert_eq!(entry.age_label(used - Duration::from_secs(10)), "Just now");
+    }
+
+    #[test]
+    fn test_entries_remember_origin() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add(ClipboardItem::Text("Copied".to_string()));
+        history.add_from(
+            ClipboardItem::Text("Selected".to_string()),
+            Selection::Primary,
+        );
+
+        assert_eq!(history.get_items()[0].origin, Selection::Primary);
+        assert_eq!(history.get_items()[1].origin, Selection::Clipboard);
+
+        // Selecting a copied item again doesn't turn it into a selection
+        history.add_from(
+            ClipboardItem::Text("Copied".to_string()),
+            Selection::Primary,
+        );
+        assert_eq!(history.get_items()[0].origin, Selection::Clipboard);
+    }
+
+    #[test]
+    fn test_copying_a_selection_makes_it_a_clipboard_entry() {
+        let mut history = ClipboardHistory::new(5);
+
+        history.add_from(ClipboardItem::Text("Item".to_string()), Selection::Primary);
+        history.pin(0).unwrap();
+        history.add_from(ClipboardItem::Text("Other".to_string()), Selection::Primary);
+
+        history.add(ClipboardItem::Text("Item".to_string()));
+        history.add(ClipboardItem::Text("Other".to_string()));
+
+        assert_eq!(history.get_pinned()[0].origin, Selection::Clipboard);
+        assert_eq!(history.get_items()[0].origin, Selection::Clipboard);
+        assert_eq!(history.get_items().len(), 1);
+    }
+
+    #[test]
+    fn test_entries_without_origin_decode_as_clipboard() {
+        // An entry as it was saved before origins existed
+        #[derive(Serialize)]
+        struct OldEntry {
+            id: u64,
+            item: ClipboardItem,
+            first_copied: SystemTime,
+            last_used: SystemTime,
+            promote_count: u32,
+            byte_size: usize,
+            source: Option<String>,
+        }
+
+        let now = SystemTime::now();
+        let bytes = rmp_serde::to_vec(&OldEntry {
+            id: 7,
+            item: ClipboardItem::Text("Item".to_string()),
+            first_copied: now,
+            last_used: now,
+            promote_count: 0,
+            byte_size: 4,
+            source: None,
+        })
+        .unwrap();
+
+        let entry: HistoryEntry = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(entry.id, 7);
+        assert_eq!(entry.origin, Selection::Clipboard);
+    }
+
+    #[test]
+    fn test_formatted_text_items() {
+        let mut history = ClipboardHistory::new(5);
+
+        let html = ClipboardItem::Html {
+            html: "<b>Bold</b>".to_string(),
+            alt_text: "Bold".to_string(),
+        };
+        let rtf = ClipboardItem::Rtf {
+            rtf: r"{\rtf1 {\b Bold}}".to_string(),
+            alt_text: "Bold".to_string(),
+        };
+        history.add(html.clone());
+        history.add(rtf.clone());
+
+        // Same plain text, different formats, so both are kept
+        assert_eq!(history.get_items().len(), 2);
+        assert_eq!(
+            history.get_items()[1].byte_size,
+            "<b>Bold</b>".len() + "Bold".len()
+        );
+        assert_eq!(html.plain_text(), Some("Bold"));
+        assert_eq!(rtf.plain_text(), Some("Bold"));
+
+        // Copying it again promotes it, like any other item
+        history.add(html.clone());
+        assert_eq!(history.get_items()[0], html);
+        assert_eq!(history.get_items()[0].promote_count, 1);
+    }
+
+    #[test]
+    fn test_copied_files_items() {
+        let mut history = ClipboardHistory::new(5);
+
+        let files = ClipboardItem::Files(vec![PathBuf::from("/tmp/a"), PathBuf::from("/tmp/bc")]);
+        history.add(files.clone());
+        history.add(ClipboardItem::Text("/tmp/a\n/tmp/bc".to_string()));
+
+        // The same paths as text are a different item
+        assert_eq!(history.get_items().len(), 2);
+        assert_eq!(
+            history.get_items()[1].byte_size,
+            "/tmp/a".len() + "/tmp/bc".len()
+        );
+        assert_eq!(files.plain_text(), None);
+
+        history.add(files.clone());
+        assert_eq!(history.get_items()[0], files);
+        assert_eq!(history.get_items().len(), 2);
+    }
+
+    #[test]
+    fn test_summary_keeps_previews_only() {
+        let mut history = ClipboardHistory::new(5);
+
+        // Multibyte text, so a byte cut would split a character
+        let long_text = "é".repeat(PREVIEW_CHARS * 2);
+        let files: Vec<PathBuf> = (0..10)
+            .map(|n| PathBuf::from(format!("/tmp/{n}")))
+            .collect();
+        history.add(image(400, 100, 7));
+        history.add(ClipboardItem::Files(files.clone()));
+        history.add(ClipboardItem::Html {
+            html: "<b>bold</b>".to_string(),
+            alt_text: "bold".to_string(),
+        });
+        history.add(ClipboardItem::Text(long_text.clone()));
+        history.pin_by_id(history.get_items()[1].id).unwrap();
+
+        let summary = history.summary();
+        assert_eq!(summary.pinned.len(), 1);
+        assert_eq!(summary.items.len(), 3);
+
+        let text = &summary.items[0];
+        assert_eq!(text.id, history.get_items()[0].id);
+        assert_eq!(text.kind, ItemKind::Text);
+        assert_eq!(text.preview, format!("{}...", "é".repeat(PREVIEW_CHARS)));
+
+        let html = &summary.pinned[0];
+        assert_eq!(html.kind, ItemKind::Html);
+        assert_eq!(html.preview, "bold");
+
+        let ItemKind::Files { shown, count } = &summary.items[1].kind else {
+            panic!("Expected Files summary");
+        };
+        assert_eq!(shown, &files[..PREVIEW_FILES]);
+        assert_eq!(*count, 10);
+
+        // Images bring their thumbnail, not the image itself
+        let ClipboardItem::Image(stored) = &history.get_items()[2].item else {
+            panic!("Expected Image item");
+        };
+        let image_summary = &summary.items[2];
+        assert_eq!(
+            image_summary.kind,
+            ItemKind::Image {
+                width: 400,
+                height: 100,
+                hash: stored.hash
+            }
+        );
+        assert_eq!(image_summary.thumbnail, stored.thumbnail);
+        assert!(image_summary.preview.is_empty());
+    }
+
+    #[test]
+    fn test_query_text() {
+        let mut history = ClipboardHistory::new(10);
+        history.add(ClipboardItem::Text("Meeting notes".to_string()));
+        history.add(image(2, 2, 1));
+        history.add(ClipboardItem::Text("my clipboard manager".to_string()));
+        history.add(ClipboardItem::Files(vec![PathBuf::from(
+            "/home/me/Notes.md",
+        )]));
+        history.add(ClipboardItem::Text("unrelated".to_string()));
+
+        let query = |text: &str| HistoryQuery {
+            text: text.to_string(),
+            ..Default::default()
+        };
+
+        // Files match by path. Text containing the query comes before fuzzy matches.
+        let found = history.query(&query("notes"));
+        let previews: Vec<&str> = found.items.iter().map(|e| e.preview.as_str()).collect();
+        assert_eq!(previews, ["", "Meeting notes"]);
+        assert!(found.items[0].highlights.is_empty());
+        assert_eq!(found.items[1].highlights, vec![8..13]);
+
+        let found = history.query(&query("clpbrd"));
+        assert_eq!(found.items.len(), 1);
+        assert_eq!(found.items[0].highlights, vec![3..5, 6..8, 10..12]);
+
+        // Everything matches an empty query, nothing matches nonsense
+        assert_eq!(history.query(&query("")), history.summary());
+        assert!(history.query(&query("zzz")).items.is_empty());
+    }
+
+    #[test]
+    fn test_query_moves_preview_to_match() {
+        let mut history = ClipboardHistory::new(5);
+        let text = format!("{}needle{}", "x".repeat(100), "y".repeat(100));
+        history.add(ClipboardItem::Text(text));
+
+        let found = history.query(&HistoryQuery {
+            text: "NEEDLE".to_string(),
+            ..Default::default()
+        });
+
+        // Ten characters of context, then the match
+        let entry = &found.items[0];
+        assert!(
+            entry
+                .preview
+                .starts_with(&format!("...{}needle", "x".repeat(10)))
+        );
+        assert!(entry.preview.ends_with("y..."));
+        assert_eq!(&entry.preview[entry.highlights[0].clone()], "needle");
+    }
+
+    #[test]
+    fn test_query_filters_kind_and_origin() {
+        let mut history = ClipboardHistory::new(10);
+        history.add(ClipboardItem::Text("text".to_string()));
+        history.add(image(2, 2, 1));
+        history.add(ClipboardItem::Rtf {
+            rtf: "{\\rtf1 text}".to_string(),
+            alt_text: "text".to_string(),
+        });
+        history.add_from(
+            ClipboardItem::Text("selected text".to_string()),
+            Selection::Primary,
+        );
+        history.pin_by_id(history.get_items()[3].id).unwrap();
+
+        let kinds = |kind: KindFilter| {
+            let found = history.query(&HistoryQuery {
+                kind: Some(kind),
+                ..Default::default()
+            });
+            (found.pinned.len(), found.items.len())
+        };
+        assert_eq!(kinds(KindFilter::Text), (1, 1));
+        assert_eq!(kinds(KindFilter::Formatted), (0, 1));
+        assert_eq!(kinds(KindFilter::Image), (0, 1));
+        assert_eq!(kinds(KindFilter::Files), (0, 0));
+
+        let selected = history.query(&HistoryQuery {
+            text: "text".to_string(),
+            origin: Some(Selection::Primary),
+            ..Default::default()
+        });
+        assert!(selected.pinned.is_empty());
+        assert_eq!(selected.items.len(), 1);
+        assert_eq!(selected.items[0].preview, "selected text");
+
+        // Images have no text to match
+        let images = history.query(&HistoryQuery {
+            text: "text".to_string(),
+            kind: Some(KindFilter::Image),
+            ..Default::default()
+        });
+        assert!(images.items.is_empty());
+    }
+
+    #[test]
+    fn test_query_words_and_phrases() {
+        let mut history = ClipboardHistory::new(10);
+        history.add(ClipboardItem::Text("world peace".to_string()));
+        history.add(ClipboardItem::Text("hello there, world".to_string()));
+        history.add(ClipboardItem::Text("Hello World".to_string()));
+        history.add(ClipboardItem::Text("helicopter".to_string()));
+
+        let previews = |text: &str| -> Vec<String> {
+            history
+                .query(&HistoryQuery {
+                    text: text.to_string(),
+                    ..Default::default()
+                })
+                .items
+                .into_iter()
+                .map(|entry| entry.preview)
+                .collect()
+        };
+
+        // Words are prefixes, whole words rank first
+        assert_eq!(
+            previews("hel"),
+            ["helicopter", "Hello World", "hello there, world"]
+        );
+        assert_eq!(
+            previews("hello world"),
+            ["Hello World", "hello there, world"]
+        );
+
+        // Phrases rank above loose words, and need the words in order
+        assert_eq!(previews(r#""hello world""#), ["Hello World"]);
+        assert_eq!(previews(r#""world hello""#), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_query_date_range() {
+        let mut history = ClipboardHistory::new(10);
+        history.add(ClipboardItem::Text("old note".to_string()));
+        std::thread::sleep(Duration::from_millis(20));
+        let between = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(20));
+        history.add(ClipboardItem::Text("new note".to_string()));
+
+        let found = |since: Option<SystemTime>, until: Option<SystemTime>| -> Vec<String> {
+            history
+                .query(&HistoryQuery {
+                    text: "note".to_string(),
+                    since,
+                    until,
+                    ..Default::default()
+                })
+                .items
+                .into_iter()
+                .map(|entry| entry.preview)
+                .collect()
+        };
+        assert_eq!(found(Some(between), None), ["new note"]);
+        assert_eq!(found(None, Some(between)), ["old note"]);
+        assert_eq!(found(None, None), ["new note", "old note"]);
+    }
+
+    #[test]
+    fn test_query_limit_keeps_total() {
+        let mut history = ClipboardHistory::new(100);
+        for n in 0..30 {
+            history.add(ClipboardItem::Text(format!("entry {n}")));
+        }
+
+        let found = history.query(&HistoryQuery {
+            text: "entry".to_string(),
+            limit: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(found.items.len(), 5);
+        assert_eq!(found.total, 30);
+        assert_eq!(found.items[0].preview, "entry 29");
+    }
+
+    #[test]
+    fn test_query_keeps_order_after_moves() {
+        let mut history = ClipboardHistory::new(10);
+        for n in 0..4 {
+            history.add(ClipboardItem::Text(format!("note {n}")));
+        }
+        history.add(ClipboardItem::Text("other".to_string()));
+        let found = |history: &ClipboardHistory| -> (Vec<String>, Vec<String>) {
+            let found = history.query(&HistoryQuery {
+                text: "note".to_string(),
+                ..Default::default()
+            });
+            let previews = |entries: Vec<_>| {
+                entries
+                    .into_iter()
+                    .map(|entry: EntrySummary| entry.preview)
+                    .collect()
+            };
+            (previews(found.pinned), previews(found.items))
+        };
+
+        // Promoted, pinned and unpinned entries are found where they are now
+        history.promote(4).unwrap(); // note 0
+        history.pin(3).unwrap(); // note 2
+        history.pin(3).unwrap(); // note 1
+        assert_eq!(
+            found(&history),
+            (
+                vec!["note 1".into(), "note 2".into()],
+                vec!["note 0".into(), "note 3".into()]
+            )
+        );
+        history.unpin(1).unwrap(); // note 2
+        history.add(ClipboardItem::Text("note 3".to_string()));
+        assert_eq!(
+            found(&history),
+            (
+                vec!["note 1".into()],
+                vec!["note 3".into(), "note 2".into(), "note 0".into()]
+            )
+        );
+
+        // And after a reload
+        let bytes = rmp_serde::to_vec(&history).unwrap();
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(found(&loaded), found(&history));
+    }
+
+    #[test]
+    fn test_query_fallback_only_searches_recent_entries() {
+        let mut history = ClipboardHistory::new(FALLBACK_ENTRIES + 10);
+        history.add(ClipboardItem::Text("needle".to_string()));
+        let found = |history: &ClipboardHistory, text: &str| {
+            history
+                .query(&HistoryQuery {
+                    text: text.to_string(),
+                    ..Default::default()
+                })
+                .items
+                .len()
+        };
+        // Only part of a word, so the index can't find it
+        assert_eq!(found(&history, "eedl"), 1);
+
+        for n in 0..FALLBACK_ENTRIES {
+            history.add(text(n));
+        }
+        assert_eq!(found(&history, "eedl"), 0);
+
+        // Whole words and prefixes are still found anywhere
+        assert_eq!(found(&history, "needle"), 1);
+        assert_eq!(found(&history, "nee"), 1);
+    }
+
+    #[test]
+    fn test_index_follows_history() {
+        let mut history = ClipboardHistory::new(2);
+        let found = |history: &ClipboardHistory, text: &str| {
+            history
+                .query(&HistoryQuery {
+                    text: text.to_string(),
+                    ..Default::default()
+                })
+                .items
+                .len()
+        };
+
+        history.add(ClipboardItem::Text("alpha".to_string()));
+        history.add(ClipboardItem::Text("bravo".to_string()));
+        history.add(ClipboardItem::Text("charlie".to_string()));
+
+        // Evicted, deleted and cleared entries are gone from the index
+        assert_eq!(found(&history, "alpha"), 0);
+        history.delete_by_id(history.get_items()[0].id).unwrap();
+        assert_eq!(found(&history, "charlie"), 0);
+        history.clear();
+        assert_eq!(found(&history, "bravo"), 0);
+
+        // A loaded history is indexed again
+        history.add(ClipboardItem::Text("delta".to_string()));
+        let bytes = rmp_serde::to_vec(&history).unwrap();
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(loaded, history);
+        assert_eq!(found(&loaded, "del"), 1);
+    }
+
+    #[test]
+    fn test_set_max_size() {
+        let mut history = ClipboardHistory::new(5);
+        for n in 0..5 {
+            history.add(ClipboardItem::Text(format!("item {n}")));
+        }
+
+        history.set_max_size(2);
+        assert_eq!(history.max_size(), 2);
+        assert_eq!(history.get_items().len(), 2);
+        assert_eq!(
+            history.get_items()[1],
+            ClipboardItem::Text("item 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_byte_limit_evicts_oldest() {
+        let mut limits = HistoryLimits::new(100);
+        limits.max_bytes = Some(35);
+        let mut history = ClipboardHistory::with_limits(limits);
+
+        for n in 0..5 {
+            history.add(text(n));
+        }
+        assert_eq!(history.get_items(), &[text(4), text(3), text(2)]);
+        assert_eq!(history.byte_size(), 30);
+
+        // Pinned items don't take from the budget
+        history.pin(0).unwrap();
+        history.add(text(5));
+        assert_eq!(history.get_items(), &[text(5), text(3), text(2)]);
+    }
+
+    #[test]
+    fn test_oversized_item_is_dropped_alone() {
+        let mut limits = HistoryLimits::new(100);
+        limits.max_bytes = Some(25);
+        let mut history = ClipboardHistory::with_limits(limits);
+        history.add(text(0));
+        history.add(text(1));
+
+        // Too big for the budget even on its own, the others stay
+        history.add(ClipboardItem::Text("x".repeat(30)));
+        assert_eq!(history.get_items(), &[text(1), text(0)]);
+    }
+
+    #[test]
+    fn test_kind_limits() {
+        let mut limits = HistoryLimits::new(100);
+        limits.kinds.insert(
+            KindFilter::Image,
+            KindLimit {
+                max_items: Some(2),
+                max_bytes: None,
+            },
+        );
+        limits.kinds.insert(
+            KindFilter::Text,
+            KindLimit {
+                max_items: None,
+                max_bytes: Some(20),
+            },
+        );
+        let mut history = ClipboardHistory::with_limits(limits);
+
+        history.add(image(1, 1, 0));
+        history.add(text(0));
+        history.add(image(1, 1, 1));
+        history.add(text(1));
+        history.add(image(1, 1, 2));
+        history.add(text(2));
+
+        // The oldest image and text went, whatever came in between
+        assert_eq!(
+            history.get_items(),
+            &[text(2), image(1, 1, 2), text(1), image(1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_set_limits() {
+        let mut history = ClipboardHistory::new(10);
+        for n in 0..6 {
+            history.add(text(n));
+        }
+
+        let mut limits = HistoryLimits::new(4);
+        limits.max_bytes = Some(30);
+        history.set_limits(limits.clone());
+        assert_eq!(history.limits(), &limits);
+        assert_eq!(history.get_items(), &[text(5), text(4), text(3)]);
+
+        // Limits are saved with the history
+        let bytes = rmp_serde::to_vec(&history).unwrap();
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(loaded.limits(), &limits);
+    }
+
+    #[test]
+    fn test_load_history_with_max_size() {
+        // Layout from before byte and kind limits: (items, max_size, pinned, next_id)
+        let mut history = ClipboardHistory::new(3);
+        history.add(text(0));
+        let bytes =
+            rmp_serde::to_vec(&(history.get_items(), 3usize, history.get_pinned(), 2u64)).unwrap();
+
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(loaded.limits(), &HistoryLimits::new(3));
+        assert_eq!(loaded.get_items(), &[text(0)]);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let start = SystemTime::now();
+        let minutes = |n: u64| start + Duration::from_secs(n * 60);
+
+        let mut history = ClipboardHistory::new(10);
+        history.set_expiry(ExpiryPolicy {
+            max_age: Some(Duration::from_secs(60 * 60)),
+            kinds: [(KindFilter::Image, Duration::from_secs(10 * 60))].into(),
+        });
+        history.add_at(text(0), Selection::Clipboard, minutes(0));
+        history.add_at(text(1), Selection::Clipboard, minutes(0));
+        history.pin(0).unwrap();
+        history.add_at(image(1, 1, 0), Selection::Clipboard, minutes(5));
+        history.add_at(text(2), Selection::Clipboard, minutes(30));
+
+        // Images go sooner than the rest
+        let image_id = history.get_items()[1].id;
+        assert_eq!(history.purge_expired(minutes(20)), vec![image_id]);
+
+        // Copying again counts as use, pinned entries expire too
+        history.add_at(text(0), Selection::Clipboard, minutes(50));
+        let pinned_id = history.get_pinned()[0].id;
+        assert_eq!(history.purge_expired(minutes(70)), vec![pinned_id]);
+        assert_eq!(history.get_items(), &[text(0), text(2)]);
+        assert!(history.get_pinned().is_empty());
+
+        // Expired entries can't be found anymore
+        let found = history.query(&HistoryQuery {
+            text: "text".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(found.total, 2);
+
+        // The policy is saved with the history
+        let bytes = rmp_serde::to_vec(&history).unwrap();
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(loaded.expiry(), history.expiry());
+    }
+
+    #[test]
+    fn test_add_secret() {
+        let now = SystemTime::now();
+        let mut history = ClipboardHistory::new(10);
+        let secret = ClipboardItem::Text("password hunter2".to_string());
+
+        history.add_secret(text(0), Selection::Clipboard, now, SecretAction::Skip);
+        assert!(history.get_items().is_empty());
+
+        // Masked secrets are kept, but neither shown nor found
+        history.add_secret(
+            secret.clone(),
+            Selection::Clipboard,
+            now,
+            SecretAction::Mask,
+        );
+        assert_eq!(history.get_items(), &[secret]);
+        let summary = history.summary();
+        assert!(summary.items[0].masked);
+        assert_eq!(summary.items[0].preview, MASKED_PREVIEW);
+        let found = history.query(&HistoryQuery {
+            text: "hunter2".to_string(),
+            ..Default::default()
+        });
+        assert!(found.items.is_empty());
+
+        // Still masked after a reload
+        let bytes = rmp_serde::to_vec(&history).unwrap();
+        let loaded: ClipboardHistory = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(loaded.get_items()[0].masked);
+        assert!(
+            loaded
+                .query(&HistoryQuery {
+                    text: "hunter2".to_string(),
+                    ..Default::default()
+                })
+                .items
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_masked_entries_are_hidden_when_handed_out() {
+        let now = SystemTime::now();
+        let mut history = ClipboardHistory::new(10);
+        history.add_at(text(0), Selection::Clipboard, now);
+        history.add_secret(
+            ClipboardItem::Html {
+                html: "<b>hunter2</b>".to_string(),
+                alt_text: "hunter2".to_string(),
+            },
+            Selection::Clipboard,
+            now,
+            SecretAction::Mask,
+        );
+
+        // Printing the history doesn't show the secret
+        let printed = history.to_string();
+        assert!(!printed.contains("hunter2"));
+        assert!(printed.contains(MASKED_PREVIEW));
+        assert!(printed.contains(&text(0).to_string()));
+
+        // Neither does the copy clients get, which keeps the kind and everything else
+        let hidden = history.without_secrets();
+        assert_eq!(
+            hidden.get_items()[0].item,
+            ClipboardItem::Html {
+                html: MASKED_PREVIEW.to_string(),
+                alt_text: MASKED_PREVIEW.to_string(),
+            }
+        );
+        assert_eq!(hidden.get_items()[0].id, history.get_items()[0].id);
+        assert_eq!(hidden.get_items()[1], history.get_items()[1]);
+
+        // The history itself still has the text
+        assert_eq!(history.get_items()[0].item.plain_text(), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_copies_remember_their_app() {
+        let now = SystemTime::now();
+        let mut history = ClipboardHistory::new(10);
+
+        history.add_copy(
+            text(0),
+            Selection::Clipboard,
+            now,
+            Some("gedit".into()),
+            None,
+        );
+        assert_eq!(history.get_items()[0].source.as_deref(), Some("gedit"));
+
+        // Copying it again from somewhere unknown keeps the app, from another app replaces it
+        history.add_copy(text(0), Selection::Clipboard, now, None, None);
+        assert_eq!(history.get_items()[0].source.as_deref(), Some("gedit"));
+        history.add_copy(text(0), Selection::Primary, now, Some("kate".into()), None);
+        assert_eq!(history.get_items()[0].source.as_deref(), Some("kate"));
+
+        // Secrets too, unless they are skipped
+        history.add_copy(
+            text(1),
+            Selection::Clipboard,
+            now,
+            Some("KeePassXC".into()),
+            Some(SecretAction::Mask),
+        );
+        assert_eq!(history.get_items()[0].source.as_deref(), Some("KeePassXC"));
+        assert!(history.get_items()[0].masked);
+        history.add_copy(
+            text(2),
+            Selection::Clipboard,
+            now,
+            Some("KeePassXC".into()),
+            Some(SecretAction::Skip),
+        );
+        assert_eq!(history.get_items().len(), 2);
+    }
+
+    #[test]
+    fn test_secrets_expire_on_their_own() {
+        let start = SystemTime::now();
+        let minutes = |n: u64| start + Duration::from_secs(n * 60);
+        let mut history = ClipboardHistory::new(10);
+
+        let code = text(1);
+        history.add_at(text(0), Selection::Clipboard, minutes(0));
+        history.add_secret(
+            code.clone(),
+            Selection::Clipboard,
+            minutes(0),
+            SecretAction::Expire(Duration::from_secs(5 * 60)),
+        );
+        let code_id = history.get_items()[0].id;
+
+        // Copying it again with a shorter expiry keeps the shorter one
+        history.add_secret(
+            code,
+            Selection::Clipboard,
+            minutes(1),
+            SecretAction::Expire(Duration::from_secs(2 * 60)),
+        );
+        assert_eq!(
+            history.get_items()[0].expire_after,
+            Some(Duration::from_secs(2 * 60))
+        );
+
+        // No expiry policy needed, only the secret goes
+        assert!(history.purge_expired(minutes(2)).is_empty());
+        assert_eq!(history.purge_expired(minutes(4)), vec![code_id]);
+        assert_eq!(history.get_items(), &[text(0)]);
+    }
+
+    #[test]
+    fn test_events_between_ids() {
+        let mut history = ClipboardHistory::new(2);
+        let before = history.ids();
+        history.add(text(0));
+        history.add(text(1));
+        let (id0, id1) = (history.get_items()[1].id, history.get_items()[0].id);
+        assert_eq!(
+            before.events_to(&history.ids()),
+            vec![HistoryEvent::Added(id1), HistoryEvent::Added(id0)]
+        );
+
+        // Copying again promotes, a third item evicts the oldest
+        let before = history.ids();
+        history.add(text(0));
+        assert_eq!(
+            before.events_to(&history.ids()),
+            vec![HistoryEvent::Promoted(id0)]
+        );
+        let before = history.ids();
+        history.add(text(2));
+        let id2 = history.get_items()[0].id;
+        assert_eq!(
+            before.events_to(&history.ids()),
+            vec![HistoryEvent::Added(id2), HistoryEvent::Deleted(vec![id1])]
+        );
+
+        // Pinning and unpinning move entries between the lists
+        let before = history.ids();
+        history.pin_by_id(id0).unwrap();
+        assert_eq!(
+            before.events_to(&history.ids()),
+            vec![HistoryEvent::Pinned(id0)]
+        );
+        let before = history.ids();
+        history.unpin_by_id(id0).unwrap();
+        assert_eq!(
+            before.events_to(&history.ids()),
+            vec![HistoryEvent::Unpinned(id0)]
+        );
+
+        // Nothing changed, nothing to tell
+        assert!(history.ids().events_to(&history.ids()).is_empty());
+    }
 }
@@ -0,0 +1,166 @@
+#[cfg(test)]
+mod clipboard_image_tests {
+    use serde::Serialize;
+    use super_v::common::{ClipboardError, ClipboardImage, ClipboardItem, THUMBNAIL_SIZE};
+
+    // ------------------ Helper Functions ----------------------
+    // Gradient, so the image doesn't compress to nothing
+    fn pixels(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|pixel| {
+                let (x, y) = ((pixel % width) as u8, (pixel / width) as u8);
+                [x, y, x ^ y, 255]
+            })
+            .collect()
+    }
+
+    fn thumbnail_size(image: &ClipboardImage) -> (u32, u32) {
+        let thumbnail = image::load_from_memory(&image.thumbnail).unwrap();
+        (thumbnail.width(), thumbnail.height())
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let rgba = pixels(64, 48);
+        let image = ClipboardImage::from_rgba(64, 48, &rgba).unwrap();
+
+        assert_eq!(image.to_rgba().unwrap(), rgba);
+        assert!(image.png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_screenshot_is_compressed() {
+        // A flat 4K screenshot, 33 MB of RGBA
+        let rgba = vec![40u8; 3840 * 2160 * 4];
+        let image = ClipboardImage::from_rgba(3840, 2160, &rgba).unwrap();
+
+        assert!(
+            image.png.len() < rgba.len() / 100,
+            "PNG is {} bytes",
+            image.png.len()
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_keeps_aspect_ratio() {
+        let wide = ClipboardImage::from_rgba(800, 200, &pixels(800, 200)).unwrap();
+        assert_eq!(thumbnail_size(&wide), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 4));
+
+        // Small images are not scaled up
+        let small = ClipboardImage::from_rgba(20, 10, &pixels(20, 10)).unwrap();
+        assert_eq!(thumbnail_size(&small), (20, 10));
+    }
+
+    #[test]
+    fn test_equality_by_content_hash() {
+        let rgba = pixels(32, 32);
+        let image = ClipboardImage::from_rgba(32, 32, &rgba).unwrap();
+        let same = ClipboardImage::from_rgba(32, 32, &rgba).unwrap();
+        assert_eq!(image, same);
+
+        let mut changed = rgba.clone();
+        changed[100] ^= 1;
+        assert_ne!(image, ClipboardImage::from_rgba(32, 32, &changed).unwrap());
+
+        // Same bytes, different shape
+        assert_ne!(image, ClipboardImage::from_rgba(16, 64, &rgba).unwrap());
+
+        // A hash collision doesn't make two images equal
+        let mut collision = ClipboardImage::from_rgba(32, 32, &changed).unwrap();
+        collision.hash = image.hash;
+        assert_ne!(image, collision);
+    }
+
+    #[test]
+    fn test_unchanged_pixels_are_not_encoded_again() {
+        let rgba = pixels(32, 32);
+        let mut last = ClipboardImage::from_rgba(32, 32, &rgba).unwrap();
+        // Marks what came from `last` rather than a new encoding
+        last.png = b"not encoded again".to_vec();
+
+        let same = ClipboardImage::from_rgba_or_last(32, 32, &rgba, Some(&last)).unwrap();
+        assert_eq!(same.png, last.png);
+
+        let mut changed = rgba.clone();
+        changed[100] ^= 1;
+        let new = ClipboardImage::from_rgba_or_last(32, 32, &changed, Some(&last)).unwrap();
+        assert!(new.png.starts_with(b"\x89PNG"));
+        assert_eq!(new.to_rgba().unwrap(), changed);
+
+        // Same bytes, different shape
+        let reshaped = ClipboardImage::from_rgba_or_last(16, 64, &rgba, Some(&last)).unwrap();
+        assert!(reshaped.png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_wrong_pixel_count_is_rejected() {
+        assert!(matches!(
+            ClipboardImage::from_rgba(10, 10, &[0u8; 10]),
+            Err(ClipboardError::InvalidImage(_))
+        ));
+        assert!(matches!(
+            ClipboardImage::from_rgba(0, 0, &[0u8; 4]),
+            Err(ClipboardError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_overflowing_size_is_rejected() {
+        // width * height * 4 doesn't fit in usize
+        let side = u32::MAX as usize;
+        assert!(matches!(
+            ClipboardImage::from_rgba(side, side, &[0u8; 16]),
+            Err(ClipboardError::InvalidImage(_))
+        ));
+
+        // Same through the old raw format, which IPC clients can still send
+        let bytes = rmp_serde::to_vec(&(side, side, vec![0u8; 16])).unwrap();
+        assert!(rmp_serde::from_slice::<ClipboardImage>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_serialized_image_round_trip() {
+        let item = ClipboardItem::Image(ClipboardImage::from_rgba(8, 8, &pixels(8, 8)).unwrap());
+
+        let bytes = rmp_serde::to_vec(&item).unwrap();
+        let decoded: ClipboardItem = rmp_serde::from_slice(&bytes).unwrap();
+
+        let (ClipboardItem::Image(decoded), ClipboardItem::Image(image)) = (decoded, item) else {
+            panic!("Expected Image items");
+        };
+        assert_eq!(decoded.png, image.png);
+        assert_eq!(decoded.thumbnail, image.thumbnail);
+        assert_eq!(decoded.hash, image.hash);
+    }
+
+    #[test]
+    fn test_raw_images_are_compressed_on_load() {
+        // Images as they were saved before compression
+        #[derive(Serialize)]
+        enum OldItem {
+            #[allow(unused)]
+            Text(String),
+            Image {
+                width: usize,
+                height: usize,
+                bytes: Vec<u8>,
+            },
+        }
+
+        let rgba = pixels(4, 4);
+        let bytes = rmp_serde::to_vec(&OldItem::Image {
+            width: 4,
+            height: 4,
+            bytes: rgba.clone(),
+        })
+        .unwrap();
+
+        let decoded: ClipboardItem = rmp_serde::from_slice(&bytes).unwrap();
+        let ClipboardItem::Image(image) = decoded else {
+            panic!("Expected Image item");
+        };
+        assert_eq!(image, ClipboardImage::from_rgba(4, 4, &rgba).unwrap());
+        assert_eq!(image.to_rgba().unwrap(), rgba);
+    }
+}
@@ -1,18 +1,55 @@
 #[cfg(test)]
 mod ipc_tests {
-    use std::fs::remove_file;
+    use std::{
+        fs::{self, remove_file},
+        io::Write,
+        os::unix::{fs::PermissionsExt, net::UnixStream},
+        path::PathBuf,
+        thread,
+        time::Duration,
+    };
 
     use serial_test::serial;
     use super_v::{
-        common::{IPCServerError, SOCKET_PATH},
-        services::clipboard_ipc_server::{create_bind, create_default_stream},
+        common::{
+            ClipboardError, ErrorCode, IPCServerError, current_uid, default_socket_path,
+            ensure_private_dir,
+        },
+        services::clipboard_ipc_server::{
+            Access, AccessPolicy, CmdIPC, DEFAULT_MAX_FRAME_SIZE, FrameLimits, IPCRequest,
+            PROTOCOL_VERSION, Payload, accept_handshake, create_bind, create_default_stream,
+            handshake, peer_uid, read_payload, read_payload_limited, send_payload,
+        },
     };
 
+    // ------------------ Helper Functions ----------------------
+    fn socket_path() -> PathBuf {
+        default_socket_path().unwrap()
+    }
+
+    fn mode(path: &PathBuf) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    // Deterministic random bytes, so a failing fuzz case can be replayed
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, below: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % below
+        }
+    }
+    // ----------------------------------------------------------
+
     #[test]
     #[serial]
     fn test_create_bind_success() {
         // Create a new listener
-        let listener = create_bind();
+        let listener = create_bind(&socket_path());
         assert!(listener.is_ok(), "Failed to create and bind listener");
     }
 
@@ -20,10 +57,10 @@ mod ipc_tests {
     #[serial]
     fn test_create_bind_already_running() {
         // Create first listener
-        let _listener1 = create_bind().unwrap();
+        let _listener1 = create_bind(&socket_path()).unwrap();
 
         // Try to create second listener - should fail
-        let listener2 = create_bind();
+        let listener2 = create_bind(&socket_path());
         match listener2 {
             Ok(_) => {
                 panic!("Server should not be created. Two instances running!");
@@ -40,7 +77,7 @@ mod ipc_tests {
     #[test]
     #[serial]
     fn test_stream_connect_no_file() {
-        let _ = remove_file(SOCKET_PATH);
+        let _ = remove_file(socket_path());
 
         let stream = create_default_stream();
 
@@ -61,7 +98,7 @@ mod ipc_tests {
     fn test_stream_connect_server_not_running() {
         // Create listener out of scope
         {
-            let _ = create_bind();
+            let _ = create_bind(&socket_path());
         }
         // Now the server should be stopped
 
@@ -80,6 +117,231 @@ mod ipc_tests {
         }
     }
 
+    #[test]
+    #[serial]
+    fn test_socket_is_private() {
+        let _listener = create_bind(&socket_path()).unwrap();
+        assert_eq!(mode(&socket_path()), 0o600);
+    }
+
+    #[test]
+    fn test_ensure_private_dir() {
+        let dir = std::env::temp_dir().join(format!("super_v_runtime_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // Missing directories are created for us only
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        ensure_private_dir(&dir).unwrap();
+
+        // One others can get into is refused
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(matches!(
+            ensure_private_dir(&dir),
+            Err(IPCServerError::UnsafePath(_))
+        ));
+
+        // So is a file in its place
+        fs::remove_dir(&dir).unwrap();
+        fs::write(&dir, "").unwrap();
+        assert!(matches!(
+            ensure_private_dir(&dir),
+            Err(IPCServerError::UnsafePath(_))
+        ));
+        let _ = remove_file(&dir);
+    }
+
+    #[test]
+    fn test_peer_uid() {
+        let (client, server) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&server).unwrap(), current_uid());
+        assert_eq!(peer_uid(&client).unwrap(), current_uid());
+    }
+
+    #[test]
+    fn test_access_policy() {
+        let me = current_uid();
+        let other = me.wrapping_add(1);
+        let (_client, server) = UnixStream::pair().unwrap();
+
+        // Only our own user by default
+        let policy = AccessPolicy::default();
+        assert_eq!(policy.authorize(&server), Ok(Access::Full));
+        assert_eq!(policy.access(other), None);
+
+        // Another user's daemon refuses us...
+        let policy = AccessPolicy {
+            owner: other,
+            readers: Vec::new(),
+            group: None,
+        };
+        assert!(matches!(
+            policy.authorize(&server),
+            Err(ClipboardError::PermissionDenied(_))
+        ));
+
+        // ...unless we may read
+        let policy = AccessPolicy {
+            owner: other,
+            readers: vec![me],
+            group: None,
+        };
+        let access = policy.authorize(&server).unwrap();
+        assert_eq!(access, Access::ReadOnly);
+        assert!(access.allows(&CmdIPC::Snapshot));
+        assert!(access.allows(&CmdIPC::Fetch(1)));
+        assert!(!access.allows(&CmdIPC::Clear));
+        assert!(!access.allows(&CmdIPC::Restore(1)));
+        assert!(!access.allows(&CmdIPC::Stop));
+        assert!(Access::Full.allows(&CmdIPC::Stop));
+    }
+
+    #[test]
+    fn test_handshake() {
+        // Same versions
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        let answer = thread::spawn(move || accept_handshake(&mut daemon, &FrameLimits::default()));
+        assert_eq!(handshake(&mut client), Ok(()));
+        assert_eq!(answer.join().unwrap(), Ok(()));
+
+        // A client from before the handshake is told, instead of answered
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        send_payload(
+            &mut client,
+            Payload::Request(IPCRequest {
+                cmd: CmdIPC::Snapshot,
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            accept_handshake(&mut daemon, &FrameLimits::default())
+                .unwrap_err()
+                .code,
+            ErrorCode::Unsupported
+        );
+        assert!(matches!(
+            read_payload(&mut client).unwrap(),
+            Payload::Response(_)
+        ));
+
+        // A Hello larger than a request is refused by its length
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        client.write_all(&(64u32 << 20).to_be_bytes()).unwrap();
+        assert_eq!(
+            accept_handshake(&mut daemon, &FrameLimits::default())
+                .unwrap_err()
+                .code,
+            ErrorCode::BadRequest
+        );
+
+        // A daemon of another version
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        thread::spawn(move || {
+            read_payload(&mut daemon).unwrap();
+            send_payload(&mut daemon, Payload::Hello(PROTOCOL_VERSION + 1)).unwrap();
+        });
+        match handshake(&mut client) {
+            Err(IPCServerError::Rejected(err)) => assert_eq!(err.code, ErrorCode::Unsupported),
+            other => panic!("Handshake should have been rejected, got {other:?}"),
+        }
+
+        // A daemon from before the handshake hangs up on it
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        thread::spawn(move || {
+            let mut len = [0u8; 4];
+            let _ = std::io::Read::read_exact(&mut daemon, &mut len);
+        });
+        assert!(matches!(
+            handshake(&mut client),
+            Err(IPCServerError::RecieveError(_))
+        ));
+    }
+
+    #[test]
+    fn test_frame_limits() {
+        let request = || {
+            Payload::Request(IPCRequest {
+                cmd: CmdIPC::Snapshot,
+            })
+        };
+
+        // Frames within the limit go through
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        send_payload(&mut client, request()).unwrap();
+        assert!(matches!(
+            read_payload_limited(&mut daemon, 64).unwrap(),
+            Payload::Request(_)
+        ));
+
+        // A larger one is refused by its length, before its bytes are waited for
+        client.write_all(&1024u32.to_be_bytes()).unwrap();
+        assert!(matches!(
+            read_payload_limited(&mut daemon, 64),
+            Err(IPCServerError::FrameTooLarge(1024))
+        ));
+
+        // A frame that ends early is an error
+        let (mut client, mut daemon) = UnixStream::pair().unwrap();
+        client.write_all(&(1u32 << 20).to_be_bytes()).unwrap();
+        client.write_all(&[0x90; 3]).unwrap();
+        drop(client);
+        assert!(matches!(
+            read_payload_limited(&mut daemon, 4 << 20),
+            Err(IPCServerError::RecieveError(_))
+        ));
+
+        // A client that stalls times out
+        let limits = FrameLimits {
+            max_request_size: DEFAULT_MAX_FRAME_SIZE,
+            timeout: Some(Duration::from_millis(50)),
+        };
+        let (_client, mut daemon) = UnixStream::pair().unwrap();
+        limits.apply(&daemon).unwrap();
+        assert!(matches!(
+            read_payload(&mut daemon),
+            Err(IPCServerError::Timeout)
+        ));
+
+        // And so does one that doesn't read what it gets
+        let (_client, mut daemon) = UnixStream::pair().unwrap();
+        limits.apply(&daemon).unwrap();
+        let result = loop {
+            if let Err(err) = send_payload(&mut daemon, request()) {
+                break err;
+            }
+        };
+        assert_eq!(result, IPCServerError::Timeout);
+
+        // A hangup is an error, not a panic
+        let (client, mut daemon) = UnixStream::pair().unwrap();
+        drop(client);
+        assert!(matches!(
+            read_payload(&mut daemon),
+            Err(IPCServerError::RecieveError(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_payload_fuzz() {
+        let mut rng = Lcg(42);
+        for case in 0..1000 {
+            let len = rng.next(128);
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.next(256) as u8).collect();
+            // Most random lengths are huge, so give half the cases one that fits
+            if case % 2 == 0 && len >= 4 {
+                let frame = rng.next(len - 3) as u32;
+                bytes[..4].copy_from_slice(&frame.to_be_bytes());
+            }
+
+            let (mut client, mut daemon) = UnixStream::pair().unwrap();
+            client.write_all(&bytes).unwrap();
+            drop(client);
+
+            // Read frames until the bytes run out. None of this may panic.
+            while read_payload_limited(&mut daemon, 4096).is_ok() {}
+        }
+    }
+
     // Sending and reading payload should already be tested via the Manager tests,
     // So no need for that here...
 }
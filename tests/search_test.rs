@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod search_tests {
+    use super_v::search::{QueryTerms, SearchIndex, TextMatch, find, match_terms, tokenize};
+
+    #[test]
+    fn test_substring_ignores_case() {
+        assert_eq!(
+            find("Copy that, COPY this", "copy"),
+            Some(TextMatch {
+                ranges: vec![0..4, 11..15],
+                exact: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_ranges_count_characters() {
+        // "ü" takes two bytes, but is one character
+        let found = find("Grüße aus Köln", "köln").unwrap();
+        assert_eq!(found.ranges, vec![10..14]);
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let found = find("my clipboard manager", "clpbrd").unwrap();
+        assert!(!found.exact);
+        // Neighbouring characters are one range
+        assert_eq!(found.ranges, vec![3..5, 6..8, 10..12]);
+    }
+
+    #[test]
+    fn test_fuzzy_prefers_shortest_span() {
+        // The first "a" starts a much longer match than the second
+        let found = find("a.........x.ab", "ab").unwrap();
+        assert_eq!(found.ranges, vec![12..14]);
+        assert!(found.exact);
+
+        let found = find("a...b.axb", "ab").unwrap();
+        assert!(!found.exact);
+        assert_eq!(found.ranges, vec![6..7, 8..9]);
+    }
+
+    #[test]
+    fn test_fuzzy_span_is_limited() {
+        // "ab" may span at most 6 characters
+        assert!(find("a....b", "ab").is_some());
+        assert_eq!(find("a.....b", "ab"), None);
+        assert_eq!(find("ba", "ab"), None);
+    }
+
+    #[test]
+    fn test_empty_query_matches_everything() {
+        assert_eq!(
+            find("", ""),
+            Some(TextMatch {
+                ranges: Vec::new(),
+                exact: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let words: Vec<_> = tokenize("Hello, Wörld! foo_bar 42").collect();
+        assert_eq!(
+            words,
+            vec![
+                (0..5, "hello".to_string()),
+                (7..12, "wörld".to_string()),
+                (14..17, "foo".to_string()),
+                (18..21, "bar".to_string()),
+                (22..24, "42".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_query_terms() {
+        let terms = QueryTerms::parse(r#"rust "hello world" "single" "open ended"#);
+        assert_eq!(terms.words, ["rust", "single"]);
+        assert_eq!(
+            terms.phrases,
+            vec![vec!["hello", "world"], vec!["open", "ended"]]
+        );
+        assert!(QueryTerms::parse(" ,. ").is_empty());
+    }
+
+    #[test]
+    fn test_match_terms_scores() {
+        let text = "Hello world, hello clipboard";
+
+        // Whole words count more than prefixes
+        let whole = match_terms(text, &QueryTerms::parse("hello")).unwrap();
+        let prefix = match_terms(text, &QueryTerms::parse("hel")).unwrap();
+        assert!(whole.score > prefix.score);
+        assert_eq!(prefix.ranges, vec![0..3, 13..16]);
+
+        let phrase = match_terms(text, &QueryTerms::parse(r#""hello clipboard""#)).unwrap();
+        assert!(phrase.score > whole.score);
+        assert_eq!(phrase.ranges, vec![13..28]);
+
+        // Every term has to match, phrases in order
+        assert_eq!(match_terms(text, &QueryTerms::parse("hello rust")), None);
+        assert_eq!(
+            match_terms(text, &QueryTerms::parse(r#""clipboard hello""#)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_index_candidates() {
+        let mut index = SearchIndex::new();
+        index.insert(1, "the quick brown fox");
+        index.insert(2, "the quiet dog");
+        index.insert(3, "brown dog");
+
+        let ids = |query: &str| {
+            index
+                .candidates(&QueryTerms::parse(query))
+                .map(|ids| ids.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(ids("qui"), Some(vec![1, 2]));
+        assert_eq!(ids("brown dog"), Some(vec![3]));
+        assert_eq!(ids(r#""brown fox""#), Some(vec![1]));
+        assert_eq!(ids("cat"), Some(vec![]));
+        assert_eq!(ids(""), None);
+
+        // Whole words score more than prefixes
+        let terms = QueryTerms::parse("quick qui");
+        assert_eq!(index.score(1, &terms), 3);
+
+        // Re-indexing replaces, removing forgets
+        index.insert(1, "slow turtle");
+        index.remove(2);
+        let ids = |query: &str| {
+            index
+                .candidates(&QueryTerms::parse(query))
+                .map(|ids| ids.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(ids("qui"), Some(vec![]));
+        assert_eq!(ids("turtle"), Some(vec![1]));
+        assert_eq!(index.len(), 2);
+    }
+}
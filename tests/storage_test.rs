@@ -0,0 +1,258 @@
+#[cfg(test)]
+mod storage_tests {
+    use std::{collections::VecDeque, fs, path::PathBuf};
+
+    use super_v::{
+        common::{ClipboardImage, ClipboardItem, StorageError},
+        history::ClipboardHistory,
+        storage::{HistoryStore, StoreKey},
+    };
+
+    // ------------------ Helper Functions ----------------------
+    fn image(width: usize, height: usize, fill: u8) -> ClipboardItem {
+        let rgba = vec![fill; width * height * 4];
+        ClipboardItem::Image(ClipboardImage::from_rgba(width, height, &rgba).unwrap())
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!(
+            "super_v_storage_test_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("history.bin")
+    }
+
+    fn temp_store(name: &str) -> HistoryStore {
+        HistoryStore::new(temp_path(name))
+    }
+
+    fn encrypted_store(name: &str, passphrase: &str) -> HistoryStore {
+        HistoryStore::encrypted(
+            temp_path(name),
+            StoreKey::from_passphrase(passphrase).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn sample_history() -> ClipboardHistory {
+        let mut history = ClipboardHistory::new(5);
+        history.add(ClipboardItem::Text("Item 1".to_string()));
+        history.add(image(1, 1, 255));
+        history.add(ClipboardItem::Text("Hello 世界! 🦀".to_string()));
+        history
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let store = temp_store("round_trip");
+        let history = sample_history();
+
+        store.save(&history).unwrap();
+
+        assert_eq!(store.load(), Ok(Some(history)));
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        // No file yet should not be an error
+        let store = temp_store("missing");
+        assert_eq!(store.load(), Ok(None));
+    }
+
+    #[test]
+    fn test_save_overwrites_and_leaves_no_temp_files() {
+        let store = temp_store("overwrite");
+
+        store.save(&sample_history()).unwrap();
+
+        let mut newer = ClipboardHistory::new(5);
+        newer.add(ClipboardItem::Text("Newer".to_string()));
+        store.save(&newer).unwrap();
+
+        assert_eq!(store.load(), Ok(Some(newer)));
+
+        // Only the state file should be left in the directory
+        let entries = fs::read_dir(store.path().parent().unwrap())
+            .unwrap()
+            .count();
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn test_truncated_file_is_corrupt() {
+        let store = temp_store("truncated");
+        let bytes = store.encode(&sample_history()).unwrap();
+
+        // Cut the file at every length, none of them should decode
+        for len in 0..bytes.len() {
+            assert!(
+                matches!(store.decode(&bytes[..len]), Err(StorageError::Corrupt(_))),
+                "Truncated file of length {len} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_flipped_byte_is_corrupt() {
+        let store = temp_store("flipped");
+        let mut bytes = store.encode(&sample_history()).unwrap();
+
+        // Flip a bit in the payload
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+
+        assert_eq!(
+            store.decode(&bytes),
+            Err(StorageError::Corrupt("Checksum mismatch".into()))
+        );
+    }
+
+    #[test]
+    fn test_unknown_version_is_rejected() {
+        let store = temp_store("version");
+        let mut bytes = store.encode(&sample_history()).unwrap();
+
+        // Version lives right after the 4 magic bytes
+        bytes[4..6].copy_from_slice(&999u16.to_be_bytes());
+
+        assert_eq!(
+            store.decode(&bytes),
+            Err(StorageError::UnsupportedVersion(999))
+        );
+    }
+
+    #[test]
+    fn test_quarantine_moves_file_aside() {
+        let store = temp_store("quarantine");
+        fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+        fs::write(store.path(), b"definitely not a history file").unwrap();
+
+        assert!(matches!(store.load(), Err(StorageError::Corrupt(_))));
+
+        let moved_to = store.quarantine().unwrap();
+        assert!(moved_to.exists());
+        assert_eq!(store.load(), Ok(None));
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let store = encrypted_store("encrypted_round_trip", "correct horse");
+        let history = sample_history();
+
+        store.save(&history).unwrap();
+
+        // The text should not be readable on disk
+        let bytes = fs::read(store.path()).unwrap();
+        assert!(!bytes.windows(6).any(|window| window == b"Item 1"));
+
+        // Reopening with the same passphrase derives the same key
+        let reopened = HistoryStore::encrypted(
+            store.path(),
+            StoreKey::from_passphrase("correct horse").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(reopened.load(), Ok(Some(history)));
+    }
+
+    #[test]
+    fn test_encrypted_wrong_key() {
+        let store = encrypted_store("encrypted_wrong_key", "correct horse");
+        store.save(&sample_history()).unwrap();
+
+        let wrong = HistoryStore::encrypted(
+            store.path(),
+            StoreKey::from_passphrase("battery staple").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(wrong.load(), Err(StorageError::DecryptionFailed));
+
+        // The file must still be there for the right key
+        assert!(store.path().exists());
+    }
+
+    #[test]
+    fn test_encrypted_without_key() {
+        let store = encrypted_store("encrypted_no_key", "correct horse");
+        store.save(&sample_history()).unwrap();
+
+        let plain = HistoryStore::new(store.path());
+        assert_eq!(plain.load(), Err(StorageError::KeyRequired));
+    }
+
+    #[test]
+    fn test_encrypted_tampered_ciphertext() {
+        let store = encrypted_store("encrypted_tampered", "correct horse");
+        let mut bytes = store.encode(&sample_history()).unwrap();
+
+        // Flip a bit in the ciphertext. There is no checksum to fix up, the tag has to catch it.
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+
+        assert_eq!(store.decode(&bytes), Err(StorageError::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_plaintext_file_is_encrypted_on_next_save() {
+        let plain = temp_store("migrate");
+        let history = sample_history();
+        plain.save(&history).unwrap();
+
+        // An encrypted store can still read the old file
+        let encrypted = HistoryStore::encrypted(
+            plain.path(),
+            StoreKey::from_passphrase("correct horse").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(encrypted.load(), Ok(Some(history.clone())));
+
+        // After saving, the plain store can no longer read it
+        encrypted.save(&history).unwrap();
+        assert_eq!(plain.load(), Err(StorageError::KeyRequired));
+    }
+
+    #[test]
+    fn test_empty_keys_are_rejected() {
+        assert!(matches!(
+            StoreKey::from_passphrase("   "),
+            Err(StorageError::InvalidKey(_))
+        ));
+
+        let keyfile = temp_path("empty_keyfile");
+        fs::create_dir_all(keyfile.parent().unwrap()).unwrap();
+        fs::write(&keyfile, b"").unwrap();
+        assert!(matches!(
+            StoreKey::from_keyfile(&keyfile),
+            Err(StorageError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_history_is_migrated() {
+        // Layout from before entries had metadata: (items, max_size, pinned)
+        let item = ClipboardItem::Text("Old".to_string());
+        let pinned = ClipboardItem::Text("Old pinned".to_string());
+        let payload = rmp_serde::to_vec(&(
+            VecDeque::from([item.clone()]),
+            5usize,
+            VecDeque::from([pinned.clone()]),
+        ))
+        .unwrap();
+
+        // Version 1 header with the FNV-1a checksum of the payload
+        let checksum = payload.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        let mut bytes = b"SUPV".to_vec();
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let history = temp_store("legacy").decode(&bytes).unwrap();
+        assert_eq!(history.get_items(), &[item]);
+        assert_eq!(history.get_pinned(), &[pinned]);
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod subscribers_tests {
+    use std::{os::unix::net::UnixStream, thread, time::Duration};
+
+    use super_v::{
+        common::ErrorCode,
+        history::HistoryEvent,
+        services::{
+            clipboard_ipc_server::{IPCResponse, Payload, read_payload},
+            subscribers::{SubscribeError, Subscribers},
+        },
+    };
+
+    // ------------------ Helper Functions ----------------------
+    // A pair whose client end fails instead of hanging when an expected frame never comes
+    fn pair() -> (UnixStream, UnixStream) {
+        let (client, daemon) = UnixStream::pair().unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        (client, daemon)
+    }
+
+    // A first payload of about `size` bytes
+    fn response(size: usize) -> Payload {
+        Payload::Response(IPCResponse::failure(
+            ErrorCode::Unavailable,
+            "x".repeat(size),
+        ))
+    }
+
+    fn added(ids: impl IntoIterator<Item = u64>) -> Vec<HistoryEvent> {
+        ids.into_iter().map(HistoryEvent::Added).collect()
+    }
+
+    fn next_event(stream: &mut UnixStream) -> HistoryEvent {
+        match read_payload(stream).unwrap() {
+            Payload::Event(event) => event,
+            other => panic!("Expected an event, got {other:?}"),
+        }
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_events_follow_the_first_payload() {
+        let subscribers = Subscribers::default();
+        let (mut client, daemon) = pair();
+        subscribers.subscribe(daemon, response(7)).unwrap();
+        subscribers.publish(&added([1, 2]));
+
+        assert!(matches!(
+            read_payload(&mut client).unwrap(),
+            Payload::Response(_)
+        ));
+        assert_eq!(next_event(&mut client), HistoryEvent::Added(1));
+        assert_eq!(next_event(&mut client), HistoryEvent::Added(2));
+    }
+
+    #[test]
+    fn test_slow_subscribers_lag() {
+        let subscribers = Subscribers::new(2);
+        let (mut client, daemon) = UnixStream::pair().unwrap();
+
+        // The writer is stuck on a first payload larger than the socket buffer
+        subscribers.subscribe(daemon, response(4 << 20)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        subscribers.publish(&added(1..=10));
+
+        // Two events fit the queue, the rest are counted
+        read_payload(&mut client).unwrap();
+        assert_eq!(next_event(&mut client), HistoryEvent::Added(1));
+        assert_eq!(next_event(&mut client), HistoryEvent::Added(2));
+
+        subscribers.publish(&added([11]));
+        assert!(matches!(
+            read_payload(&mut client).unwrap(),
+            Payload::Lagged(8)
+        ));
+        assert_eq!(next_event(&mut client), HistoryEvent::Added(11));
+    }
+
+    #[test]
+    fn test_gone_subscribers_are_removed() {
+        let subscribers = Subscribers::default();
+        let (client, daemon) = pair();
+        subscribers.subscribe(daemon, response(7)).unwrap();
+        assert_eq!(subscribers.len(), 1);
+
+        // The writer notices on its next write, the publish after that forgets it
+        drop(client);
+        subscribers.publish(&added([1]));
+        thread::sleep(Duration::from_millis(100));
+        subscribers.publish(&added([2]));
+        assert!(subscribers.is_empty());
+
+        // Closing ends the stream
+        let (mut client, daemon) = pair();
+        subscribers.subscribe(daemon, response(7)).unwrap();
+        subscribers.close();
+        read_payload(&mut client).unwrap();
+        assert!(read_payload(&mut client).is_err());
+    }
+
+    #[test]
+    fn test_subscribers_beyond_the_limit_are_refused() {
+        let subscribers = Subscribers::with_limits(4, 1);
+        let (client, daemon) = pair();
+        subscribers.subscribe(daemon, response(7)).unwrap();
+
+        let (_other, daemon) = pair();
+        assert!(matches!(
+            subscribers.subscribe(daemon, response(7)),
+            Err(SubscribeError::Full(1))
+        ));
+
+        // Once the first one's writer has stopped, there is room again
+        drop(client);
+        subscribers.publish(&added([1]));
+        thread::sleep(Duration::from_millis(100));
+        let (_other, daemon) = pair();
+        subscribers.subscribe(daemon, response(7)).unwrap();
+        assert_eq!(subscribers.len(), 1);
+    }
+}
@@ -0,0 +1,471 @@
+#[cfg(test)]
+mod clipboard_watcher_tests {
+    use std::{
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+    use super_v::{
+        common::{ClipboardBackend, ClipboardError, ClipboardItem, ClipboardSource, Selection},
+        services::clipboard_watcher::{
+            PollingWatcher, SelectionWatcher, WatchEvent, WatchOptions, run_watch_loop,
+        },
+    };
+
+    // ------------------ Helper Functions ----------------------
+    // Watcher that replays whatever the test sends it
+    struct ScriptedWatcher {
+        events: Receiver<WatchEvent>,
+        offered: Vec<String>,
+    }
+
+    impl SelectionWatcher for ScriptedWatcher {
+        fn wait(&mut self, timeout: Duration) -> WatchEvent {
+            match self.events.recv_timeout(timeout) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => WatchEvent::Idle,
+                Err(RecvTimeoutError::Disconnected) => WatchEvent::Disconnected,
+            }
+        }
+
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn offered_types(&self, _selection: Selection) -> Option<Vec<String>> {
+            Some(self.offered.clone())
+        }
+    }
+
+    // Clipboard that counts how often it is read
+    struct FakeClipboard {
+        item: Option<ClipboardItem>,
+        primary: Option<ClipboardItem>,
+        reads: Arc<AtomicUsize>,
+
+        // Owner of both selections, and who takes the clipboard over while it is read
+        app: Option<String>,
+        copying_app: Option<String>,
+    }
+
+    impl ClipboardBackend for FakeClipboard {
+        fn get_item(&mut self) -> Result<ClipboardItem, ClipboardError> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            if let Some(app) = self.copying_app.take() {
+                self.app = Some(app);
+            }
+            self.item.clone().ok_or(ClipboardError::ClipboardEmpty)
+        }
+
+        fn get_source(&mut self, _selection: Selection) -> ClipboardSource {
+            ClipboardSource {
+                mime_types: vec!["text/plain".to_string()],
+                app: self.app.clone(),
+            }
+        }
+
+        fn set_item(&mut self, item: ClipboardItem) -> Result<(), ClipboardError> {
+            self.item = Some(item);
+            Ok(())
+        }
+
+        fn get_primary(&mut self) -> Result<ClipboardItem, ClipboardError> {
+            self.primary.clone().ok_or(ClipboardError::ClipboardEmpty)
+        }
+    }
+
+    struct Harness {
+        events: Sender<WatchEvent>,
+        clipboard: Arc<Mutex<FakeClipboard>>,
+        reads: Arc<AtomicUsize>,
+        recorded: Arc<Mutex<Vec<(ClipboardItem, Selection)>>>,
+        sources: Arc<Mutex<Vec<ClipboardSource>>>,
+        stop_signal: Arc<AtomicBool>,
+        handle: JoinHandle<()>,
+    }
+
+    impl Harness {
+        // `accept` decides whether the n-th new item is stored
+        fn start(accept: fn(usize) -> bool) -> Self {
+            Self::start_with(accept, WatchOptions::default())
+        }
+
+        fn start_with(accept: fn(usize) -> bool, options: WatchOptions) -> Self {
+            Self::start_offering(accept, options, Vec::new())
+        }
+
+        // The watcher reports `offered` as the MIME types of every change
+        fn start_offering(
+            accept: fn(usize) -> bool,
+            options: WatchOptions,
+            offered: Vec<String>,
+        ) -> Self {
+            let (events, rx) = mpsc::channel();
+            let reads = Arc::new(AtomicUsize::new(0));
+            let clipboard = Arc::new(Mutex::new(FakeClipboard {
+                item: None,
+                primary: None,
+                reads: reads.clone(),
+                app: None,
+                copying_app: None,
+            }));
+            let recorded = Arc::new(Mutex::new(Vec::new()));
+            let sources = Arc::new(Mutex::new(Vec::new()));
+            let stop_signal = Arc::new(AtomicBool::new(false));
+
+            let handle = {
+                let clipboard = clipboard.clone();
+                let recorded = recorded.clone();
+                let sources = sources.clone();
+                let stop_signal = stop_signal.clone();
+                let mut attempts = 0;
+                thread::spawn(move || {
+                    let watcher = Box::new(ScriptedWatcher {
+                        events: rx,
+                        offered,
+                    });
+                    run_watch_loop(
+                        watcher,
+                        &clipboard,
+                        &stop_signal,
+                        &Mutex::new(options),
+                        |item, origin, source| {
+                            attempts += 1;
+                            if !accept(attempts) {
+                                return false;
+                            }
+                            recorded.lock().unwrap().push((item, origin));
+                            sources.lock().unwrap().push(source.clone());
+                            true
+                        },
+                    );
+                })
+            };
+
+            // Let the loop take its initial read
+            thread::sleep(Duration::from_millis(50));
+
+            Self {
+                events,
+                clipboard,
+                reads,
+                recorded,
+                sources,
+                stop_signal,
+                handle,
+            }
+        }
+
+        fn copy_from(&self, text: &str, app: &str) {
+            let mut clipboard = self.clipboard.lock().unwrap();
+            clipboard.item = Some(ClipboardItem::Text(text.to_string()));
+            clipboard.app = Some(app.to_string());
+        }
+
+        fn recorded_apps(&self) -> Vec<Option<String>> {
+            self.sources
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|source| source.app.clone())
+                .collect()
+        }
+
+        fn copy(&self, text: &str) {
+            self.clipboard.lock().unwrap().item = Some(ClipboardItem::Text(text.to_string()));
+        }
+
+        fn select(&self, text: &str) {
+            self.clipboard.lock().unwrap().primary = Some(ClipboardItem::Text(text.to_string()));
+        }
+
+        fn notify(&self) {
+            self.notify_selection(Selection::Clipboard);
+        }
+
+        fn notify_selection(&self, selection: Selection) {
+            self.events.send(WatchEvent::Changed(selection)).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        fn recorded(&self) -> Vec<ClipboardItem> {
+            self.recorded_from()
+                .into_iter()
+                .map(|(item, _)| item)
+                .collect()
+        }
+
+        fn recorded_from(&self) -> Vec<(ClipboardItem, Selection)> {
+            self.recorded.lock().unwrap().clone()
+        }
+
+        fn stop(self) {
+            self.stop_signal.store(true, Ordering::SeqCst);
+            self.handle.join().unwrap();
+        }
+    }
+
+    fn text(text: &str) -> ClipboardItem {
+        ClipboardItem::Text(text.to_string())
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_reads_only_on_change() {
+        let harness = Harness::start(|_| true);
+        assert_eq!(harness.reads.load(Ordering::SeqCst), 1);
+
+        // Copies without an event are not noticed
+        harness.copy("item1");
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(harness.reads.load(Ordering::SeqCst), 1);
+        assert!(harness.recorded().is_empty());
+
+        harness.notify();
+        assert_eq!(harness.reads.load(Ordering::SeqCst), 2);
+        assert_eq!(harness.recorded(), vec![text("item1")]);
+
+        harness.stop();
+    }
+
+    #[test]
+    fn test_records_every_change() {
+        let harness = Harness::start(|_| true);
+
+        for item in ["item1", "item2", "item3"] {
+            harness.copy(item);
+            harness.notify();
+        }
+
+        assert_eq!(
+            harness.recorded(),
+            vec![text("item1"), text("item2"), text("item3")]
+        );
+        harness.stop();
+    }
+
+    #[test]
+    fn test_skips_duplicates_and_empty_text() {
+        let harness = Harness::start(|_| true);
+
+        harness.copy("item1");
+        harness.notify();
+
+        // Same content again
+        harness.notify();
+
+        // Whitespace only
+        harness.copy("   ");
+        harness.notify();
+
+        assert_eq!(harness.recorded(), vec![text("item1")]);
+        harness.stop();
+    }
+
+    #[test]
+    fn test_retries_until_stored() {
+        // The first attempt fails, e.g. because the history was locked
+        let harness = Harness::start(|attempt| attempt > 1);
+
+        harness.copy("item1");
+        harness.notify();
+
+        // No second event, the loop retries on its own
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(harness.recorded(), vec![text("item1")]);
+        harness.stop();
+    }
+
+    #[test]
+    fn test_disconnect_falls_back_to_polling() {
+        let harness = Harness::start(|_| true);
+
+        harness.events.send(WatchEvent::Disconnected).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // No event, but the polling fallback picks it up
+        harness.copy("item1");
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(harness.recorded(), vec![text("item1")]);
+
+        harness.stop();
+    }
+
+    #[test]
+    fn test_stops_on_signal() {
+        let harness = Harness::start(|_| true);
+        harness.stop_signal.store(true, Ordering::SeqCst);
+
+        // The loop must notice within a tick even though no event arrives
+        thread::sleep(Duration::from_millis(250));
+        assert!(harness.handle.is_finished());
+        harness.handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_polling_watcher_ticks() {
+        let mut watcher = PollingWatcher::new(Duration::from_millis(20));
+        assert_eq!(
+            watcher.wait(Duration::from_millis(100)),
+            WatchEvent::Changed(Selection::Clipboard)
+        );
+
+        // PRIMARY is reported right after, without another tick
+        assert_eq!(
+            watcher.wait(Duration::from_millis(5)),
+            WatchEvent::Changed(Selection::Primary)
+        );
+        assert_eq!(watcher.wait(Duration::from_millis(5)), WatchEvent::Idle);
+    }
+
+    #[test]
+    fn test_poll_interval_follows_the_options() {
+        let clipboard = Arc::new(Mutex::new(FakeClipboard {
+            item: None,
+            primary: None,
+            reads: Arc::new(AtomicUsize::new(0)),
+            app: None,
+            copying_app: None,
+        }));
+        let options = Arc::new(Mutex::new(WatchOptions {
+            poll_interval: Duration::from_secs(3600),
+            ..WatchOptions::default()
+        }));
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+
+        let handle = {
+            let (clipboard, options) = (clipboard.clone(), options.clone());
+            let (stop_signal, recorded) = (stop_signal.clone(), recorded.clone());
+            thread::spawn(move || {
+                // Ticks once an hour, until the options say otherwise
+                let watcher = Box::new(PollingWatcher::new(Duration::from_secs(3600)));
+                run_watch_loop(watcher, &clipboard, &stop_signal, &options, |item, _, _| {
+                    recorded.lock().unwrap().push(item);
+                    true
+                });
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+
+        clipboard.lock().unwrap().item = Some(text("copied"));
+        thread::sleep(Duration::from_millis(250));
+        assert!(recorded.lock().unwrap().is_empty());
+
+        // Like a reloaded config
+        options.lock().unwrap().poll_interval = Duration::from_millis(20);
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(*recorded.lock().unwrap(), vec![text("copied")]);
+
+        stop_signal.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_primary_ignored_by_default() {
+        let harness = Harness::start(|_| true);
+
+        harness.select("selected");
+        harness.notify_selection(Selection::Primary);
+        thread::sleep(Duration::from_millis(600));
+
+        assert!(harness.recorded().is_empty());
+        harness.stop();
+    }
+
+    #[test]
+    fn test_primary_records_only_settled_selection() {
+        let options = WatchOptions {
+            track_primary: true,
+            primary_debounce: Duration::from_millis(200),
+            ..WatchOptions::default()
+        };
+        let harness = Harness::start_with(|_| true, options);
+
+        // Dragging the mouse grows the selection a few characters at a time
+        for text in ["s", "sel", "selec", "selected"] {
+            harness.select(text);
+            harness.notify_selection(Selection::Primary);
+        }
+        assert!(harness.recorded().is_empty());
+
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(
+            harness.recorded_from(),
+            vec![(text("selected"), Selection::Primary)]
+        );
+
+        // Copies are still recorded as clipboard items
+        harness.copy("copied");
+        harness.notify();
+        assert_eq!(
+            harness.recorded_from()[1],
+            (text("copied"), Selection::Clipboard)
+        );
+
+        harness.stop();
+    }
+
+    #[test]
+    fn test_copies_are_credited_to_the_owner_before_the_read() {
+        let harness = Harness::start(|_| true);
+
+        harness.copy_from("password", "keepassxc");
+        harness.notify();
+        assert_eq!(harness.recorded_apps(), vec![Some("keepassxc".to_string())]);
+
+        // Someone else copies while the daemon reads. That read isn't credited to
+        // either app, the next tick reads the new copy with its owner.
+        harness.copy_from("notes", "editor");
+        harness.clipboard.lock().unwrap().copying_app = Some("browser".to_string());
+        harness.notify();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(harness.recorded(), vec![text("password"), text("notes")]);
+        assert_eq!(
+            harness.recorded_apps(),
+            vec![Some("keepassxc".to_string()), Some("browser".to_string())]
+        );
+
+        harness.stop();
+    }
+
+    #[test]
+    fn test_primary_and_offered_types_come_with_the_source() {
+        let options = WatchOptions {
+            track_primary: true,
+            primary_debounce: Duration::ZERO,
+            ..WatchOptions::default()
+        };
+        let offered = vec![
+            "text/plain".to_string(),
+            "x-kde-passwordManagerHint".to_string(),
+        ];
+        let harness = Harness::start_offering(|_| true, options, offered);
+
+        harness.clipboard.lock().unwrap().app = Some("keepassxc".to_string());
+        harness.select("selected");
+        harness.notify_selection(Selection::Primary);
+        thread::sleep(Duration::from_millis(200));
+
+        // PRIMARY has an owner too, and what the watcher was told is added once
+        assert_eq!(
+            harness.recorded_from(),
+            vec![(text("selected"), Selection::Primary)]
+        );
+        let sources = harness.sources.lock().unwrap().clone();
+        assert_eq!(sources[0].app.as_deref(), Some("keepassxc"));
+        assert_eq!(
+            sources[0].mime_types,
+            vec![
+                "text/plain".to_string(),
+                "x-kde-passwordManagerHint".to_string()
+            ]
+        );
+
+        harness.stop();
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod x11_clipboard_tests {
+    use std::path::PathBuf;
+    use super_v::services::x11_clipboard::{
+        paths_from_uri_list, paths_to_gnome_copied_files, paths_to_uri_list,
+    };
+
+    #[test]
+    fn test_uri_list_round_trip() {
+        let paths = vec![
+            PathBuf::from("/home/user/notes.txt"),
+            PathBuf::from("/home/user/My Photos/über #1.png"),
+        ];
+
+        let uri_list = paths_to_uri_list(&paths);
+        assert_eq!(
+            uri_list,
+            "file:///home/user/notes.txt\r\nfile:///home/user/My%20Photos/%C3%BCber%20%231.png"
+        );
+        assert_eq!(paths_from_uri_list(uri_list.as_bytes()), paths);
+    }
+
+    #[test]
+    fn test_gnome_copied_files_round_trip() {
+        let paths = vec![PathBuf::from("/tmp/a"), PathBuf::from("/tmp/b")];
+
+        let copied = paths_to_gnome_copied_files(&paths);
+        assert_eq!(copied, "copy\nfile:///tmp/a\nfile:///tmp/b");
+
+        // The "copy" line is not a file
+        assert_eq!(paths_from_uri_list(copied.as_bytes()), paths);
+    }
+
+    #[test]
+    fn test_uri_list_skips_non_local_files() {
+        let uri_list = b"# comment\r\n\
+            file://localhost/tmp/local\r\n\
+            file://otherhost/tmp/remote\r\n\
+            https://example.com/file\r\n\
+            file:///tmp/%FF\r\n\
+            file:///tmp/last";
+
+        assert_eq!(
+            paths_from_uri_list(uri_list),
+            vec![PathBuf::from("/tmp/local"), PathBuf::from("/tmp/last")]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod x11_watcher_tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::Duration,
+    };
+    use super_v::{
+        common::{ClipboardError, Selection, WatcherError},
+        services::{
+            clipboard_watcher::{SelectionWatcher, WatchEvent},
+            x11_clipboard::{PLAIN_TEXT, RTF, SelectionReader, offer_flavours},
+            x11_watcher::X11Watcher,
+        },
+    };
+    use x11rb::{
+        CURRENT_TIME,
+        connection::{Connection, RequestConnection},
+        protocol::xproto::{AtomEnum, ConnectionExt, CreateWindowAux, WindowClass},
+        rust_connection::RustConnection,
+    };
+
+    // ------------------ Helper Functions ----------------------
+    // Headless X server, killed on drop
+    struct Xvfb {
+        process: Child,
+        display: String,
+    }
+
+    impl Xvfb {
+        // None if Xvfb is not installed
+        fn start() -> Option<Self> {
+            let mut process = Command::new("Xvfb")
+                .args(["-displayfd", "1", "-nolisten", "tcp"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            // Xvfb prints the display number once it accepts connections
+            let mut line = String::new();
+            BufReader::new(process.stdout.take()?)
+                .read_line(&mut line)
+                .ok()?;
+
+            Some(Self {
+                process,
+                display: format!(":{}", line.trim()),
+            })
+        }
+    }
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    // A client that can take selection ownership
+    fn selection_owner(display: &str) -> (RustConnection, u32) {
+        let (conn, screen_num) = x11rb::connect(Some(display)).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            0,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.flush().unwrap();
+
+        (conn, window)
+    }
+    // ----------------------------------------------------------
+
+    #[test]
+    fn test_reports_clipboard_and_primary_changes() {
+        let Some(xvfb) = Xvfb::start() else {
+            eprintln!("Xvfb not found, skipping");
+            return;
+        };
+
+        let mut watcher = X11Watcher::connect(Some(&xvfb.display)).unwrap();
+        assert_eq!(watcher.wait(Duration::from_millis(200)), WatchEvent::Idle);
+
+        let (conn, window) = selection_owner(&xvfb.display);
+        let clipboard = conn
+            .intern_atom(false, b"CLIPBOARD")
+            .unwrap()
+            .reply()
+            .unwrap()
+            .atom;
+
+        conn.set_selection_owner(window, clipboard, CURRENT_TIME)
+            .unwrap();
+        conn.flush().unwrap();
+        assert_eq!(
+            watcher.wait(Duration::from_secs(2)),
+            WatchEvent::Changed(Selection::Clipboard)
+        );
+
+        conn.set_selection_owner(window, u32::from(AtomEnum::PRIMARY), CURRENT_TIME)
+            .unwrap();
+        conn.flush().unwrap();
+        assert_eq!(
+            watcher.wait(Duration::from_secs(2)),
+            WatchEvent::Changed(Selection::Primary)
+        );
+
+        // The owner going away clears the selection, which is a change as well
+        drop(conn);
+        let event = watcher.wait(Duration::from_secs(2));
+        assert!(matches!(event, WatchEvent::Changed(_)), "Got {event:?}");
+    }
+
+    #[test]
+    fn test_server_going_away_disconnects() {
+        let Some(xvfb) = Xvfb::start() else {
+            eprintln!("Xvfb not found, skipping");
+            return;
+        };
+
+        let mut watcher = X11Watcher::connect(Some(&xvfb.display)).unwrap();
+        drop(xvfb);
+
+        assert_eq!(
+            watcher.wait(Duration::from_secs(2)),
+            WatchEvent::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_connect_without_server_fails() {
+        // Nothing listens on this display
+        match X11Watcher::connect(Some(":4242")) {
+            Err(WatcherError::Unavailable(_)) => { /* Passed */ }
+            Err(err) => panic!("Wrong error: {err}"),
+            Ok(_) => panic!("Connected to a display that does not exist"),
+        }
+    }
+
+    #[test]
+    fn test_offered_flavours_can_be_read() {
+        let Some(xvfb) = Xvfb::start() else {
+            eprintln!("Xvfb not found, skipping");
+            return;
+        };
+        let display = Some(xvfb.display.as_str());
+
+        // Nobody owns the clipboard yet
+        let reader = SelectionReader::open(display, Selection::Clipboard).unwrap();
+        assert!(reader.targets().is_empty());
+        assert_eq!(reader.read(RTF), Err(ClipboardError::ClipboardEmpty));
+
+        offer_flavours(
+            display,
+            vec![
+                (RTF, br"{\rtf1 Bold}".to_vec()),
+                (PLAIN_TEXT, b"Bold".to_vec()),
+            ],
+        )
+        .unwrap();
+
+        // One connection reads every flavour the owner lists
+        let reader = SelectionReader::open(display, Selection::Clipboard).unwrap();
+        assert!(reader.targets().iter().any(|target| target == RTF));
+        assert!(!reader.offers(|target| target == "text/html"));
+        assert_eq!(reader.read(RTF).unwrap(), br"{\rtf1 Bold}");
+        assert_eq!(reader.read(PLAIN_TEXT).unwrap(), b"Bold");
+        assert_eq!(
+            reader.read("text/html"),
+            Err(ClipboardError::ClipboardEmpty)
+        );
+
+        // A new copy is noticed by the reader that opened before it
+        assert!(!reader.owner_changed());
+        offer_flavours(display, vec![(PLAIN_TEXT, b"Other".to_vec())]).unwrap();
+        assert!(reader.owner_changed());
+    }
+
+    #[test]
+    fn test_large_flavours_are_sent_in_chunks() {
+        let Some(xvfb) = Xvfb::start() else {
+            eprintln!("Xvfb not found, skipping");
+            return;
+        };
+        let display = Some(xvfb.display.as_str());
+
+        // Larger than the biggest request the server takes, so only INCR gets it across
+        let (conn, _) = selection_owner(&xvfb.display);
+        let rtf: Vec<u8> = (0..conn.maximum_request_bytes() * 2)
+            .map(|n| b'a' + (n % 26) as u8)
+            .collect();
+        offer_flavours(display, vec![(RTF, rtf.clone())]).unwrap();
+
+        let reader = SelectionReader::open(display, Selection::Clipboard).unwrap();
+        assert_eq!(reader.read(RTF).unwrap(), rtf);
+    }
+}